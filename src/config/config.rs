@@ -1,6 +1,13 @@
 use log::{debug, info, warn};
 use serde::{Deserialize, Serialize};
-use std::{env, fs, str::FromStr, time::Duration};
+use std::{
+    env, fs,
+    net::{SocketAddr, ToSocketAddrs},
+    str::FromStr,
+    time::Duration,
+};
+
+use crate::models::emergency_field::EmergencyField;
 
 #[derive(Debug, Serialize, Deserialize, Clone, Default, Eq, PartialEq)]
 pub enum IMAPModes {
@@ -9,12 +16,21 @@ pub enum IMAPModes {
     Idle,
     #[serde(alias = "poll", alias = "POLL")]
     Poll,
+    /// IDLE with a periodic check for missed mails, falls back to polling if IDLE fails repeatedly
+    #[serde(alias = "hybrid", alias = "HYBRID")]
+    Hybrid,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct IMAPModeDescription {
     pub method: IMAPModes,
-    pub interval: u64, // in minutes or seconds depending on the mode
+    pub interval: u64, // in minutes (idle) or seconds (poll, hybrid) depending on the mode
+    /// hybrid mode: number of consecutive IDLE failures, after which the source falls back to polling
+    #[serde(default = "IMAPModeDescription::default_max_idle_failures")]
+    pub max_idle_failures: u32,
+    /// hybrid mode: in minutes, how long to poll before trying IDLE again
+    #[serde(default = "IMAPModeDescription::default_fallback_duration")]
+    pub fallback_duration: u64,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -22,9 +38,317 @@ pub struct IMAPConfig {
     pub host: String,
     pub port: u16,
     pub username: String,
+    /// the watched mailbox, e.g. a folder a server rule moves the Nachalarmierungen to
+    #[serde(default = "IMAPConfig::default_mailbox")]
+    pub mailbox: String,
+    /// only used for the login method, not needed for OAuth
+    #[serde(default)]
     pub password: String,
     #[serde(default)]
+    pub auth: AuthConfig,
+    #[serde(default)]
+    pub tls: TlsConfig,
+    #[serde(default)]
     pub mode: IMAPModeDescription,
+    /// the file storing the last processed mail (see [ImapState])
+    ///
+    /// [ImapState]: crate::connection::imap_state::ImapState
+    #[serde(default = "IMAPConfig::default_state_file")]
+    pub state_file: String,
+    /// mails received longer ago (in minutes) are not printed, e.g. after a longer downtime
+    #[serde(default = "IMAPConfig::default_max_age")]
+    pub max_age: u64,
+    #[serde(default)]
+    pub post_processing: PostProcessingConfig,
+    #[serde(default)]
+    pub reconnect: ReconnectConfig,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, Eq, PartialEq)]
+pub enum TlsMode {
+    /// TLS from the start (usually port 993)
+    #[serde(alias = "tls", alias = "TLS")]
+    #[default]
+    Tls,
+    /// plain connection upgraded with STARTTLS (usually port 143), fails if the server doesn't offer it
+    #[serde(alias = "starttls", alias = "STARTTLS")]
+    StartTls,
+    /// unencrypted, only for local test servers (requires allow_plaintext)
+    #[serde(alias = "plaintext", alias = "PLAINTEXT")]
+    Plaintext,
+}
+
+/// How the connection to the IMAP server is secured (see [connect_client]).
+///
+/// [connect_client]: crate::connection::tls::connect_client
+#[derive(Debug, Serialize, Deserialize, Clone, Default, Eq, PartialEq)]
+pub struct TlsConfig {
+    #[serde(default)]
+    pub mode: TlsMode,
+    /// must be set to use the plaintext mode, so it can't be enabled by accident
+    #[serde(default)]
+    pub allow_plaintext: bool,
+    /// additional trusted CA certificate (PEM), e.g. of an internal CA
+    #[serde(default)]
+    pub ca_file: Option<String>,
+    /// SHA-256 fingerprint of the server certificate (hex, colons are ignored)
+    #[serde(default)]
+    pub pin_sha256: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, Eq, PartialEq)]
+pub enum AuthMethod {
+    /// LOGIN with username and password
+    #[serde(alias = "login", alias = "LOGIN")]
+    #[default]
+    Login,
+    /// SASL XOAUTH2 (Gmail, Microsoft 365)
+    #[serde(alias = "xoauth2", alias = "XOAUTH2")]
+    XOAuth2,
+    /// SASL OAUTHBEARER (RFC 7628)
+    #[serde(alias = "oauthbearer", alias = "OAUTHBEARER")]
+    OAuthBearer,
+}
+
+impl AuthMethod {
+    pub fn is_oauth(&self) -> bool {
+        return *self != AuthMethod::Login;
+    }
+}
+
+/// How to authenticate at the IMAP server. OAuth access tokens are requested with the stored
+/// refresh token on every connect (see [refresh_access_token]).
+///
+/// [refresh_access_token]: crate::connection::oauth::refresh_access_token
+#[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq)]
+pub struct AuthConfig {
+    #[serde(default)]
+    pub method: AuthMethod,
+    /// the token endpoint of the OAuth provider
+    #[serde(default)]
+    pub token_endpoint: Option<String>,
+    #[serde(default)]
+    pub client_id: Option<String>,
+    #[serde(default)]
+    pub client_secret: Option<String>,
+    /// requested scopes, separated by spaces
+    #[serde(default)]
+    pub scope: Option<String>,
+    /// the file storing the refresh token, updated when the provider issues a new one, each source needs its own
+    #[serde(default = "AuthConfig::default_refresh_token_file")]
+    pub refresh_token_file: String,
+}
+
+/// What to do with alarm mails on the server after they were handled.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, Eq, PartialEq)]
+pub struct PostProcessingConfig {
+    /// flag set on printed mails, e.g. `\Seen` or a keyword like `$Printed`
+    #[serde(default)]
+    pub flag: Option<String>,
+    /// mailbox printed mails are moved to
+    #[serde(default)]
+    pub processed_folder: Option<String>,
+    /// mailbox mails, that couldn't be parsed, are moved to
+    #[serde(default)]
+    pub failed_folder: Option<String>,
+    /// mailbox mails of rejected senders are moved to (see [SenderFilterConfig])
+    #[serde(default)]
+    pub quarantine_folder: Option<String>,
+}
+
+/// A local directory, alarm mails are delivered to (see [watch_spool]).
+///
+/// [watch_spool]: crate::connection::spool::watch_spool
+#[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq)]
+pub struct SpoolConfig {
+    /// a Maildir (containing `new` and `cur`) or a plain directory with `.eml` and `.txt` files
+    pub path: String,
+    /// in seconds, the time between two checks of the directory
+    #[serde(default = "SpoolConfig::default_interval")]
+    pub interval: u64,
+    /// plain directories only, subdirectory processed files are moved to
+    #[serde(default = "SpoolConfig::default_processed_folder")]
+    pub processed_folder: String,
+    /// plain directories only, subdirectory files, that couldn't be processed or were rejected, are moved to
+    #[serde(default = "SpoolConfig::default_failed_folder")]
+    pub failed_folder: String,
+}
+
+/// The protocol spoken by the embedded listener.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, Eq, PartialEq)]
+pub enum SmtpProtocol {
+    /// for mails delivered directly by the dispatch centre (or its mail server)
+    #[serde(alias = "smtp", alias = "SMTP")]
+    #[default]
+    Smtp,
+    /// for mails handed over by a local mail server (e.g. postfix with `lmtp:inet:127.0.0.1:2424`)
+    #[serde(alias = "lmtp", alias = "LMTP")]
+    Lmtp,
+}
+
+/// An embedded SMTP or LMTP listener, alarm mails are delivered to directly (see [watch_smtp]).
+///
+/// [watch_smtp]: crate::connection::smtp::watch_smtp
+#[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq)]
+pub struct SmtpConfig {
+    /// address and port to listen on, e.g. `0.0.0.0:25` or `127.0.0.1:2525` for local tests
+    #[serde(default = "SmtpConfig::default_listen")]
+    pub listen: String,
+    #[serde(default)]
+    pub protocol: SmtpProtocol,
+    /// the name of the alarm pc in the greeting and the Received header
+    #[serde(default = "SmtpConfig::default_hostname")]
+    pub hostname: String,
+    /// recipient addresses (`alarm@feuerwehr.de`) or domains (`@feuerwehr.de`), all recipients are accepted if empty
+    #[serde(default)]
+    pub allowed_recipients: Vec<String>,
+    /// envelope sender addresses or domains (MAIL FROM), all senders are accepted if empty
+    #[serde(default)]
+    pub allowed_senders: Vec<String>,
+    /// in bytes, larger mails are rejected
+    #[serde(default = "SmtpConfig::default_max_size")]
+    pub max_size: usize,
+    /// offers STARTTLS, if configured
+    #[serde(default)]
+    pub tls: Option<SmtpTlsConfig>,
+    /// clients connecting while this many sessions are running are asked to retry later
+    #[serde(default = "SmtpConfig::default_max_sessions")]
+    pub max_sessions: usize,
+}
+
+/// The certificate used for STARTTLS by the embedded listener.
+#[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq)]
+pub struct SmtpTlsConfig {
+    /// the certificate (chain) in PEM format
+    pub certificate: String,
+    /// the private key in PEM format (PKCS #8)
+    pub key: String,
+    /// reject mails of clients, that didn't use STARTTLS
+    #[serde(default)]
+    pub required: bool,
+}
+
+/// How the webhook authenticates the posted alarms.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, Eq, PartialEq)]
+pub enum WebhookAuth {
+    /// the secret in the `Authorization: Bearer <secret>` header, only allowed on a loopback `listen` address,
+    /// as the secret is sent unencrypted
+    #[serde(alias = "token", alias = "TOKEN")]
+    Token,
+    /// a HMAC-SHA256 signature of the `X-Timestamp` header and the body, keyed with the secret,
+    /// in the `X-Signature: sha256=<hex>` header
+    #[serde(alias = "hmac", alias = "HMAC")]
+    #[default]
+    Hmac,
+}
+
+/// An HTTP endpoint, gateways and test tools post alarms to (see [watch_webhook]).
+///
+/// [watch_webhook]: crate::connection::webhook::watch_webhook
+#[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq)]
+pub struct WebhookConfig {
+    /// address and port to listen on, e.g. `0.0.0.0:8080`
+    #[serde(default = "WebhookConfig::default_listen")]
+    pub listen: String,
+    /// the path alarms are posted to
+    #[serde(default = "WebhookConfig::default_path")]
+    pub path: String,
+    /// the shared secret, leave empty to use the environment variable EM_WEBHOOK_SECRET
+    #[serde(default)]
+    pub secret: String,
+    #[serde(default)]
+    pub auth: WebhookAuth,
+    /// in bytes, larger requests are rejected
+    #[serde(default = "WebhookConfig::default_max_size")]
+    pub max_size: usize,
+    /// requests arriving while this many are handled are answered with 503
+    #[serde(default = "WebhookConfig::default_max_sessions")]
+    pub max_sessions: usize,
+}
+
+/// How failed connections are retried (see [Supervisor]).
+///
+/// [Supervisor]: crate::connection::supervisor::Supervisor
+#[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq)]
+pub struct ReconnectConfig {
+    /// in seconds, the delay after the first failure, doubled after each further failure
+    #[serde(default = "ReconnectConfig::default_initial_delay")]
+    pub initial_delay: u64,
+    /// in seconds, the maximum delay between two connection attempts
+    #[serde(default = "ReconnectConfig::default_max_delay")]
+    pub max_delay: u64,
+    /// in minutes, raise an alert if the source is offline longer, 0 disables the alert
+    #[serde(default = "ReconnectConfig::default_offline_alert")]
+    pub offline_alert: u64,
+    /// program called with a message as argument, when the source is offline too long and when it is back
+    #[serde(default)]
+    pub alert_command: Option<String>,
+}
+
+/// Restricts, who can trigger a printout (see [check_sender]).
+///
+/// [check_sender]: crate::connection::sender_filter::check_sender
+#[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq)]
+pub struct SenderFilterConfig {
+    /// sender addresses (`alarm@leitstelle.de`) or domains (`@leitstelle.de`), all senders are allowed if empty
+    #[serde(default)]
+    pub allowed_senders: Vec<String>,
+    /// require `spf=pass` in the Authentication-Results header
+    #[serde(default)]
+    pub require_spf: bool,
+    /// require `dkim=pass` for the domain of the sender in the Authentication-Results header
+    #[serde(default)]
+    pub require_dkim: bool,
+    /// only trust the topmost Authentication-Results header added by this server (e.g. `mx.example.com`),
+    /// required for `require_spf` and `require_dkim`
+    #[serde(default)]
+    pub authserv_id: Option<String>,
+    /// require one of the topmost Received headers (see `received_hops`) to contain this text (e.g. `from mail.leitstelle.de`)
+    #[serde(default)]
+    pub required_received: Option<String>,
+    /// number of Received headers added by the own mail servers, headers further down can be forged by the sender
+    #[serde(default = "SenderFilterConfig::default_received_hops")]
+    pub received_hops: usize,
+    /// program called with the reason as argument, when a mail is rejected
+    #[serde(default)]
+    pub notify_command: Option<String>,
+}
+
+/// An unknown property of the alarm mail (see [Emergency::extra_fields]), that should be printed.
+///
+/// [Emergency::extra_fields]: crate::models::emergency::Emergency::extra_fields
+#[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq)]
+pub struct ExtraFieldDisplay {
+    /// the key in the mail, e.g. `Meldender` for `~~Meldender~~...~~`
+    pub key: String,
+    /// the label on the printout
+    pub label: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, Eq, PartialEq)]
+pub enum AttachmentPrintMode {
+    /// attachments are not printed
+    #[serde(alias = "disabled", alias = "DISABLED")]
+    #[default]
+    Disabled,
+    /// images are appended as pages to the generated pdf, pdfs are printed as separate jobs (they can't be merged)
+    #[serde(alias = "append", alias = "APPEND")]
+    Append,
+    /// every attachment is printed as a separate job
+    #[serde(alias = "separate", alias = "SEPARATE")]
+    Separate,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq)]
+pub struct AttachmentConfig {
+    #[serde(default)]
+    pub mode: AttachmentPrintMode,
+    /// only attachments of these types are printed
+    #[serde(default = "AttachmentConfig::default_mime_types")]
+    pub mime_types: Vec<String>,
+    /// larger attachments are skipped (in KiB)
+    #[serde(default = "AttachmentConfig::default_max_size_kb")]
+    pub max_size_kb: u64,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -36,18 +360,115 @@ pub struct PrintingConfig {
     pub amt: u8,
     pub sumatra_path: String,
     pub disable: Option<bool>,
+    /// extra fields printed below the address section, in this order
+    #[serde(default)]
+    pub extra_fields: Vec<ExtraFieldDisplay>,
+    #[serde(default)]
+    pub attachments: AttachmentConfig,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, Eq, PartialEq)]
+pub enum ParsingMode {
+    /// print whatever could be parsed
+    #[serde(alias = "lenient", alias = "LENIENT")]
+    #[default]
+    Lenient,
+    /// reject mails with missing mandatory fields or parsing errors
+    #[serde(alias = "strict", alias = "STRICT")]
+    Strict,
+    /// print, but mark missing mandatory fields on the printout
+    #[serde(alias = "validate", alias = "VALIDATE")]
+    Validate,
+}
+
+/// The export format of the dispatch centre (see [EmergencyParser]).
+///
+/// [EmergencyParser]: crate::models::emergency_parser::EmergencyParser
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, Eq, PartialEq)]
+pub enum MailFormat {
+    /// detect the format of each mail, so that mails of several dispatch centres can be handled
+    #[serde(alias = "auto", alias = "AUTO")]
+    #[default]
+    Auto,
+    /// the `~~Key~~Value~~` export of ELS
+    #[serde(alias = "els", alias = "ELS")]
+    Els,
+    /// plain text with `Label: value` lines
+    #[serde(alias = "alarmfax", alias = "ALARMFAX")]
+    Alarmfax,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ParsingConfig {
+    #[serde(default)]
+    pub format: MailFormat,
+    #[serde(default)]
+    pub mode: ParsingMode,
+    /// the fields, that every alarm mail of the dispatch centre must contain
+    #[serde(default = "EmergencyField::default_required")]
+    pub required_fields: Vec<EmergencyField>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Config {
-    pub imap: IMAPConfig,
+    /// the watched mailboxes, a single source or a list of sources
+    #[serde(default, deserialize_with = "one_or_many")]
+    pub imap: Vec<IMAPConfig>,
+    /// the watched local directories, a single directory or a list
+    #[serde(default, deserialize_with = "one_or_many")]
+    pub spool: Vec<SpoolConfig>,
+    /// the embedded listener, alarm mails can be delivered to directly
+    #[serde(default)]
+    pub smtp: Option<SmtpConfig>,
+    /// the HTTP endpoint, alarms can be posted to as `~~` text or json
+    #[serde(default)]
+    pub webhook: Option<WebhookConfig>,
     pub printing: PrintingConfig,
     pub pdf_save_path: Option<String>,
+    #[serde(default)]
+    pub parsing: ParsingConfig,
+    #[serde(default)]
+    pub sender_filter: SenderFilterConfig,
+    #[serde(default)]
+    pub deduplication: DeduplicationConfig,
+    /// file the metrics are written to in the Prometheus text format (e.g. for the textfile collector of the node exporter)
+    #[serde(default)]
+    pub metrics_file: Option<String>,
+}
+
+/// Detects alarms received more than once (see [AlarmHistory]).
+///
+/// [AlarmHistory]: crate::models::alarm_history::AlarmHistory
+#[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq)]
+pub struct DeduplicationConfig {
+    /// in minutes, an alarm received again within this time is not printed again, 0 disables the deduplication
+    #[serde(default = "DeduplicationConfig::default_window")]
+    pub window: u64,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum OneOrMany<T> {
+    One(T),
+    Many(Vec<T>),
+}
+
+/// accepts a single value as well as a list
+fn one_or_many<'de, D, T>(deserializer: D) -> Result<Vec<T>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    return match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(value) => Ok(vec![value]),
+        OneOrMany::Many(values) => Ok(values),
+    };
 }
 
 const ENV_IMAP_HOST: &str = "EM_IMAP_HOST";
 const ENV_IMAP_USERNAME: &str = "EM_IMAP_USERNAME";
 const ENV_IMAP_PASSWORD: &str = "EM_IMAP_PASSWORD";
+const ENV_WEBHOOK_SECRET: &str = "EM_WEBHOOK_SECRET";
 const SECONDS_PER_MINUTE: u64 = 60;
 pub const IMAP_IDLE_DEFAULT_INTERVAL: u64 = 29; // as per RFC 2177
 pub const IMAP_IDLE_MAX_INTERVAL: u64 = 29; // as per RFC 2177
@@ -58,19 +479,231 @@ impl Config {
             fs::read_to_string(path).map_err(|_e| format!("couldn't open file at {}", path))?;
         return Config::from_str(&config);
     }
-
-    pub fn interval_as_duration(&self) -> Duration {
-        return self.imap.mode.interval_as_duration();
-    }
 }
 
 impl IMAPModeDescription {
+    fn default_max_idle_failures() -> u32 {
+        return 3;
+    }
+
+    fn default_fallback_duration() -> u64 {
+        return 10;
+    }
+
     pub fn interval_as_duration(&self) -> Duration {
         match self.method {
-            IMAPModes::Poll => Duration::from_secs(self.interval),
+            IMAPModes::Poll | IMAPModes::Hybrid => Duration::from_secs(self.interval),
             IMAPModes::Idle => Duration::from_secs(self.interval * SECONDS_PER_MINUTE),
         }
     }
+
+    pub fn fallback_duration_as_duration(&self) -> Duration {
+        return Duration::from_secs(self.fallback_duration * SECONDS_PER_MINUTE);
+    }
+}
+
+impl IMAPConfig {
+    fn default_mailbox() -> String {
+        return "INBOX".to_string();
+    }
+
+    /// identifies the source in log messages
+    pub fn source_name(&self) -> String {
+        return format!("{}@{}/{}", self.username, self.host, self.mailbox);
+    }
+
+    fn default_state_file() -> String {
+        return "imap_state.yaml".to_string();
+    }
+
+    fn default_max_age() -> u64 {
+        return 60;
+    }
+
+    pub fn max_age_as_duration(&self) -> Duration {
+        return Duration::from_secs(self.max_age * SECONDS_PER_MINUTE);
+    }
+}
+
+impl AuthConfig {
+    fn default_refresh_token_file() -> String {
+        return "oauth_refresh_token.txt".to_string();
+    }
+}
+
+impl Default for AuthConfig {
+    fn default() -> Self {
+        return AuthConfig {
+            method: AuthMethod::default(),
+            token_endpoint: None,
+            client_id: None,
+            client_secret: None,
+            scope: None,
+            refresh_token_file: AuthConfig::default_refresh_token_file(),
+        };
+    }
+}
+
+impl SpoolConfig {
+    fn default_interval() -> u64 {
+        return 2;
+    }
+
+    fn default_processed_folder() -> String {
+        return "processed".to_string();
+    }
+
+    fn default_failed_folder() -> String {
+        return "failed".to_string();
+    }
+
+    pub fn interval_as_duration(&self) -> Duration {
+        return Duration::from_secs(self.interval);
+    }
+
+    /// identifies the source in log messages
+    pub fn source_name(&self) -> String {
+        return format!("spool:{}", self.path);
+    }
+}
+
+impl SmtpConfig {
+    fn default_listen() -> String {
+        return "0.0.0.0:25".to_string();
+    }
+
+    fn default_hostname() -> String {
+        return "emergency-mail".to_string();
+    }
+
+    fn default_max_size() -> usize {
+        return 10 * 1024 * 1024;
+    }
+
+    fn default_max_sessions() -> usize {
+        return 10;
+    }
+
+    /// identifies the source in log messages
+    pub fn source_name(&self) -> String {
+        return format!("smtp:{}", self.listen);
+    }
+}
+
+impl WebhookConfig {
+    fn default_listen() -> String {
+        return "0.0.0.0:8080".to_string();
+    }
+
+    fn default_path() -> String {
+        return "/alarm".to_string();
+    }
+
+    fn default_max_size() -> usize {
+        return 1024 * 1024;
+    }
+
+    fn default_max_sessions() -> usize {
+        return 10;
+    }
+
+    /// identifies the source in log messages
+    pub fn source_name(&self) -> String {
+        return format!("webhook:{}{}", self.listen, self.path);
+    }
+
+    /// whether only clients on this machine can connect (e.g. `127.0.0.1:8080` or `localhost:8080`)
+    pub fn is_local(&self) -> bool {
+        let Ok(addresses) = self.listen.to_socket_addrs() else {
+            return false;
+        };
+        let addresses: Vec<SocketAddr> = addresses.collect();
+        return !addresses.is_empty() && addresses.iter().all(|a| a.ip().is_loopback());
+    }
+}
+
+impl ReconnectConfig {
+    fn default_initial_delay() -> u64 {
+        return 5;
+    }
+
+    fn default_max_delay() -> u64 {
+        return 300;
+    }
+
+    fn default_offline_alert() -> u64 {
+        return 15;
+    }
+
+    pub fn initial_delay_as_duration(&self) -> Duration {
+        return Duration::from_secs(self.initial_delay);
+    }
+
+    pub fn max_delay_as_duration(&self) -> Duration {
+        return Duration::from_secs(self.max_delay);
+    }
+
+    /// None, if the alert is disabled
+    pub fn offline_alert_as_duration(&self) -> Option<Duration> {
+        return (self.offline_alert > 0)
+            .then(|| Duration::from_secs(self.offline_alert * SECONDS_PER_MINUTE));
+    }
+}
+
+impl Default for ReconnectConfig {
+    fn default() -> Self {
+        return ReconnectConfig {
+            initial_delay: ReconnectConfig::default_initial_delay(),
+            max_delay: ReconnectConfig::default_max_delay(),
+            offline_alert: ReconnectConfig::default_offline_alert(),
+            alert_command: None,
+        };
+    }
+}
+
+impl SenderFilterConfig {
+    fn default_received_hops() -> usize {
+        return 1;
+    }
+
+    pub fn enabled(&self) -> bool {
+        return !self.allowed_senders.is_empty()
+            || self.require_spf
+            || self.require_dkim
+            || self.required_received.is_some();
+    }
+}
+
+impl Default for SenderFilterConfig {
+    fn default() -> Self {
+        return SenderFilterConfig {
+            allowed_senders: Vec::new(),
+            require_spf: false,
+            require_dkim: false,
+            authserv_id: None,
+            required_received: None,
+            received_hops: SenderFilterConfig::default_received_hops(),
+            notify_command: None,
+        };
+    }
+}
+
+impl DeduplicationConfig {
+    fn default_window() -> u64 {
+        return 30;
+    }
+
+    pub fn window_as_duration(&self) -> Duration {
+        return Duration::from_secs(self.window * SECONDS_PER_MINUTE);
+    }
+}
+
+impl Default for DeduplicationConfig {
+    fn default() -> Self {
+        return DeduplicationConfig {
+            window: DeduplicationConfig::default_window(),
+        };
+    }
 }
 
 impl Default for IMAPModeDescription {
@@ -78,6 +711,52 @@ impl Default for IMAPModeDescription {
         return IMAPModeDescription {
             method: IMAPModes::Idle,
             interval: IMAP_IDLE_DEFAULT_INTERVAL,
+            max_idle_failures: IMAPModeDescription::default_max_idle_failures(),
+            fallback_duration: IMAPModeDescription::default_fallback_duration(),
+        };
+    }
+}
+
+impl Default for ParsingConfig {
+    fn default() -> Self {
+        return ParsingConfig {
+            format: MailFormat::default(),
+            mode: ParsingMode::default(),
+            required_fields: EmergencyField::default_required(),
+        };
+    }
+}
+
+impl AttachmentConfig {
+    fn default_mime_types() -> Vec<String> {
+        return vec![
+            "application/pdf".to_string(),
+            "image/png".to_string(),
+            "image/jpeg".to_string(),
+        ];
+    }
+
+    fn default_max_size_kb() -> u64 {
+        return 10 * 1024;
+    }
+
+    /// whether an attachment of the given type and size (in bytes) should be printed
+    pub fn allows(&self, mime_type: &str, size: usize) -> bool {
+        return self.mode != AttachmentPrintMode::Disabled
+            && self
+                .mime_types
+                .iter()
+                .any(|t| t.eq_ignore_ascii_case(mime_type))
+            && size as u64 <= self.max_size_kb * 1024;
+    }
+}
+
+impl Default for AttachmentConfig {
+    fn default() -> Self {
+        return AttachmentConfig {
+            mode: AttachmentPrintMode::default(),
+            mime_types: AttachmentConfig::default_mime_types(),
+            max_size_kb: AttachmentConfig::default_max_size_kb(),
         };
     }
 }
@@ -96,39 +775,152 @@ impl FromStr for Config {
             return format!("couldn't parse yaml: {}", e);
         })?;
 
-        // imap required field resolution
-        if config.imap.host == "" {
-            let host = env::var(ENV_IMAP_HOST)
-                .map_err(|_e| format!("couldn't get {} from environment", ENV_IMAP_HOST))?;
-            config.imap.host = host;
-            debug!("acquired imap host from environment: {}", config.imap.host);
+        if config.imap.is_empty()
+            && config.spool.is_empty()
+            && config.smtp.is_none()
+            && config.webhook.is_none()
+        {
+            return Err(
+                "at least one imap, spool, smtp or webhook source must be configured".to_string(),
+            );
+        }
+        if config.spool.iter().any(|spool| spool.interval == 0) {
+            return Err("interval for spool directories must be greater than 0".to_string());
+        }
+        if let Some(smtp) = &config.smtp {
+            if smtp.listen.is_empty() || smtp.max_size == 0 || smtp.max_sessions == 0 {
+                return Err(
+                    "smtp requires a listen address, a max_size > 0 and max_sessions > 0"
+                        .to_string(),
+                );
+            }
+        }
+        if let Some(webhook) = config.webhook.as_mut() {
+            if webhook.secret.is_empty() {
+                webhook.secret = env::var(ENV_WEBHOOK_SECRET).map_err(|_e| {
+                    format!("couldn't get {} from environment", ENV_WEBHOOK_SECRET)
+                })?;
+                debug!("acquired webhook secret from environment");
+            }
+            if webhook.secret.is_empty() || !webhook.path.starts_with('/') {
+                return Err("webhook requires a secret and a path starting with /".to_string());
+            }
+            if webhook.listen.is_empty() || webhook.max_size == 0 || webhook.max_sessions == 0 {
+                return Err(
+                    "webhook requires a listen address, a max_size > 0 and max_sessions > 0"
+                        .to_string(),
+                );
+            }
+            if webhook.auth == WebhookAuth::Token && !webhook.is_local() {
+                return Err(
+                    "webhook token auth sends the secret unencrypted, use hmac or listen on a loopback address"
+                        .to_string(),
+                );
+            }
         }
+        for imap in config.imap.iter_mut() {
+            // imap required field resolution
+            if imap.host == "" {
+                let host = env::var(ENV_IMAP_HOST)
+                    .map_err(|_e| format!("couldn't get {} from environment", ENV_IMAP_HOST))?;
+                imap.host = host;
+                debug!("acquired imap host from environment: {}", imap.host);
+            }
 
-        if config.imap.password.is_empty() {
-            let password = env::var(ENV_IMAP_PASSWORD)
-                .map_err(|_e| format!("couldn't get {} from environment", ENV_IMAP_PASSWORD))?;
+            if imap.auth.method.is_oauth() {
+                if imap.username.is_empty() {
+                    let username = env::var(ENV_IMAP_USERNAME).map_err(|_e| {
+                        format!("couldn't get {} from environment", ENV_IMAP_USERNAME)
+                    })?;
+                    imap.username = username;
+                    debug!("acquired imap username from environment");
+                }
+                if imap.auth.token_endpoint.is_none() || imap.auth.client_id.is_none() {
+                    return Err("oauth requires a token_endpoint and a client_id".to_string());
+                }
+            } else if imap.password.is_empty() {
+                let password = env::var(ENV_IMAP_PASSWORD)
+                    .map_err(|_e| format!("couldn't get {} from environment", ENV_IMAP_PASSWORD))?;
 
-            config.imap.password = password;
-            debug!("acquired imap password from environment");
+                imap.password = password;
+                debug!("acquired imap password from environment");
 
-            if config.imap.username.is_empty() {
-                // only allow empty username, if password is also empty (makes no sense otherwise)
-                let username = env::var(ENV_IMAP_USERNAME)
-                    .map_err(|_e| format!("couldn't get {} from environment", ENV_IMAP_USERNAME))?;
-                config.imap.username = username;
-                debug!("acquired imap username from environment");
+                if imap.username.is_empty() {
+                    // only allow empty username, if password is also empty (makes no sense otherwise)
+                    let username = env::var(ENV_IMAP_USERNAME).map_err(|_e| {
+                        format!("couldn't get {} from environment", ENV_IMAP_USERNAME)
+                    })?;
+                    imap.username = username;
+                    debug!("acquired imap username from environment");
+                }
+            }
+
+            // imap sanity checks
+            if imap.tls.mode == TlsMode::Plaintext && !imap.tls.allow_plaintext {
+                return Err("plaintext imap connections must be allowed explicitly".to_string());
+            }
+            if imap.tls.mode == TlsMode::Plaintext && imap.tls.pin_sha256.is_some() {
+                return Err(
+                    "a certificate pin can't be checked on plaintext connections".to_string(),
+                );
+            }
+
+            if imap.mode.interval == 0 {
+                return Err("interval for IMAP mode must be greater than 0".to_string());
             }
-        }
 
-        // imap sanity checks
-        if config.imap.mode.interval == 0 {
-            return Err("interval for IMAP mode must be greater than 0".to_string());
+            if imap.mode.method == IMAPModes::Idle && imap.mode.interval > IMAP_IDLE_MAX_INTERVAL {
+                return Err("Interval for IDLE outside of RFC 2177 specification!".to_string());
+            }
+
+            if imap.mode.method == IMAPModes::Hybrid
+                && (imap.mode.interval > IMAP_IDLE_MAX_INTERVAL * SECONDS_PER_MINUTE
+                    || imap.mode.max_idle_failures == 0)
+            {
+                return Err(
+                    "hybrid mode requires an interval of at most 29 minutes and max_idle_failures > 0"
+                        .to_string(),
+                );
+            }
+
+            if imap.reconnect.initial_delay == 0
+                || imap.reconnect.max_delay < imap.reconnect.initial_delay
+            {
+                return Err(
+                    "reconnect delays must be greater than 0 and initial_delay <= max_delay"
+                        .to_string(),
+                );
+            }
+        }
+        let mut state_files: Vec<&str> =
+            config.imap.iter().map(|i| i.state_file.as_str()).collect();
+        state_files.sort();
+        if state_files.windows(2).any(|w| w[0] == w[1]) {
+            return Err("each imap source needs its own state_file".to_string());
+        }
+        let mut refresh_token_files: Vec<&str> = config
+            .imap
+            .iter()
+            .filter(|i| i.auth.method.is_oauth())
+            .map(|i| i.auth.refresh_token_file.as_str())
+            .collect();
+        refresh_token_files.sort();
+        if refresh_token_files.windows(2).any(|w| w[0] == w[1]) {
+            return Err("each oauth imap source needs its own refresh_token_file".to_string());
         }
 
-        if config.imap.mode.method == IMAPModes::Idle
-            && config.imap.mode.interval > IMAP_IDLE_MAX_INTERVAL
+        // sender filter sanity checks
+        let sender_filter = &config.sender_filter;
+        if (sender_filter.require_spf || sender_filter.require_dkim)
+            && sender_filter.authserv_id.is_none()
         {
-            return Err("Interval for IDLE outside of RFC 2177 specification!".to_string());
+            return Err(
+                "require_spf and require_dkim need the authserv_id of the receiving server"
+                    .to_string(),
+            );
+        }
+        if sender_filter.required_received.is_some() && sender_filter.received_hops == 0 {
+            return Err("required_received needs received_hops > 0".to_string());
         }
 
         // printing sanity checks
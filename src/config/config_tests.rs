@@ -1,8 +1,14 @@
-use std::{env, str::FromStr};
+use std::{env, str::FromStr, time::Duration};
 
-use crate::config::config::IMAPModes::{Idle, Poll};
+use crate::config::config::IMAPModes::{Hybrid, Idle, Poll};
 use crate::config::config::IMAP_IDLE_DEFAULT_INTERVAL;
+use crate::config::config::{
+    AttachmentConfig, AttachmentPrintMode, AuthConfig, AuthMethod, DeduplicationConfig,
+    ExtraFieldDisplay, MailFormat, ParsingMode, PostProcessingConfig, ReconnectConfig,
+    SenderFilterConfig, SmtpProtocol, TlsConfig, TlsMode, WebhookAuth,
+};
 use crate::config::Config;
+use crate::models::emergency_field::EmergencyField;
 
 #[cfg(test)]
 const TEST_FULL_CONFIG: &str = include_str!("../../examples/config_full.yaml");
@@ -10,12 +16,67 @@ const TEST_FULL_CONFIG: &str = include_str!("../../examples/config_full.yaml");
 #[test]
 fn test_from_str() {
     let config = Config::from_str(TEST_FULL_CONFIG).unwrap();
-    assert_eq!(config.imap.host, "imap.gmail.com");
-    assert_eq!(config.imap.port, 993);
-    assert_eq!(config.imap.username, "abc");
-    assert_eq!(config.imap.password, "def");
-    assert_eq!(config.imap.mode.interval, 25);
-    assert_eq!(config.imap.mode.method, Poll);
+    assert_eq!(config.imap[0].host, "imap.gmail.com");
+    assert_eq!(config.imap[0].port, 993);
+    assert_eq!(config.imap[0].username, "abc");
+    assert_eq!(config.imap[0].password, "def");
+    assert_eq!(config.imap[0].mode.interval, 25);
+    assert_eq!(config.imap[0].mode.method, Poll);
+    assert_eq!(config.imap[0].state_file, "imap_state.yaml");
+    assert_eq!(config.imap[0].max_age, 30);
+    assert_eq!(config.imap[0].reconnect.initial_delay, 5);
+    assert_eq!(
+        config.imap[0].reconnect.max_delay_as_duration(),
+        Duration::from_secs(300)
+    );
+    assert_eq!(
+        config.imap[0].reconnect.offline_alert_as_duration(),
+        Some(Duration::from_secs(15 * 60))
+    );
+    assert_eq!(
+        config.imap[0].reconnect.alert_command,
+        Some("notify_admin.bat".to_string())
+    );
+    assert_eq!(config.imap[0].auth.method, AuthMethod::Login);
+    assert_eq!(config.imap[0].tls.mode, TlsMode::Tls);
+    assert_eq!(
+        config.imap[0].tls.ca_file,
+        Some("certs/internal-ca.pem".to_string())
+    );
+    assert!(config.imap[0].tls.pin_sha256.is_some());
+    assert_eq!(
+        config.imap[0].auth.client_id,
+        Some("00000000-0000-0000-0000-000000000000".to_string())
+    );
+    assert_eq!(config.imap[0].auth.client_secret, None);
+    assert_eq!(
+        config.imap[0].max_age_as_duration(),
+        Duration::from_secs(30 * 60)
+    );
+    assert_eq!(
+        config.imap[0].post_processing,
+        PostProcessingConfig {
+            flag: Some("$Printed".to_string()),
+            processed_folder: Some("Processed".to_string()),
+            failed_folder: Some("Failed".to_string()),
+            quarantine_folder: Some("Quarantine".to_string()),
+        }
+    );
+    assert_eq!(
+        config.sender_filter.allowed_senders,
+        vec![
+            "@leitstelle.de".to_string(),
+            "alarm@example.com".to_string()
+        ]
+    );
+    assert!(config.sender_filter.require_spf);
+    assert!(config.sender_filter.require_dkim);
+    assert_eq!(
+        config.sender_filter.authserv_id,
+        Some("mx.example.com".to_string())
+    );
+    assert_eq!(config.sender_filter.received_hops, 1);
+    assert!(config.sender_filter.enabled());
     assert_eq!(config.printing.min_copies, 2);
     assert_eq!(config.printing.max_copies, Some(5));
     assert_eq!(
@@ -24,18 +85,54 @@ fn test_from_str() {
     );
     assert_eq!(config.printing.amt, 1);
     assert_eq!(config.printing.disabled(), false);
-    assert_eq!(config.printing.disable, Some(false))
+    assert_eq!(config.printing.disable, Some(false));
+    assert_eq!(
+        config.printing.extra_fields,
+        vec![
+            ExtraFieldDisplay {
+                key: "Meldender".to_string(),
+                label: "Meldender:".to_string()
+            },
+            ExtraFieldDisplay {
+                key: "Rueckrufnummer".to_string(),
+                label: "Rückruf:".to_string()
+            }
+        ]
+    );
+    assert_eq!(
+        config.printing.attachments.mode,
+        AttachmentPrintMode::Append
+    );
+    assert_eq!(config.printing.attachments.max_size_kb, 2048);
+    assert!(config.printing.attachments.allows("image/png", 1024));
+    assert!(!config.printing.attachments.allows("image/gif", 1024));
+    assert!(!config
+        .printing
+        .attachments
+        .allows("application/pdf", 3 * 1024 * 1024));
+    assert_eq!(config.parsing.format, MailFormat::Auto);
+    assert_eq!(config.parsing.mode, ParsingMode::Validate);
+    assert_eq!(
+        config.parsing.required_fields,
+        vec![
+            EmergencyField::Town,
+            EmergencyField::Street,
+            EmergencyField::HouseNumber,
+            EmergencyField::Keyword,
+            EmergencyField::EmergencyNumber
+        ]
+    );
 }
 
 #[test]
 fn test_parse_file() {
     let config = Config::parse("examples/config_full.yaml").unwrap();
-    assert_eq!(config.imap.host, "imap.gmail.com");
-    assert_eq!(config.imap.port, 993);
-    assert_eq!(config.imap.username, "abc");
-    assert_eq!(config.imap.password, "def");
-    assert_eq!(config.imap.mode.interval, 25);
-    assert_eq!(config.imap.mode.method, Poll);
+    assert_eq!(config.imap[0].host, "imap.gmail.com");
+    assert_eq!(config.imap[0].port, 993);
+    assert_eq!(config.imap[0].username, "abc");
+    assert_eq!(config.imap[0].password, "def");
+    assert_eq!(config.imap[0].mode.interval, 25);
+    assert_eq!(config.imap[0].mode.method, Poll);
     assert_eq!(config.printing.min_copies, 2);
     assert_eq!(config.printing.max_copies, Some(5));
     assert_eq!(config.printing.additional_copies, Some(1));
@@ -59,12 +156,25 @@ fn test_parse_file_minmal_config() {
     env::set_var("EM_IMAP_PASSWORD", "pass");
 
     let config = Config::parse("examples/config.yaml").unwrap();
-    assert_eq!(config.imap.host, "host"); // should be pulled from environment
-    assert_eq!(config.imap.port, 993);
-    assert_eq!(config.imap.username, "user"); // as should this
-    assert_eq!(config.imap.password, "pass"); // and this
-    assert_eq!(config.imap.mode.interval, IMAP_IDLE_DEFAULT_INTERVAL); // default value, as not set in file
-    assert_eq!(config.imap.mode.method, Idle); // default value, as not set in file
+    assert_eq!(config.imap[0].host, "host"); // should be pulled from environment
+    assert_eq!(config.imap[0].port, 993);
+    assert_eq!(config.imap[0].username, "user"); // as should this
+    assert_eq!(config.imap[0].password, "pass"); // and this
+    assert_eq!(config.imap[0].mode.interval, IMAP_IDLE_DEFAULT_INTERVAL); // default value, as not set in file
+    assert_eq!(config.imap[0].mode.method, Idle); // default value, as not set in file
+    assert_eq!(config.imap[0].state_file, "imap_state.yaml"); // default value
+    assert_eq!(config.imap[0].max_age, 60); // default value
+    assert_eq!(config.imap[0].auth, AuthConfig::default()); // login with username and password
+    assert_eq!(config.imap[0].tls, TlsConfig::default()); // implicit tls without pin
+    assert_eq!(
+        config.imap[0].post_processing,
+        PostProcessingConfig::default()
+    ); // nothing is changed on the server
+    assert_eq!(config.imap[0].reconnect, ReconnectConfig::default());
+    assert_eq!(config.sender_filter, SenderFilterConfig::default());
+    assert!(!config.sender_filter.enabled()); // all senders are allowed
+    assert_eq!(config.deduplication, DeduplicationConfig::default());
+    assert_eq!(config.deduplication.window, 30); // default value
     assert_eq!(config.printing.min_copies, 1);
     assert_eq!(config.printing.max_copies, None);
     assert_eq!(
@@ -72,6 +182,9 @@ fn test_parse_file_minmal_config() {
         Some("HPE76479 (HP OfficeJet Pro 8020 series)".to_string())
     );
     assert_eq!(config.printing.amt, 1);
+    assert!(config.printing.extra_fields.is_empty());
+    assert_eq!(config.printing.attachments, AttachmentConfig::default()); // disabled by default
+    assert!(!config.printing.attachments.allows("application/pdf", 1024));
     assert_eq!(
         config.printing.sumatra_path,
         "C:\\Users\\Markus\\AppData\\Local\\SumatraPDF\\SumatraPDF.exe".to_string()
@@ -79,8 +192,240 @@ fn test_parse_file_minmal_config() {
     assert_eq!(config.printing.additional_copies, None);
     assert_eq!(config.printing.disable, None);
     assert_eq!(config.printing.disabled(), false);
+    assert_eq!(config.parsing.mode, ParsingMode::Lenient); // default value, as not set in file
+    assert_eq!(
+        config.parsing.required_fields,
+        EmergencyField::DEFAULT_REQUIRED
+    );
 
     env::remove_var("EM_IMAP_HOST");
     env::remove_var("EM_IMAP_USERNAME");
     env::remove_var("EM_IMAP_PASSWORD");
 }
+
+#[test]
+fn test_oauth_config() {
+    let config = TEST_FULL_CONFIG
+        .replace("password: \"def\"", "password: \"\"")
+        .replace("method: \"login\"", "method: \"xoauth2\"");
+    let config = Config::from_str(&config).unwrap();
+    assert_eq!(config.imap[0].auth.method, AuthMethod::XOAuth2);
+    assert_eq!(config.imap[0].password, ""); // not needed for oauth
+
+    let config = TEST_FULL_CONFIG
+        .replace("method: \"login\"", "method: \"oauthbearer\"")
+        .replace("    client_id: ", "    # client_id: ");
+    assert!(Config::from_str(&config).is_err()); // the client id is required
+}
+
+#[test]
+fn test_sender_filter_config() {
+    let config = TEST_FULL_CONFIG.replace("  authserv_id: ", "  # authserv_id: ");
+    assert!(Config::from_str(&config).is_err()); // any header would be trusted
+
+    let config = TEST_FULL_CONFIG.replace("received_hops: 1", "received_hops: 0");
+    assert!(Config::from_str(&config).is_err()); // nothing to check
+}
+
+#[test]
+fn test_plaintext_config() {
+    let config = TEST_FULL_CONFIG.replace("mode: \"tls\"", "mode: \"plaintext\"");
+    assert!(Config::from_str(&config).is_err()); // not allowed explicitly
+
+    let config = config
+        .replace("allow_plaintext: false", "allow_plaintext: true")
+        .replace("    pin_sha256: ", "    # pin_sha256: ");
+    let config = Config::from_str(&config).unwrap();
+    assert_eq!(config.imap[0].tls.mode, TlsMode::Plaintext);
+}
+
+#[test]
+fn test_multiple_sources() {
+    let yaml = r#"
+imap:
+  - host: "imap.gmail.com"
+    port: 993
+    username: "abc"
+    password: "def"
+  - host: "imap.example.com"
+    port: 993
+    username: "backup"
+    password: "ghi"
+    mailbox: "Alarm"
+    state_file: "imap_state_backup.yaml"
+printing:
+  min_copies: 1
+  printer: "HP_LaserJet_500_Pro"
+  amt: 1
+  sumatra_path: ""
+"#;
+    let config = Config::from_str(yaml).unwrap();
+    assert_eq!(config.imap.len(), 2);
+    assert_eq!(config.imap[0].mailbox, "INBOX"); // default value
+    assert_eq!(config.imap[0].source_name(), "abc@imap.gmail.com/INBOX");
+    assert_eq!(
+        config.imap[1].source_name(),
+        "backup@imap.example.com/Alarm"
+    );
+
+    // the sources can't share the state file
+    let shared = yaml.replace("imap_state_backup.yaml", "imap_state.yaml");
+    assert!(Config::from_str(&shared).is_err());
+
+    // nor the refresh token file of oauth
+    let oauth = |refresh_token_file: &str| {
+        return format!(
+            "password: \"\"\n    auth:\n      method: \"xoauth2\"\n      token_endpoint: \"https://oauth2.googleapis.com/token\"\n      client_id: \"client\"\n      refresh_token_file: \"{}\"",
+            refresh_token_file
+        );
+    };
+    let shared = yaml
+        .replace("password: \"def\"", &oauth("oauth_refresh_token.txt"))
+        .replace("password: \"ghi\"", &oauth("oauth_refresh_token.txt"));
+    assert!(Config::from_str(&shared).is_err());
+    let own = yaml
+        .replace("password: \"def\"", &oauth("oauth_refresh_token.txt"))
+        .replace(
+            "password: \"ghi\"",
+            &oauth("oauth_refresh_token_backup.txt"),
+        );
+    let config = Config::from_str(&own).unwrap();
+    assert_eq!(
+        config.imap[1].auth.refresh_token_file,
+        "oauth_refresh_token_backup.txt"
+    );
+}
+
+#[test]
+fn test_reconnect_config() {
+    let config = TEST_FULL_CONFIG.replace("offline_alert: 15", "offline_alert: 0");
+    let config = Config::from_str(&config).unwrap();
+    assert_eq!(config.imap[0].reconnect.offline_alert_as_duration(), None); // disabled
+
+    let config = TEST_FULL_CONFIG.replace("max_delay: 300", "max_delay: 2");
+    assert!(Config::from_str(&config).is_err()); // below the initial delay
+    let config = TEST_FULL_CONFIG.replace("initial_delay: 5", "initial_delay: 0");
+    assert!(Config::from_str(&config).is_err()); // would hammer the server
+}
+
+#[test]
+fn test_hybrid_mode_config() {
+    let config = TEST_FULL_CONFIG.replace("method: \"poll\"", "method: \"hybrid\"");
+    let config = Config::from_str(&config).unwrap();
+    assert_eq!(config.imap[0].mode.method, Hybrid);
+    assert_eq!(
+        config.imap[0].mode.interval_as_duration(),
+        Duration::from_secs(25)
+    ); // in seconds, like polling
+    assert_eq!(config.imap[0].mode.max_idle_failures, 3);
+    assert_eq!(
+        config.imap[0].mode.fallback_duration_as_duration(),
+        Duration::from_secs(10 * 60)
+    );
+    assert_eq!(
+        config.metrics_file,
+        Some("metrics/emergency_mail.prom".to_string())
+    );
+
+    let config = TEST_FULL_CONFIG
+        .replace("method: \"poll\"", "method: \"hybrid\"")
+        .replace("max_idle_failures: 3", "max_idle_failures: 0");
+    assert!(Config::from_str(&config).is_err()); // would never use idle
+}
+
+#[test]
+fn test_spool_config() {
+    let config = Config::from_str(TEST_FULL_CONFIG).unwrap();
+    assert_eq!(config.spool.len(), 1);
+    assert_eq!(config.spool[0].path, "C:/alarm/spool");
+    assert_eq!(
+        config.spool[0].interval_as_duration(),
+        Duration::from_secs(2)
+    );
+    assert_eq!(config.spool[0].source_name(), "spool:C:/alarm/spool");
+
+    // a spool directory can be the only source
+    let yaml = r#"
+spool:
+  path: "/var/mail/alarm"
+printing:
+  min_copies: 1
+  printer: "HP_LaserJet_500_Pro"
+  amt: 1
+  sumatra_path: ""
+"#;
+    let config = Config::from_str(yaml).unwrap();
+    assert!(config.imap.is_empty());
+    assert_eq!(config.spool[0].processed_folder, "processed");
+    assert_eq!(config.spool[0].failed_folder, "failed");
+
+    let without_sources = yaml.replace("spool:\n  path: \"/var/mail/alarm\"\n", "");
+    assert!(Config::from_str(&without_sources).is_err());
+}
+
+#[test]
+fn test_smtp_config() {
+    let config = Config::from_str(TEST_FULL_CONFIG).unwrap();
+    let smtp = config.smtp.unwrap();
+    assert_eq!(smtp.protocol, SmtpProtocol::Smtp);
+    assert_eq!(smtp.source_name(), "smtp:0.0.0.0:25");
+    assert_eq!(smtp.allowed_recipients, vec!["alarm@ff-teltow.de"]);
+    let tls = smtp.tls.unwrap();
+    assert_eq!(tls.certificate, "certs/alarmpc.pem");
+    assert!(!tls.required);
+
+    // the listener can be the only source
+    let yaml = r#"
+smtp:
+  protocol: lmtp
+printing:
+  min_copies: 1
+  printer: "HP_LaserJet_500_Pro"
+  amt: 1
+  sumatra_path: ""
+"#;
+    let config = Config::from_str(yaml).unwrap();
+    let smtp = config.smtp.unwrap();
+    assert_eq!(smtp.protocol, SmtpProtocol::Lmtp);
+    assert_eq!(smtp.listen, "0.0.0.0:25"); // default value
+    assert_eq!(smtp.max_size, 10 * 1024 * 1024);
+    assert!(smtp.tls.is_none());
+
+    let config = yaml.replace("protocol: lmtp", "max_size: 0");
+    assert!(Config::from_str(&config).is_err());
+}
+
+#[test]
+fn test_webhook_config() {
+    let config = Config::from_str(TEST_FULL_CONFIG).unwrap();
+    let webhook = config.webhook.unwrap();
+    assert_eq!(webhook.auth, WebhookAuth::Hmac);
+    assert_eq!(webhook.secret, "change-me");
+    assert_eq!(webhook.source_name(), "webhook:0.0.0.0:8080/alarm");
+
+    let yaml = r#"
+webhook:
+  secret: "geheim"
+  auth: HMAC
+printing:
+  min_copies: 1
+  printer: "HP_LaserJet_500_Pro"
+  amt: 1
+  sumatra_path: ""
+"#;
+    let config = Config::from_str(yaml).unwrap();
+    let webhook = config.webhook.unwrap();
+    assert_eq!(webhook.auth, WebhookAuth::Hmac);
+    assert_eq!(webhook.path, "/alarm"); // default value
+    assert_eq!(webhook.max_size, 1024 * 1024);
+
+    let config = yaml.replace("auth: HMAC", "path: \"alarm\"");
+    assert!(Config::from_str(&config).is_err()); // not an absolute path
+
+    // the token would be sent unencrypted over the network
+    let token = yaml.replace("auth: HMAC", "auth: token");
+    assert!(Config::from_str(&token).is_err());
+    let local = token.replace("auth: token", "auth: token\n  listen: \"127.0.0.1:8080\"");
+    let config = Config::from_str(&local).unwrap();
+    assert_eq!(config.webhook.unwrap().auth, WebhookAuth::Token);
+}
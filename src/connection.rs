@@ -1,6 +1,18 @@
+pub mod hybrid;
 pub mod imap;
 
 pub mod imap_multipart;
+pub mod imap_state;
+pub mod mime;
+pub mod notify;
+pub mod oauth;
+pub mod sender_filter;
+pub mod smtp;
+pub mod sources;
+pub mod spool;
+pub mod supervisor;
+pub mod tls;
+pub mod webhook;
 
 #[cfg(test)]
 mod tests;
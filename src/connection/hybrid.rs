@@ -0,0 +1,114 @@
+use std::time::{Duration, Instant};
+
+use log::{debug, warn};
+
+use crate::{config::config::IMAPModeDescription, metrics};
+
+/// number of switches between IDLE and polling per source
+pub const METRIC_MODE_SWITCHES: &str = "emergency_mail_mode_switches_total";
+/// 1 while the source fell back to polling, 0 while it uses IDLE
+pub const METRIC_POLLING_FALLBACK: &str = "emergency_mail_polling_fallback";
+
+/// Decides, whether a source in the hybrid mode uses IDLE or falls back to polling.
+///
+/// # description
+/// IDLE is considered failed, if the command fails or the periodic check finds mails, that the
+/// server didn't notify about. After `max_idle_failures` consecutive failures, the source polls for
+/// `fallback_duration`, then IDLE is tried again.
+pub struct HybridMode {
+    source: String,
+    max_failures: u32,
+    fallback_duration: Duration,
+    failures: u32,
+    polling_until: Option<Instant>,
+}
+
+impl HybridMode {
+    pub fn new(source: String, mode: &IMAPModeDescription) -> Self {
+        metrics::set_gauge(METRIC_POLLING_FALLBACK, &source, 0);
+        return HybridMode {
+            source,
+            max_failures: mode.max_idle_failures,
+            fallback_duration: mode.fallback_duration_as_duration(),
+            failures: 0,
+            polling_until: None,
+        };
+    }
+
+    /// whether IDLE should be used now, switches back to IDLE once the fallback expired
+    pub fn use_idle(&mut self, now: Instant) -> bool {
+        let Some(until) = self.polling_until else {
+            return true;
+        };
+        if now < until {
+            return false;
+        }
+        warn!("{}: trying idle again", self.source);
+        self.polling_until = None;
+        self.failures = 0;
+        metrics::increment(METRIC_MODE_SWITCHES, &self.source);
+        metrics::set_gauge(METRIC_POLLING_FALLBACK, &self.source, 0);
+        return true;
+    }
+
+    pub fn idle_succeeded(&mut self) {
+        self.failures = 0;
+    }
+
+    pub fn idle_failed(&mut self, now: Instant) {
+        self.failures += 1;
+        debug!(
+            "{}: idle failed {} of {} times",
+            self.source, self.failures, self.max_failures
+        );
+        if self.failures < self.max_failures || self.polling_until.is_some() {
+            return;
+        }
+        warn!(
+            "{}: idle failed {} times, polling for {} minutes",
+            self.source,
+            self.failures,
+            self.fallback_duration.as_secs() / 60
+        );
+        self.polling_until = Some(now + self.fallback_duration);
+        metrics::increment(METRIC_MODE_SWITCHES, &self.source);
+        metrics::set_gauge(METRIC_POLLING_FALLBACK, &self.source, 1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::{HybridMode, METRIC_MODE_SWITCHES, METRIC_POLLING_FALLBACK};
+    use crate::{config::config::IMAPModeDescription, metrics};
+
+    #[test]
+    fn test_fallback() {
+        let source = "test_fallback@host/INBOX";
+        let mode = IMAPModeDescription {
+            max_idle_failures: 2,
+            fallback_duration: 10,
+            ..Default::default()
+        };
+        let mut hybrid = HybridMode::new(source.to_string(), &mode);
+        let start = Instant::now();
+        assert!(hybrid.use_idle(start));
+
+        // a success resets the failures
+        hybrid.idle_failed(start);
+        hybrid.idle_succeeded();
+        hybrid.idle_failed(start);
+        assert!(hybrid.use_idle(start));
+        assert_eq!(metrics::get(METRIC_POLLING_FALLBACK, source), Some(0));
+
+        hybrid.idle_failed(start);
+        assert!(!hybrid.use_idle(start + Duration::from_secs(60)));
+        assert_eq!(metrics::get(METRIC_POLLING_FALLBACK, source), Some(1));
+        assert_eq!(metrics::get(METRIC_MODE_SWITCHES, source), Some(1));
+
+        assert!(hybrid.use_idle(start + Duration::from_secs(600)));
+        assert_eq!(metrics::get(METRIC_POLLING_FALLBACK, source), Some(0));
+        assert_eq!(metrics::get(METRIC_MODE_SWITCHES, source), Some(2));
+    }
+}
@@ -1,4 +1,6 @@
-use std::{cmp::max, time::Duration};
+use std::{cmp::max, path::PathBuf, time::Duration};
+
+use chrono::Local;
 
 use imap::{
     types::{Mailbox, UnsolicitedResponse},
@@ -7,12 +9,13 @@ use imap::{
 
 use log::{debug, error, info, trace, warn};
 
-use crate::config::Config;
+use crate::config::config::{AuthMethod, IMAPConfig, PostProcessingConfig};
 
-use super::imap_multipart::get_message_body;
+use super::imap_multipart::{get_message_content, MailContent};
+use super::imap_state::ImapState;
 use super::message::Message;
-
-const MAILBOX_INBOX: &str = "INBOX";
+use super::oauth::{refresh_access_token, OAuthBearer, XOAuth2};
+use super::tls::connect_client;
 
 /// Possible errors that can occur when using the IMAP IDLE command.
 pub enum IMAPIdleError {
@@ -25,108 +28,126 @@ pub struct IMAPConnection {
     session: Session<Box<dyn ImapConnection>>,
     inbox: Mailbox,
     idle_interval: Duration,
+    state: ImapState,
+    state_path: PathBuf,
+    max_age: Duration,
+    post_processing: PostProcessingConfig,
+    /// whether the server supports the MOVE command (RFC 6851)
+    supports_move: bool,
+    /// whether the server supports UID EXPUNGE (RFC 4315)
+    supports_uidplus: bool,
 }
 
 impl IMAPConnection {
     /// Creates a new IMAPConnection by connecting and authenticating to the server specified in the config.
     ///
     /// # description
-    /// Connects to the IMAPServer in config, using native OpenSSL (TLS).
-    /// It then logs in to the server using the username and password specified in the config and selects the INBOX mailbox.
-    pub fn connect(config: &Config) -> Result<Self, String> {
-        let imap_cfg = &config.imap;
-        let client = imap::ClientBuilder::new(&imap_cfg.host, imap_cfg.port)
-            .connect()
-            .map_err(|e| format!("couldn't create imap client: {}", e))?;
-        let mut session = client
-            .login(&imap_cfg.username, &imap_cfg.password)
-            .map_err(|e| format!("couldn't login to imap server: {}", e.0))?;
+    /// Connects to the IMAPServer in config, using the configured TLS mode (see [connect_client]).
+    /// It then logs in to the server using the username and password specified in the config (or an OAuth access token,
+    /// see [refresh_access_token]) and selects the configured mailbox.
+    /// The processing continues after the last mail processed by a previous run (see [ImapState::resume]).
+    pub fn connect(imap_cfg: &IMAPConfig) -> Result<Self, String> {
+        let client = connect_client(imap_cfg)?;
+        let session = match imap_cfg.auth.method {
+            AuthMethod::Login => client.login(&imap_cfg.username, &imap_cfg.password),
+            AuthMethod::XOAuth2 => {
+                let authenticator = XOAuth2 {
+                    user: imap_cfg.username.clone(),
+                    access_token: refresh_access_token(&imap_cfg.auth)?,
+                };
+                client.authenticate("XOAUTH2", &authenticator)
+            }
+            AuthMethod::OAuthBearer => {
+                let authenticator = OAuthBearer {
+                    user: imap_cfg.username.clone(),
+                    host: imap_cfg.host.clone(),
+                    port: imap_cfg.port,
+                    access_token: refresh_access_token(&imap_cfg.auth)?,
+                };
+                client.authenticate("OAUTHBEARER", &authenticator)
+            }
+        };
+        let mut session = session.map_err(|e| format!("couldn't login to imap server: {}", e.0))?;
 
         let inbox = session
-            .select(MAILBOX_INBOX)
-            .map_err(|e| format!("couldn't select mailbox {}: {}", MAILBOX_INBOX, e))?;
+            .select(&imap_cfg.mailbox)
+            .map_err(|e| format!("couldn't select mailbox {}: {}", imap_cfg.mailbox, e))?;
 
-        return Ok(Self {
-            session: session,
-            inbox: inbox,
-            idle_interval: config.interval_as_duration(),
+        let uid_validity = inbox.uid_validity.unwrap_or_else(|| {
+            warn!("server didn't send uidvalidity, assuming it never changes");
+            0
         });
-    }
+        let highest_uid = match inbox.uid_next {
+            Some(uid_next) => uid_next.saturating_sub(1),
+            None => session
+                .uid_search("ALL")
+                .map_err(|e| format!("couldn't search mailbox {}: {}", imap_cfg.mailbox, e))?
+                .into_iter()
+                .max()
+                .unwrap_or(0),
+        };
+        let capabilities = session
+            .capabilities()
+            .map_err(|e| format!("couldn't query capabilities: {}", e))?;
+        let supports_move = capabilities.has_str("MOVE");
+        let supports_uidplus = capabilities.has_str("UIDPLUS");
 
-    /// loads the newest mails in the inbox from the server
-    ///
-    /// # description
-    /// fetches all mails with an uid greater than the current max uid in the inbox.
-    /// the uid value is transmitted when selecting the inbox in the connect method.
-    /// the uid is then updated to the maximum uid of the fetched mails.
-    /// Therefor consecutive calls to this method will only fetch new mails and *should not* fetch the same message twice.
-    ///
-    #[deprecated(
-        note = "fails with an empty vec, use load_new_mails instead to get a result return"
-    )]
-    pub fn load_newest(&mut self) -> Vec<Option<String>> {
-        return self.load_since(self.inbox.exists + 1).unwrap_or_else(|e| {
-            error!("couldn't load newest mails: {:?}", e);
-            Vec::new()
-        });
+        let state_path = PathBuf::from(&imap_cfg.state_file);
+        let state = ImapState::resume(ImapState::load(&state_path), uid_validity, highest_uid);
+
+        let connection = Self {
+            session,
+            inbox,
+            idle_interval: imap_cfg.mode.interval_as_duration(),
+            state,
+            state_path,
+            max_age: imap_cfg.max_age_as_duration(),
+            post_processing: imap_cfg.post_processing.clone(),
+            supports_move,
+            supports_uidplus,
+        };
+        connection.save_state();
+        return Ok(connection);
     }
 
-    /// loads the newest mails in the inbox from the server
+    /// loads the mails, that arrived after the last processed one
     ///
     /// # description
-    /// fetches all mails with an uid greater than the current max uid in the inbox.
-    /// the uid value is transmitted when selecting the inbox in the connect method.
-    /// the uid is then updated to the maximum uid of the fetched mails.
-    /// Therefor consecutive calls to this method will only fetch new mails and *should not* fetch the same message twice.
-    ///
-    pub fn load_new_mails(&mut self) -> Result<Vec<Option<String>>, ()> {
-        // return self.load_since(self.inbox.exists + 1);
-        let messages = self
-            .session
-            .uid_fetch("12345:*", "(BODY[Header.FIELDS (Content-Type)] FLAGS UID)");
+    /// only the uids are fetched first, the mails are loaded only if there is a new one (see [Self::load_since]).
+    /// Consecutive calls to this method will only fetch new mails and *should not* fetch the same message twice.
+    /// The progress is only persisted by [Self::save_state], after the mails were processed.
+    pub fn load_new_mails(&mut self) -> Result<Vec<Option<MailContent>>, ()> {
+        let min_uid = self.state.last_uid + 1;
+        let uids = self.session.uid_fetch(format!("{}:*", min_uid), "UID");
 
-        let messages = match messages {
+        let uids = match uids {
             Err(e) => {
                 error!("failed to fetch newest message (uid set): {}", e);
                 return Err(());
             }
-            Ok(messages) => messages,
+            Ok(uids) => uids,
         };
 
-        let exists_curr = self.inbox.exists; // must be saved before filter, since it would otherwise be borrowed twice (once mutable in map)
-        if messages
-            .iter()
-            .map(Message::from_fetch)
-            .filter(|m| m.header.is_some())
-            .any(|m| m.seq > exists_curr)
-        {
-            // there is a message with a higher sequence number than the current max seq nr.
-            // unfortunately uid_fetch only fetches the most recent message, so we have to fetch all since last again
-
-            // note: reduces the risk of race conditions with new mails arriving while fetching, because
-            // load since uses an open interval, so it will always fetch all new mails.
+        // `n:*` always contains the mail with the highest uid, even if it is lower than n
+        if uids.iter().filter_map(|f| f.uid).any(|uid| uid >= min_uid) {
             trace!("new mail detected, fetching all new mails");
-            return self.load_since(exists_curr + 1);
+            return self.load_since(min_uid);
         }
 
         return Ok(vec![]);
     }
 
-    /// loads the newest mails in the inbox from the server
+    /// loads all mails with a uid of at least min_uid
     ///
     /// # description
-    /// fetches all mails with a uid greater than the current max uid in the inbox.
-    /// the uid value is transmitted when selecting the inbox in the connect method.
-    /// the uid is then updated to the maximum uid of the fetched mails.
-    /// Therefore consecutive calls to this method will only fetch new mails and *should not* fetch the same message twice.
-    ///
-    pub fn load_since(&mut self, min_id: u32) -> Result<Vec<Option<String>>, ()> {
-        let set = format!("{}:*", min_id);
-        debug!("fetching mails with set {}", set);
-        let fetch_res = self.session.fetch(
-            set,
-            "(BODY[Header.FIELDS (Content-Type)] FLAGS UID BODY[TEXT])",
-        );
+    /// mails received longer ago than the configured maximum age are skipped.
+    /// The highest fetched uid is remembered, so that the next call to [Self::load_new_mails] continues after it.
+    pub fn load_since(&mut self, min_uid: u32) -> Result<Vec<Option<MailContent>>, ()> {
+        let set = format!("{}:*", min_uid);
+        debug!("fetching mails with uid set {}", set);
+        let fetch_res = self
+            .session
+            .uid_fetch(set, "(UID INTERNALDATE BODY[HEADER] BODY[TEXT])");
 
         let messages = match fetch_res {
             Err(e) => {
@@ -136,22 +157,98 @@ impl IMAPConnection {
             Ok(messages) => messages,
         };
 
+        let oldest = Local::now() - self.max_age;
         return Ok(messages
             .iter()
             .map(Message::from_fetch)
-            .filter(|m| m.text.is_some())
+            .filter(|m| m.uid.is_some_and(|uid| uid >= min_uid))
             .map(|m| {
                 trace!("fetched message: {:?}", m.uid);
                 // set the maximum uid currently seen.
-
+                self.state.last_uid = max(self.state.last_uid, m.uid.unwrap_or(0));
                 self.inbox.exists = max(self.inbox.exists, m.seq);
 
                 return m;
             })
-            .map(get_message_body)
+            .filter(|m| m.text.is_some())
+            .filter(|m| match m.internal_date {
+                Some(received) if received < oldest => {
+                    info!("skipping mail {:?} received at {}", m.uid, received);
+                    false
+                }
+                _ => true,
+            })
+            .map(get_message_content)
             .collect());
     }
 
+    /// persists the uid of the last fetched mail, call after the fetched mails were processed
+    pub fn save_state(&self) {
+        if let Err(e) = self.state.save(&self.state_path) {
+            error!("{}", e);
+        }
+    }
+
+    /// marks a printed mail on the server: sets the configured flag and moves it to the processed folder
+    pub fn mark_processed(&mut self, uid: u32) {
+        if let Some(flag) = self.post_processing.flag.clone() {
+            let res = self
+                .session
+                .uid_store(uid.to_string(), format!("+FLAGS ({})", flag));
+            if let Err(e) = res {
+                error!("couldn't set flag {} on mail {}: {}", flag, uid, e);
+            }
+        }
+        if let Some(folder) = self.post_processing.processed_folder.clone() {
+            self.move_mail(uid, &folder);
+        }
+    }
+
+    /// moves a mail of a rejected sender to the quarantine folder (if configured)
+    pub fn mark_quarantined(&mut self, uid: u32) {
+        if let Some(folder) = self.post_processing.quarantine_folder.clone() {
+            self.move_mail(uid, &folder);
+        }
+    }
+
+    /// moves a mail, that couldn't be parsed, to the failed folder (if configured)
+    pub fn mark_failed(&mut self, uid: u32) {
+        if let Some(folder) = self.post_processing.failed_folder.clone() {
+            self.move_mail(uid, &folder);
+        }
+    }
+
+    /// moves the mail to another mailbox, using COPY and EXPUNGE if the server doesn't support MOVE
+    fn move_mail(&mut self, uid: u32, folder: &str) {
+        let uid_set = uid.to_string();
+        if self.supports_move {
+            match self.session.uid_mv(&uid_set, folder) {
+                Ok(_) => info!("moved mail {} to {}", uid, folder),
+                Err(e) => error!("couldn't move mail {} to {}: {}", uid, folder, e),
+            }
+            return;
+        }
+
+        if let Err(e) = self.session.uid_copy(&uid_set, folder) {
+            error!("couldn't copy mail {} to {}: {}", uid, folder, e);
+            return; // keep the mail, if the copy failed
+        }
+        if let Err(e) = self.session.uid_store(&uid_set, "+FLAGS (\\Deleted)") {
+            error!("couldn't delete mail {} after copying it: {}", uid, e);
+            return;
+        }
+        let res = if self.supports_uidplus {
+            self.session.uid_expunge(&uid_set)
+        } else {
+            // also removes other mails flagged as deleted, but there is no other way without UIDPLUS
+            self.session.expunge()
+        };
+        match res {
+            Ok(_) => info!("moved mail {} to {}", uid, folder),
+            Err(e) => error!("couldn't expunge mail {} after copying it: {}", uid, e),
+        }
+    }
+
     pub fn most_current_id(&self) -> u32 {
         return self.inbox.exists;
     }
@@ -194,41 +291,68 @@ impl IMAPConnection {
         };
     }
 
-    /// waits for new mails to arrive in the inbox using the IMAP IDLE command. Reconnects on error.
+    /// Waits for a new mail using the IMAP IDLE command, but at most for `timeout`.
+    ///
+    /// # return value
+    /// whether the server notified about a new mail, false on a timeout
+    pub fn idle_with_timeout(&mut self, timeout: Duration) -> Result<bool, IMAPIdleError> {
+        let mut idle = self.session.idle();
+        idle.timeout(timeout);
+        idle.keepalive(false);
+        let mut notified = false;
+
+        let res = idle.wait_while(|response| {
+            return match response {
+                UnsolicitedResponse::Exists(_) => {
+                    notified = true;
+                    false
+                }
+                _ => {
+                    trace!("unsolicited response while idling: {:?}", response);
+                    true
+                }
+            };
+        });
+
+        return match res {
+            Ok(_) => Ok(notified),
+            Err(e) => {
+                error!("idle error: {}", e);
+                Err(IMAPIdleError::InitialisationError)
+            }
+        };
+    }
+
+    /// checks the connection with a NOOP and loads the mails, that arrived since the last check
+    pub fn check_new_mails(&mut self) -> Result<Vec<Option<MailContent>>, ()> {
+        self.session
+            .noop()
+            .map_err(|e| error!("noop failed: {}", e))?;
+        return self.load_new_mails();
+    }
+
+    /// waits for new mails using the IMAP IDLE command and loads them.
     ///
     /// # description
-    /// similar to await_new_mail, but reconnects on errors.
-    /// The reconnect strategy is currently to reconnect immediately. In the future this might be changed
-    /// to wait for a dynamically calculated time period, based on the number of errors, before retrying.
+    /// errors are not retried here, the connection is reestablished with an increasing delay
+    /// instead (see [Supervisor]).
     ///
-    pub fn reconnecting_await_new_mail(&mut self) -> Result<Vec<Option<String>>, ()> {
-        const MAX_RECONNECTION_ATTEMPTS: u8 = 3; // try to reconnect, but fail rather fast
-        let mut init_err_count = 0;
-        loop {
-            let result = self.await_new_mail();
-            if let Ok(exists) = result {
+    /// [Supervisor]: super::supervisor::Supervisor
+    pub fn idle_for_new_mails(&mut self) -> Result<Vec<Option<MailContent>>, ()> {
+        return match self.await_new_mail() {
+            Ok(exists) => {
                 info!("new mail nr: {}", exists);
-                return self.load_new_mails();
-            } else if let Err(e) = result {
-                match e {
-                    IMAPIdleError::InitialisationError => {
-                        init_err_count += 1;
-                        if init_err_count > MAX_RECONNECTION_ATTEMPTS {
-                            error!("too many initialisation errors");
-                            // strategy: error out and signal to the main process to reestablish
-                            // the connection from the get go (not only the idle request).
-                            return Err(());
-                        }
-                        info!("resetting idle connection");
-                    }
-
-                    IMAPIdleError::ConnectionError => {
-                        info!("resetting idle connection");
-                        init_err_count = 0;
-                    }
-                }
+                self.load_new_mails()
             }
-        }
+            Err(IMAPIdleError::InitialisationError) => {
+                error!("couldn't wait for new mails");
+                Err(())
+            }
+            Err(IMAPIdleError::ConnectionError) => {
+                error!("idle connection lost");
+                Err(())
+            }
+        };
     }
 
     /// ends the session by logging out
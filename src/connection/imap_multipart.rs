@@ -1,4 +1,12 @@
-use super::message::Message;
+use super::{
+    message::{mail_str_decode_unicode, Message},
+    mime::{
+        decode_multipart_text, decode_text, first_line_delimiter, header_value,
+        multipart_delimiter, parse_headers, split_multipart, split_part, ContentType,
+        TransferEncoding, MAX_MULTIPART_DEPTH,
+    },
+};
+use crate::models::attachment::Attachment;
 
 use log::{debug, error, trace, warn};
 
@@ -16,73 +24,143 @@ use log::{debug, error, trace, warn};
 ///
 /// # return value
 ///
-/// The plain text of the mail is returned, if it exists and the mail was multipart.
+/// The decoded plain text of the mail is returned, if it exists and the mail was multipart.
+/// If there is no text/plain part, the text/html part is converted to plain text (see [html_to_text]).
 ///
 /// For mails that do not contain a multipart body, None is returned - even if the mail itself is valid plain text content.
 /// $\implies$ Before calling this function, the mail headers (not within the body) should be checked for content-type multipart.
 ///
 pub fn extract_multipart_plain_text(content: &str) -> Option<String> {
     // @pre: content is a multipart mail, starting with the separator
-
-    let Some(sepereator_end) = content.find("\r\n") else {
-        error!("couldn't find separator end");
+    if !content.starts_with("--") {
+        error!("couldn't find separator");
         return None;
-    };
-    let separator = content[..sepereator_end].to_string();
+    }
+    return decode_multipart_text(content.as_bytes());
+}
 
-    let parts = content.split(&separator);
-    for part in parts {
-        let header_end = part.find("\r\n\r\n").unwrap_or_default();
-        let headers = &part[..header_end].to_string();
-        if headers.is_empty() {
-            continue;
-        }
-        let headers = headers.split("\r\n");
-        let mut is_plain_text = false;
-        for header in headers {
-            if header.starts_with("Content-Type: text/plain") {
-                is_plain_text = true;
-                break;
-            }
-        }
-        if !is_plain_text {
-            continue;
-        }
+/// the content of these elements is not displayed and therefore skipped
+const HTML_HIDDEN_ELEMENTS: &[&str] = &["head", "style", "script", "title"];
+/// elements, that start a new line when rendered
+const HTML_BLOCK_ELEMENTS: &[&str] = &["p", "div", "br", "tr", "li", "h1", "h2", "h3", "table"];
+/// longest entity name (incl. '#' for numeric entities) decoded by [html_to_text]
+const HTML_MAX_ENTITY_LENGTH: usize = 8;
 
-        let body = &part[header_end + 4..];
-        return Some(body.to_string());
+fn decode_html_entity(entity: &str) -> Option<char> {
+    if let Some(number) = entity.strip_prefix('#') {
+        let code = match number.strip_prefix(['x', 'X']) {
+            Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+            None => number.parse::<u32>().ok()?,
+        };
+        return char::from_u32(code);
     }
-
-    return None;
+    return match entity {
+        "amp" => Some('&'),
+        "lt" => Some('<'),
+        "gt" => Some('>'),
+        "quot" => Some('"'),
+        "apos" => Some('\''),
+        "nbsp" => Some(' '), // the parser splits units at ", "
+        "auml" => Some('ä'),
+        "ouml" => Some('ö'),
+        "uuml" => Some('ü'),
+        "Auml" => Some('Ä'),
+        "Ouml" => Some('Ö'),
+        "Uuml" => Some('Ü'),
+        "szlig" => Some('ß'),
+        _ => None,
+    };
 }
 
-fn headers_get_content_type(headers: Vec<u8>) -> Option<String> {
-    const HEADER_CONTENT_TYPE: &str = "Content-Type: ";
-    let Ok(headers) = String::from_utf8(headers.to_vec()).map_err(|_| {
-        warn!("couldn't convert headers to string");
-    }) else {
-        return None;
-    };
+/// Converts (already transfer-decoded) html to plain text.
+///
+/// # description
+/// Tags are removed, block elements (e.g. `<p>`, `<br>`) end the line and entities like `&amp;` are decoded.
+/// Line breaks within the html source are replaced by spaces, as a browser would do.
+/// Unknown entities are kept unchanged.
+pub fn html_to_text(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    let mut hidden_element: Option<String> = None;
+    let mut chars = html.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c == '<' {
+            let mut tag = String::new();
+            for t in chars.by_ref() {
+                if t == '>' {
+                    break;
+                }
+                tag.push(t);
+            }
+            let is_closing = tag.starts_with('/');
+            let name: String = tag
+                .trim_start_matches('/')
+                .chars()
+                .take_while(|c| c.is_ascii_alphanumeric())
+                .collect::<String>()
+                .to_ascii_lowercase();
 
-    let headers = headers.split("\r\n");
-    const HEADER_CONTENT_TRANSFER_ENCODING: &str = "Content-transfer-encoding: ";
-    for header in headers {
-        if header.starts_with(HEADER_CONTENT_TYPE) {
-            let content_type = header[HEADER_CONTENT_TYPE.len()..].to_string();
-            debug!("content type of new message: {}", header);
-            return Some(content_type);
+            if let Some(hidden) = &hidden_element {
+                if is_closing && &name == hidden {
+                    hidden_element = None;
+                }
+                continue;
+            }
+            if !is_closing && HTML_HIDDEN_ELEMENTS.contains(&name.as_str()) {
+                hidden_element = Some(name);
+                continue;
+            }
+            if HTML_BLOCK_ELEMENTS.contains(&name.as_str()) && (is_closing || name == "br") {
+                text.push_str("\r\n");
+            }
+            continue;
         }
-        if header.starts_with(HEADER_CONTENT_TRANSFER_ENCODING) {
-            let encoding = header[HEADER_CONTENT_TRANSFER_ENCODING.len()..].to_string();
-            debug!("content transfer encoding of new message: {}", encoding);
+        if hidden_element.is_some() {
+            continue;
         }
-    }
 
-    return None;
+        match c {
+            '\r' => {}
+            '\n' => text.push(' '),
+            '&' => {
+                let mut entity = String::new();
+                while let Some(e) = chars.peek() {
+                    if !(e.is_ascii_alphanumeric() || *e == '#')
+                        || entity.len() > HTML_MAX_ENTITY_LENGTH
+                    {
+                        break;
+                    }
+                    entity.push(*e);
+                    chars.next();
+                }
+                let decoded = if chars.peek() == Some(&';') {
+                    decode_html_entity(&entity)
+                } else {
+                    None
+                };
+                if let Some(decoded) = decoded {
+                    chars.next(); // skip the ';'
+                    text.push(decoded);
+                } else {
+                    trace!("unknown html entity &{}", entity);
+                    text.push('&');
+                    text.push_str(&entity);
+                }
+            }
+            _ => text.push(c),
+        }
+    }
+    return text;
 }
 
 const DEBUG_MESSAGE_FILE: &str = "debug_message.txt";
 
+/// Decodes the text of a fetched mail (see [decode_text]).
+///
+/// # description
+/// If the headers were not part of the response, the body is checked for a multipart separator.
+/// Bodies without a Content-Transfer-Encoding header are decoded with [mail_str_decode_unicode],
+/// since the dispatch centre sends quoted-printable.
 pub fn get_message_body(message: Message) -> Option<String> {
     let Some(body) = message.text else {
         error!(
@@ -91,56 +169,164 @@ pub fn get_message_body(message: Message) -> Option<String> {
         );
         return None;
     };
+    trace!("got mail body: {}", String::from_utf8_lossy(&body));
+
+    #[cfg(debug_assertions)]
+    {
+        use std::fs::write;
+        write(DEBUG_MESSAGE_FILE, &body).unwrap();
+    }
+
+    let headers = message
+        .header
+        .map(|h| String::from_utf8_lossy(&h).to_string())
+        .unwrap_or_default();
+    let parsed_headers = parse_headers(&headers);
+    let content_type = header_value(&parsed_headers, "content-type");
+    debug!("content type of new message: {:?}", content_type);
 
-    // TODO: add decoding step here?
+    // check if the mail is multipart, even if no headers were part of the response
+    // (due to the imap fetch request not including the content-type header o.s.)
+    if content_type.is_none() && body.starts_with(b"--") {
+        return extract_multipart_plain_text(&String::from_utf8_lossy(&body));
+    }
 
-    let content = String::from_utf8(body.to_vec()).map_err(|_| {
+    let text = decode_text(&headers, &body);
+    if text.is_none() {
         error!(
-            "couldn't convert mail {} to string.",
+            "mail {} contains no text part",
             message.uid.unwrap_or_default()
         );
-    });
-    let Ok(content) = content else {
-        return None;
-    };
-    trace!("got mail body: {}", content);
+    }
 
-    #[cfg(debug_assertions)]
-    {
-        use std::fs::write;
-        write(DEBUG_MESSAGE_FILE, content.clone()).unwrap();
+    let is_multipart = content_type.is_some_and(|c| c.to_ascii_lowercase().contains("multipart/"));
+    if !is_multipart && header_value(&parsed_headers, "content-transfer-encoding").is_none() {
+        return text.map(|t| mail_str_decode_unicode(&t));
     }
+    return text;
+}
 
-    if let Some(headers) = message.header {
-        let content_type = headers_get_content_type(headers);
-        trace!("body content type: {:?}", content_type);
-        if let Some(content_type) = content_type {
-            if content_type.contains("multipart/alternative") {
-                return extract_multipart_plain_text(&content);
-            }
-            if content_type.contains("text/plain") {
-                // plain text mail
-                return Some(content);
-            }
+/// The decoded content of a fetched mail.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MailContent {
+    /// the uid on the server, needed to flag or move the mail after processing
+    pub uid: Option<u32>,
+    /// all headers of the mail (lower case names), used to check the sender
+    pub headers: Vec<(String, String)>,
+    pub text: String,
+    pub attachments: Vec<Attachment>,
+}
+
+fn collect_attachments(
+    headers: &[(String, String)],
+    body: &[u8],
+    depth: usize,
+    attachments: &mut Vec<Attachment>,
+) {
+    let content_type = header_value(headers, "content-type")
+        .map(ContentType::parse)
+        .unwrap_or_default();
+
+    if content_type.mime_type.starts_with("multipart/") {
+        let delimiter = content_type
+            .parameter("boundary")
+            .and_then(|boundary| multipart_delimiter(boundary, body));
+        let Some(delimiter) = delimiter else {
+            return;
+        };
+        if depth >= MAX_MULTIPART_DEPTH {
+            warn!("multipart nested too deep, ignoring its attachments");
+            return;
         }
+        for part in split_multipart(body, &delimiter) {
+            let (part_headers, part_body) = split_part(part);
+            let part_headers = parse_headers(&String::from_utf8_lossy(part_headers));
+            collect_attachments(&part_headers, part_body, depth + 1, attachments);
+        }
+        return;
     }
 
-    // check if the mail is multipart, even if no headers were part of the response
-    // (due to the imap fetch request not including the content-type header o.s.)
-    if content.starts_with("--") {
-        // multipart mail
-        // this sequence from -- to \r\n is used to separate the different parts of the mail
-        // possible parts are: text/plain, text/html, etc.
-        // we only want the text/plain part
-        return extract_multipart_plain_text(&content);
+    // Content-Disposition has the same syntax as Content-Type, e.g. `attachment; filename="map.png"`
+    let disposition = header_value(headers, "content-disposition").map(ContentType::parse);
+    let is_attachment = disposition
+        .as_ref()
+        .is_some_and(|d| d.mime_type == "attachment");
+    // inline images (e.g. a map in the html part) are attachments as well, the text parts are not
+    if depth == 0 || (!is_attachment && content_type.mime_type.starts_with("text/")) {
+        return;
+    }
+
+    let filename = disposition
+        .as_ref()
+        .and_then(|d| d.parameter("filename"))
+        .or_else(|| content_type.parameter("name"))
+        .map(|f| f.to_string());
+    let encoding = header_value(headers, "content-transfer-encoding")
+        .map(TransferEncoding::parse)
+        .unwrap_or(TransferEncoding::Identity);
+    trace!(
+        "found attachment {:?} of type {}",
+        filename,
+        content_type.mime_type
+    );
+    attachments.push(Attachment {
+        filename,
+        mime_type: content_type.mime_type.clone(),
+        data: encoding.decode(body),
+    });
+}
+
+/// Extracts all attachments (parts, that are not the text of the mail) from a multipart mail.
+///
+/// # arguments
+/// * `headers` - the mail headers, at least Content-Type. If missing, the first line of the body is used as separator.
+/// * `body` - the raw mail body (BODY[TEXT])
+pub fn extract_attachments(headers: &str, body: &[u8]) -> Vec<Attachment> {
+    let mut headers = parse_headers(headers);
+    if header_value(&headers, "content-type").is_none() {
+        let Some(delimiter) = first_line_delimiter(body) else {
+            return Vec::new(); // not a multipart mail
+        };
+        // multipart_delimiter accepts the boundary including the leading dashes
+        headers.push((
+            "content-type".to_string(),
+            format!("multipart/mixed; boundary=\"{}\"", delimiter),
+        ));
     }
 
-    return Some(content);
+    let mut attachments = Vec::new();
+    collect_attachments(&headers, body, 0, &mut attachments);
+    return attachments;
+}
+
+/// Decodes the text (see [get_message_body]) and the attachments of a fetched mail.
+pub fn get_message_content(message: Message) -> Option<MailContent> {
+    let headers = message
+        .header
+        .as_ref()
+        .map(|h| String::from_utf8_lossy(h).to_string())
+        .unwrap_or_default();
+    let attachments = message
+        .text
+        .as_ref()
+        .map(|body| extract_attachments(&headers, body))
+        .unwrap_or_default();
+    let uid = message.uid;
+    let text = get_message_body(message)?;
+    return Some(MailContent {
+        uid,
+        headers: parse_headers(&headers),
+        text,
+        attachments,
+    });
 }
 
 #[cfg(test)]
 pub mod test {
-    use super::extract_multipart_plain_text;
+    use std::str::FromStr;
+
+    use super::{extract_attachments, extract_multipart_plain_text, html_to_text};
+    use crate::{connection::message::mail_str_decode_unicode, models::emergency::Emergency};
 
     pub const MULTIPART_BODY: &str = "--fcd0a2e3-f220-407c-96ea-a69339f943bc-1\r\nContent-Type: text/plain; charset=\"utf-8\"\r\nContent-Transfer-Encoding: quoted-printable\r\n\r\n~~Ort~~Brandenburg an der Havel~~\r\n\r\n\r\n~~Ortsteil~~G=C3=B6ttin/BRB~~\r\n\r\n\r\n\r\n~~Ortslage~~G=C3=B6risgr=C3=A4ben~~\r\n\r\n\r\n\r\n~~Strasse~~G=C3=B6risgr=C3=A4ben~~\r\n\r\n\r\n\r\n~~Hausnummer~~22~~\r\n\r\n\r\n\r\n~~Objekt~~~~\r\n\r\n\r\n\r\n~~FWPlan~~~~\r\n\r\n\r\n\r\n~~Objektteil~~~~\r\n\r\n\r\n\r\n~~Objektnummer~~-1~~\r\n\r\n\r\n\r\n~~Einsatzart~~Hilfeleistungseinsatz~~\r\n\r\n\r\n\r\n~~Alarmgrund~~H:Natur~~\r\n\r\n\r\n\r\n~~Sondersignal~~ohne Sondersignal~~\r\n\r\n\r\n\r\n~~Einsatznummer~~322088295~~\r\n\r\n\r\n\r\n~~Besonderheiten~~TESTETESTTESTE~~\r\n\r\n\r\n\r\n~~Name~~,~~\r\n\r\n\r\n\r\n~~EMListe~~FL BRB 01/16-21, RLS BRB DGL 2~~\r\n\r\n\r\n\r\n~~Status~~Fahrzeug~~Zuget~~Alarm~~Ausger=C3=BCckt~~\r\n\r\n\r\n\r\n~~ALARM~~unbekannt#~~BRB FW Brandenburg 1=C3=B8~~FL BRB 01/16-21~~08:21~~=\r\n~~\r\n\r\n\r\n\r\n~~ALARM~~unbekannt#~~BRB FW Brandenburg 1=C3=B8~~FL BRB 01/16-21~~08:21~~=\r\n~~\r\n\r\n\r\n\r\n~~ALARM~~unbekannt#~~BRB FW Brandenburg 1=C3=B8~~RLS BRB DGL 2~~08:23~~~~\r\n\r\n\r\n\r\n~~WGS84_X~~52.33823333~~\r\n\r\n\r\n\r\n~~WGS84_Y~~12.48626667~~\r\n\r\n\r\n\r\n~~Koord_EPSG_25833~~12.48626667~~52.33823333~~\r\n\r\n\r\n\r\n~~Koord_EPSG_4326~~E1248630~~N5233820~~~~Einsatzortzusatz~~~~\r\n\r\n\r\n\r\n~~Alarmzeit~~29.09.22&08:23~~\r\n--fcd0a2e3-f220-407c-96ea-a69339f943bc-1\r\nContent-Type: text/html; charset=\"utf-8\"\r\nContent-Transfer-Encoding: quoted-printable\r\n\r\n<!DOCTYPE html><html><head><meta http-equiv=3D\"Content-Type\" content=3D\"t=\r\next/html; charset=3Dutf-8\"></head><body><div style><div style><div style>=\r\n<div style><div style>~~Ort~~Brandenburg an der Havel~~<u style></u></div=\r\n><div style><div dir=3D\"ltr\" style><div style><div style><div link=3D\"#05=\r\n63C1\" vlink=3D\"#954F72\" style=3D\"overflow-wrap: break-word;\" lang=3D\"DE\">=\r\n<div style><p style>~~Ortsteil~~G=C3=B6ttin/BRB~~<u style></u></p><p styl=\r\ne>~~Ortslage~~G=C3=B6risgr=C3=A4ben~~<u style></u></p><p style>~~Strasse~=\r\n~G=C3=B6risgr=C3=A4ben~~<u style></u></p><p style>~~Hausnummer~~22~~<u st=\r\nyle></u></p><p style>~~Objekt~~~~<u style></u></p><p style>~~FWPlan~~~~<u=\r\n style></u></p><p style>~~Objektteil~~~~<u style></u></p><p style>~~Objek=\r\ntnummer~~-1~~<u style></u></p><p style>~~Einsatzart~~Hilfeleistungseinsat=\r\nz~~<u style></u></p><p style>~~Alarmgrund~~H:Natur~~<u style></u></p><p s=\r\ntyle>~~Sondersignal~~ohne Sondersignal~~<u style></u></p><p style>~~Einsa=\r\ntznummer~~322088295~~<u style></u></p><p style>~~Besonderheiten~~TESTETES=\r\nTTESTE~~<u style></u></p><p style>~~Name~~,~~<u style></u></p><p style>~~=\r\nEMListe~~FL BRB 01/16-21, RLS BRB DGL 2~~<u style></u></p><p style>~~Stat=\r\nus~~Fahrzeug~~Zuget~~Alarm~~Ausger=C3=BCckt~~<u style></u></p><p style>~~=\r\nALARM~~unbekannt#~~BRB FW Brandenburg 1=C3=B8~~FL BRB 01/16-21~~08:21~~~~=\r\n<u style></u></p><p style>~~ALARM~~unbekannt#~~BRB FW Brandenburg 1=C3=B8=\r\n~~FL BRB 01/16-21~~08:21~~~~<u style></u></p><p style>~~ALARM~~unbekannt#=\r\n~~BRB FW Brandenburg 1=C3=B8~~RLS BRB DGL 2~~08:23~~~~<u style></u></p><p=\r\n style>~~WGS84_X~~52.33823333~~<u style></u></p><p style>~~WGS84_Y~~12.48=\r\n626667~~<u style></u></p><p style>~~Koord_EPSG_25833~~12.48626667~~52.338=\r\n23333~~<u style></u></p><p style>~~Koord_EPSG_4326~~E1248630~~N5233820~~~=\r\n~Einsatzortzusatz~~~~<u style></u></p><p style>~~Alarmzeit~~29.09.22&amp;=\r\n08:23~~<u style></u></p></div></div></div></div></div></div><div style><b=\r\nr></div></div></div></div></div></body></html>\r\n--fcd0a2e3-f220-407c-96ea-a69339f943bc-1--\r\n";
     pub const MULTIPART_BODY_PLAIN_TEXT: &str = "~~Ort~~Brandenburg an der Havel~~\r\n\r\n\r\n~~Ortsteil~~G=C3=B6ttin/BRB~~\r\n\r\n\r\n\r\n~~Ortslage~~G=C3=B6risgr=C3=A4ben~~\r\n\r\n\r\n\r\n~~Strasse~~G=C3=B6risgr=C3=A4ben~~\r\n\r\n\r\n\r\n~~Hausnummer~~22~~\r\n\r\n\r\n\r\n~~Objekt~~~~\r\n\r\n\r\n\r\n~~FWPlan~~~~\r\n\r\n\r\n\r\n~~Objektteil~~~~\r\n\r\n\r\n\r\n~~Objektnummer~~-1~~\r\n\r\n\r\n\r\n~~Einsatzart~~Hilfeleistungseinsatz~~\r\n\r\n\r\n\r\n~~Alarmgrund~~H:Natur~~\r\n\r\n\r\n\r\n~~Sondersignal~~ohne Sondersignal~~\r\n\r\n\r\n\r\n~~Einsatznummer~~322088295~~\r\n\r\n\r\n\r\n~~Besonderheiten~~TESTETESTTESTE~~\r\n\r\n\r\n\r\n~~Name~~,~~\r\n\r\n\r\n\r\n~~EMListe~~FL BRB 01/16-21, RLS BRB DGL 2~~\r\n\r\n\r\n\r\n~~Status~~Fahrzeug~~Zuget~~Alarm~~Ausger=C3=BCckt~~\r\n\r\n\r\n\r\n~~ALARM~~unbekannt#~~BRB FW Brandenburg 1=C3=B8~~FL BRB 01/16-21~~08:21~~=\r\n~~\r\n\r\n\r\n\r\n~~ALARM~~unbekannt#~~BRB FW Brandenburg 1=C3=B8~~FL BRB 01/16-21~~08:21~~=\r\n~~\r\n\r\n\r\n\r\n~~ALARM~~unbekannt#~~BRB FW Brandenburg 1=C3=B8~~RLS BRB DGL 2~~08:23~~~~\r\n\r\n\r\n\r\n~~WGS84_X~~52.33823333~~\r\n\r\n\r\n\r\n~~WGS84_Y~~12.48626667~~\r\n\r\n\r\n\r\n~~Koord_EPSG_25833~~12.48626667~~52.33823333~~\r\n\r\n\r\n\r\n~~Koord_EPSG_4326~~E1248630~~N5233820~~~~Einsatzortzusatz~~~~\r\n\r\n\r\n\r\n~~Alarmzeit~~29.09.22&08:23~~\r\n";
@@ -149,7 +335,10 @@ pub mod test {
     pub fn test_extract_multipart_plain_text_from_multipart() {
         let plaintext = extract_multipart_plain_text(MULTIPART_BODY);
         assert!(plaintext.is_some());
-        assert_eq!(plaintext.unwrap(), MULTIPART_BODY_PLAIN_TEXT);
+        // the part is quoted-printable, which is decoded as well.
+        // the line break before the separator belongs to the separator (RFC 2046)
+        let expected = mail_str_decode_unicode(MULTIPART_BODY_PLAIN_TEXT);
+        assert_eq!(plaintext.unwrap(), expected.strip_suffix("\r\n").unwrap());
     }
 
     #[test]
@@ -158,6 +347,83 @@ pub mod test {
         assert!(plaintext.is_none());
     }
 
+    #[test]
+    pub fn test_extract_multipart_html_fallback() {
+        // drop the text/plain part, so only the html part is left
+        let html_start = MULTIPART_BODY.find("Content-Type: text/html").unwrap();
+        let separator = &MULTIPART_BODY[..MULTIPART_BODY.find("\r\n").unwrap() + 2];
+        let html_only = format!("{}{}", separator, &MULTIPART_BODY[html_start..]);
+
+        let text = extract_multipart_plain_text(&html_only).unwrap();
+        assert!(!text.contains('<'), "{}", text);
+        assert!(text.contains("~~Alarmzeit~~29.09.22&08:23~~"), "{}", text);
+
+        let from_html = Emergency::from_str(&text).unwrap();
+        let from_plain =
+            Emergency::from_str(&mail_str_decode_unicode(MULTIPART_BODY_PLAIN_TEXT)).unwrap();
+        assert_eq!(from_html, from_plain);
+    }
+
+    #[test]
+    pub fn test_html_to_text() {
+        assert_eq!(
+            html_to_text("<html><head><title>Alarm</title></head><body><p>a &amp; b</p>c<br>&lt;d&gt; &unknown; &#228;&#xFC;</body></html>"),
+            "a & b\r\nc\r\n<d> &unknown; äü"
+        );
+        assert_eq!(
+            html_to_text("~~Ort~~\r\nKleinmachnow~~"),
+            "~~Ort~~ Kleinmachnow~~"
+        );
+    }
+
+    const MAIL_WITH_ATTACHMENTS: &str = "--outer\r\n\
+Content-Type: multipart/related; boundary=\"inner\"\r\n\
+\r\n\
+--inner\r\n\
+Content-Type: text/plain; charset=utf-8\r\n\
+\r\n\
+~~Stichwort~~B:BMA~~\r\n\
+--inner\r\n\
+Content-Type: image/png; name=\"karte.png\"\r\n\
+Content-Disposition: inline\r\n\
+Content-Transfer-Encoding: base64\r\n\
+\r\n\
+iVBORw0KGgo=\r\n\
+--inner--\r\n\
+--outer\r\n\
+Content-Type: application/pdf\r\n\
+Content-Disposition: attachment; filename=\"Alarmfax.pdf\"\r\n\
+Content-Transfer-Encoding: base64\r\n\
+\r\n\
+JVBERi0xLjQ=\r\n\
+--outer--\r\n";
+
+    #[test]
+    pub fn test_extract_attachments() {
+        let headers = "Content-Type: multipart/mixed;\r\n boundary=\"outer\"\r\n";
+        let attachments = extract_attachments(headers, MAIL_WITH_ATTACHMENTS.as_bytes());
+        assert_eq!(attachments.len(), 2);
+
+        assert_eq!(attachments[0].filename, Some("karte.png".to_string()));
+        assert_eq!(attachments[0].mime_type, "image/png");
+        assert!(attachments[0].is_image());
+        assert_eq!(attachments[0].data, b"\x89PNG\r\n\x1a\n");
+
+        assert_eq!(attachments[1].filename, Some("Alarmfax.pdf".to_string()));
+        assert!(attachments[1].is_pdf());
+        assert_eq!(attachments[1].data, b"%PDF-1.4");
+
+        // without headers the first line is used as separator
+        assert_eq!(
+            extract_attachments("", MAIL_WITH_ATTACHMENTS.as_bytes()),
+            attachments
+        );
+        assert!(extract_attachments("", b"~~Stichwort~~B:BMA~~").is_empty());
+        // an empty boundary can't be split at
+        let headers = "Content-Type: multipart/mixed; boundary=\"\"\r\n";
+        assert!(extract_attachments(headers, b"~~Stichwort~~B:BMA~~").is_empty());
+    }
+
     #[test]
     pub fn test_extract_multipart_plain_text_from_empty() {
         let plaintext = extract_multipart_plain_text("");
@@ -0,0 +1,129 @@
+use std::{fs, path::Path};
+
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+
+/// The progress of the mail processing, persisted so that alarms received while the program
+/// was not running are processed after a restart.
+///
+/// # description
+/// UIDs are only valid together with the UIDVALIDITY of the mailbox. If the server changes it
+/// (e.g. the mailbox was recreated), all stored UIDs are meaningless.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub struct ImapState {
+    pub uid_validity: u32,
+    /// the highest UID, that was processed
+    pub last_uid: u32,
+}
+
+impl ImapState {
+    /// loads the state file, None if it doesn't exist or can't be read
+    pub fn load(path: &Path) -> Option<Self> {
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) => {
+                debug!("couldn't read imap state file {:?}: {}", path, e);
+                return None;
+            }
+        };
+        return serde_yaml::from_str(&content)
+            .map_err(|e| warn!("ignoring invalid imap state file {:?}: {}", path, e))
+            .ok();
+    }
+
+    /// saves the state, replacing the file only after it was written completely
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let content = serde_yaml::to_string(self)
+            .map_err(|e| format!("couldn't serialize imap state: {}", e))?;
+        let tmp_path = path.with_extension("tmp");
+        fs::write(&tmp_path, content)
+            .map_err(|e| format!("couldn't write imap state to {:?}: {}", tmp_path, e))?;
+        fs::rename(&tmp_path, path)
+            .map_err(|e| format!("couldn't replace imap state file {:?}: {}", path, e))?;
+        return Ok(());
+    }
+
+    /// Determines where to continue processing after selecting the mailbox.
+    ///
+    /// # arguments
+    /// * `stored` - the state saved by the previous run
+    /// * `uid_validity` - the UIDVALIDITY of the selected mailbox
+    /// * `highest_uid` - the highest UID currently in the mailbox
+    ///
+    /// # return value
+    /// * the stored state, if the UIDVALIDITY is unchanged. All mails received in the meantime are processed.
+    /// * without a stored state (first start), only mails arriving from now on are processed.
+    /// * if the UIDVALIDITY changed, the whole mailbox is checked again. Only mails younger than
+    ///   the configured maximum age are processed, so old alarms aren't printed again.
+    pub fn resume(stored: Option<ImapState>, uid_validity: u32, highest_uid: u32) -> ImapState {
+        return match stored {
+            Some(state) if state.uid_validity == uid_validity => {
+                info!("resuming after uid {}", state.last_uid);
+                state
+            }
+            Some(state) => {
+                warn!(
+                    "uidvalidity changed from {} to {}, checking all recent mails again",
+                    state.uid_validity, uid_validity
+                );
+                ImapState {
+                    uid_validity,
+                    last_uid: 0,
+                }
+            }
+            None => {
+                info!("no imap state found, starting after uid {}", highest_uid);
+                ImapState {
+                    uid_validity,
+                    last_uid: highest_uid,
+                }
+            }
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::ImapState;
+
+    #[test]
+    fn test_resume() {
+        let stored = ImapState {
+            uid_validity: 42,
+            last_uid: 100,
+        };
+        assert_eq!(ImapState::resume(Some(stored), 42, 120), stored);
+        assert_eq!(
+            ImapState::resume(Some(stored), 43, 120),
+            ImapState {
+                uid_validity: 43,
+                last_uid: 0
+            }
+        );
+        assert_eq!(
+            ImapState::resume(None, 42, 120),
+            ImapState {
+                uid_validity: 42,
+                last_uid: 120
+            }
+        );
+    }
+
+    #[test]
+    fn test_save_load() {
+        let path = env::temp_dir().join("emergency_mail_test_imap_state.yaml");
+        let state = ImapState {
+            uid_validity: 7,
+            last_uid: 1234,
+        };
+        state.save(&path).unwrap();
+        assert_eq!(ImapState::load(&path), Some(state));
+
+        std::fs::write(&path, "no: state").unwrap();
+        assert_eq!(ImapState::load(&path), None);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(ImapState::load(&path), None);
+    }
+}
@@ -1,6 +1,9 @@
+use chrono::{DateTime, FixedOffset};
 use imap::types::{Fetch, Seq};
 use log::trace;
 
+use super::mime::split_part;
+
 #[cfg(test)]
 use crate::models::emergency::Emergency;
 #[cfg(test)]
@@ -22,6 +25,8 @@ use std::str;
 pub struct Message {
     pub uid: Option<u32>,
     pub seq: Seq,
+    /// the time the server received the mail
+    pub internal_date: Option<DateTime<FixedOffset>>,
     pub header: Option<Vec<u8>>,
     pub text: Option<Vec<u8>>,
 }
@@ -45,10 +50,34 @@ impl Message {
         return Message {
             seq: fetch.message,
             uid: fetch.uid,
+            internal_date: fetch.internal_date(),
             header: header,
             text: text,
         };
     }
+
+    /// splits a raw mail (e.g. an `.eml` file) into its header and body
+    pub fn from_raw(raw: &[u8]) -> Self {
+        let (header, text) = split_part(raw);
+        return Message {
+            uid: None,
+            seq: 0,
+            internal_date: None,
+            header: Some(header.to_vec()),
+            text: Some(text.to_vec()),
+        };
+    }
+
+    /// a mail consisting of the alarm text only, without headers (e.g. a `.txt` file)
+    pub fn from_text(text: &[u8]) -> Self {
+        return Message {
+            uid: None,
+            seq: 0,
+            internal_date: None,
+            header: None,
+            text: Some(text.to_vec()),
+        };
+    }
 }
 
 // for encoding see: https://www.w3.org/Protocols/rfc1341/5_Content-Transfer-Encoding.html
@@ -83,6 +112,16 @@ pub fn mail_str_decode_unicode(str: &str) -> String {
         }
 
         prev_buffer.push(c);
+        if prev_buffer.len() == 3 {
+            // a single escaped ascii character, e.g. =3D for '='
+            if let Ok(byte) = u8::from_str_radix(&prev_buffer[1..3], 16) {
+                if byte.is_ascii() {
+                    new_str.push(byte as char);
+                    prev_buffer.clear();
+                    continue;
+                }
+            }
+        }
         if prev_buffer.len() == 6 {
             let hex_1 = &prev_buffer[1..3];
             let hex_2 = &prev_buffer[4..6];
@@ -144,6 +183,16 @@ fn test_mail_str_decode_unicode_simple() {
     assert_eq!("testa", mail_str_decode_unicode("test=\r\na"));
 }
 
+#[test]
+fn test_mail_str_decode_unicode_ascii() {
+    assert_eq!(mail_str_decode_unicode("a=3Db"), "a=b");
+    assert_eq!(mail_str_decode_unicode("a=3db=20c"), "a=b c");
+    assert_eq!(mail_str_decode_unicode("=3D=C3=A4=3D"), "=ä=");
+    assert_eq!(mail_str_decode_unicode("Tor 2=3DHof=\r\n1"), "Tor 2=Hof1");
+    // not an escape sequence, kept as is
+    assert_eq!(mail_str_decode_unicode("2=Hof"), "2=Hof");
+}
+
 #[test]
 fn test_mail_str_decode_unicode_full() {
     // testing with full mail:
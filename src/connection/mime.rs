@@ -0,0 +1,520 @@
+use log::{debug, trace, warn};
+
+use super::imap_multipart::html_to_text;
+
+/// nested multiparts deeper than this are ignored (protects against malicious mails)
+pub const MAX_MULTIPART_DEPTH: usize = 8;
+
+/// Windows-1252 characters for the bytes 0x80 - 0x9F, which are control characters in ISO-8859-1.
+/// Undefined bytes are mapped to the control character of the same value.
+const WINDOWS_1252_HIGH: [char; 32] = [
+    '€', '\u{81}', '‚', 'ƒ', '„', '…', '†', '‡', 'ˆ', '‰', 'Š', '‹', 'Œ', '\u{8D}', 'Ž', '\u{8F}',
+    '\u{90}', '‘', '’', '“', '”', '•', '–', '—', '˜', '™', 'š', '›', 'œ', '\u{9D}', 'ž', 'Ÿ',
+];
+
+/// The Content-Transfer-Encoding of a mail or part.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransferEncoding {
+    /// 7bit, 8bit and binary, i.e. the body is not encoded
+    Identity,
+    QuotedPrintable,
+    Base64,
+}
+
+impl TransferEncoding {
+    pub fn parse(value: &str) -> Self {
+        return match value.trim().to_ascii_lowercase().as_str() {
+            "quoted-printable" => TransferEncoding::QuotedPrintable,
+            "base64" => TransferEncoding::Base64,
+            "7bit" | "8bit" | "binary" => TransferEncoding::Identity,
+            other => {
+                warn!(
+                    "unknown content transfer encoding {}, using the body as is",
+                    other
+                );
+                TransferEncoding::Identity
+            }
+        };
+    }
+
+    pub fn decode(&self, body: &[u8]) -> Vec<u8> {
+        return match self {
+            TransferEncoding::Identity => body.to_vec(),
+            TransferEncoding::QuotedPrintable => decode_quoted_printable(body),
+            TransferEncoding::Base64 => decode_base64(body).unwrap_or_else(|| {
+                warn!("invalid base64 body, using it as is");
+                body.to_vec()
+            }),
+        };
+    }
+}
+
+/// A parsed Content-Type header, e.g. `text/plain; charset="utf-8"`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContentType {
+    /// lower case, e.g. `text/plain`
+    pub mime_type: String,
+    /// parameter names are lower case, values are unquoted
+    pub parameters: Vec<(String, String)>,
+}
+
+impl ContentType {
+    pub fn parse(value: &str) -> Self {
+        let mut segments = split_unquoted(value, ';').into_iter();
+        let mime_type = segments
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .to_ascii_lowercase();
+        let parameters = segments
+            .filter_map(|segment| {
+                let (name, value) = segment.split_once('=')?;
+                let value = value.trim();
+                let value = value
+                    .strip_prefix('"')
+                    .and_then(|v| v.strip_suffix('"'))
+                    .unwrap_or(value);
+                return Some((name.trim().to_ascii_lowercase(), value.to_string()));
+            })
+            .collect();
+        return ContentType {
+            mime_type,
+            parameters,
+        };
+    }
+
+    pub fn parameter(&self, name: &str) -> Option<&str> {
+        return self
+            .parameters
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str());
+    }
+}
+
+impl Default for ContentType {
+    /// the default of RFC 2045, if no Content-Type header is given
+    fn default() -> Self {
+        return ContentType {
+            mime_type: "text/plain".to_string(),
+            parameters: Vec::new(),
+        };
+    }
+}
+
+/// splits at `separator`, except within double quotes
+fn split_unquoted(value: &str, separator: char) -> Vec<String> {
+    let mut segments = vec![String::new()];
+    let mut quoted = false;
+    for c in value.chars() {
+        if c == '"' {
+            quoted = !quoted;
+        }
+        if c == separator && !quoted {
+            segments.push(String::new());
+            continue;
+        }
+        segments.last_mut().unwrap().push(c);
+    }
+    return segments;
+}
+
+/// Parses a header section into (lower case name, value) pairs, unfolding continuation lines.
+pub fn parse_headers(headers: &str) -> Vec<(String, String)> {
+    let mut parsed: Vec<(String, String)> = Vec::new();
+    for line in headers.lines() {
+        if line.starts_with([' ', '\t']) {
+            if let Some((_, value)) = parsed.last_mut() {
+                value.push(' ');
+                value.push_str(line.trim());
+            }
+            continue;
+        }
+        let Some((name, value)) = line.split_once(':') else {
+            continue;
+        };
+        parsed.push((name.trim().to_ascii_lowercase(), value.trim().to_string()));
+    }
+    return parsed;
+}
+
+/// the value of the first header named `name` (lower case)
+pub fn header_value<'a>(headers: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    return headers
+        .iter()
+        .find(|(n, _)| n == name)
+        .map(|(_, v)| v.as_str());
+}
+
+/// Decodes quoted-printable (RFC 2045): `=XX` escapes any byte, `=` at the line end is a soft line break.
+/// Invalid escape sequences are kept unchanged.
+pub fn decode_quoted_printable(body: &[u8]) -> Vec<u8> {
+    let mut decoded = Vec::with_capacity(body.len());
+    let mut i = 0;
+    while i < body.len() {
+        if body[i] != b'=' {
+            decoded.push(body[i]);
+            i += 1;
+            continue;
+        }
+        if body[i + 1..].starts_with(b"\r\n") {
+            i += 3;
+            continue;
+        }
+        if body[i + 1..].starts_with(b"\n") {
+            i += 2;
+            continue;
+        }
+        let hex = body
+            .get(i + 1..i + 3)
+            .and_then(|h| std::str::from_utf8(h).ok());
+        if let Some(byte) = hex.and_then(|h| u8::from_str_radix(h, 16).ok()) {
+            decoded.push(byte);
+            i += 3;
+        } else {
+            decoded.push(b'=');
+            i += 1;
+        }
+    }
+    return decoded;
+}
+
+/// Decodes base64 (RFC 2045), ignoring line breaks and other whitespace.
+///
+/// # return value
+/// None, if the body contains characters outside of the base64 alphabet.
+pub fn decode_base64(body: &[u8]) -> Option<Vec<u8>> {
+    fn value(c: u8) -> Option<u32> {
+        return match c {
+            b'A'..=b'Z' => Some((c - b'A') as u32),
+            b'a'..=b'z' => Some((c - b'a') as u32 + 26),
+            b'0'..=b'9' => Some((c - b'0') as u32 + 52),
+            b'+' => Some(62),
+            b'/' => Some(63),
+            _ => None,
+        };
+    }
+
+    let mut decoded = Vec::with_capacity(body.len() * 3 / 4);
+    let mut buffer: u32 = 0;
+    let mut bits = 0;
+    for &c in body {
+        if c.is_ascii_whitespace() {
+            continue;
+        }
+        if c == b'=' {
+            break; // padding, the remaining bits are discarded
+        }
+        buffer = (buffer << 6) | value(c)?;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            decoded.push((buffer >> bits) as u8);
+            buffer &= (1 << bits) - 1;
+        }
+    }
+    return Some(decoded);
+}
+
+/// Converts text in the given charset to UTF-8.
+///
+/// # description
+/// Supported are UTF-8, US-ASCII, ISO-8859-1, ISO-8859-15 and Windows-1252.
+/// Without a charset, UTF-8 is assumed if valid, Windows-1252 otherwise (as sent by old ELS systems).
+/// Unknown charsets are decoded as UTF-8, replacing invalid sequences.
+pub fn decode_charset(bytes: &[u8], charset: Option<&str>) -> String {
+    let latin1 = |b: &u8| *b as char;
+    let windows_1252 = |b: &u8| match b {
+        0x80..=0x9F => WINDOWS_1252_HIGH[(b - 0x80) as usize],
+        _ => *b as char,
+    };
+    let latin9 = |b: &u8| match b {
+        0xA4 => '€',
+        0xA6 => 'Š',
+        0xA8 => 'š',
+        0xB4 => 'Ž',
+        0xB8 => 'ž',
+        0xBC => 'Œ',
+        0xBD => 'œ',
+        0xBE => 'Ÿ',
+        _ => *b as char,
+    };
+
+    let Some(charset) = charset else {
+        return match String::from_utf8(bytes.to_vec()) {
+            Ok(text) => text,
+            Err(_) => bytes.iter().map(windows_1252).collect(),
+        };
+    };
+    return match charset.trim().to_ascii_lowercase().as_str() {
+        "utf-8" | "utf8" => String::from_utf8_lossy(bytes).to_string(),
+        "us-ascii" | "ascii" | "iso-8859-1" | "iso8859-1" | "latin1" | "latin-1" => {
+            bytes.iter().map(latin1).collect()
+        }
+        "windows-1252" | "cp1252" => bytes.iter().map(windows_1252).collect(),
+        "iso-8859-15" | "iso8859-15" | "latin9" => bytes.iter().map(latin9).collect(),
+        other => {
+            warn!("unsupported charset {}, decoding as utf-8", other);
+            String::from_utf8_lossy(bytes).to_string()
+        }
+    };
+}
+
+fn find_bytes(haystack: &[u8], needle: &[u8], from: usize) -> Option<usize> {
+    if needle.is_empty() || from > haystack.len() {
+        return None;
+    }
+    return haystack[from..]
+        .windows(needle.len())
+        .position(|w| w == needle)
+        .map(|p| p + from);
+}
+
+/// splits a part into its header section and body
+pub fn split_part(part: &[u8]) -> (&[u8], &[u8]) {
+    if part.starts_with(b"\r\n") {
+        return (&[], &part[2..]); // no headers
+    }
+    if part.starts_with(b"\n") {
+        return (&[], &part[1..]);
+    }
+    if let Some(end) = find_bytes(part, b"\r\n\r\n", 0) {
+        return (&part[..end], &part[end + 4..]);
+    }
+    if let Some(end) = find_bytes(part, b"\n\n", 0) {
+        return (&part[..end], &part[end + 2..]);
+    }
+    return (part, &[]);
+}
+
+/// Splits a multipart body at the delimiter lines (`--boundary`), the preamble and epilogue are dropped.
+/// An empty delimiter can't be split at, there are no parts then.
+pub fn split_multipart<'a>(body: &'a [u8], delimiter: &str) -> Vec<&'a [u8]> {
+    let delimiter = delimiter.as_bytes();
+    let mut parts = Vec::new();
+    let Some(mut start) = find_bytes(body, delimiter, 0) else {
+        return parts;
+    };
+    loop {
+        let after_delimiter = start + delimiter.len();
+        if body[after_delimiter..].starts_with(b"--") {
+            break; // close delimiter
+        }
+        let content_start = match find_bytes(body, b"\n", after_delimiter) {
+            Some(line_end) => line_end + 1,
+            None => break,
+        };
+        let Some(next) = find_bytes(body, delimiter, content_start) else {
+            // missing close delimiter, the rest of the body is the last part
+            parts.push(&body[content_start..]);
+            break;
+        };
+        // the line break before the delimiter belongs to the delimiter
+        let mut content_end = next;
+        if content_end > content_start && body[content_end - 1] == b'\n' {
+            content_end -= 1;
+            if content_end > content_start && body[content_end - 1] == b'\r' {
+                content_end -= 1;
+            }
+        }
+        parts.push(&body[content_start..content_end]);
+        start = next;
+    }
+    return parts;
+}
+
+/// the delimiter line of a multipart body (`--boundary`), None for an empty boundary
+pub fn multipart_delimiter(boundary: &str, body: &[u8]) -> Option<String> {
+    if boundary.is_empty() {
+        return None;
+    }
+    let delimiter = format!("--{}", boundary);
+    if find_bytes(body, delimiter.as_bytes(), 0).is_none() && body.starts_with(boundary.as_bytes())
+    {
+        // tolerate boundaries given including the leading dashes of the delimiter
+        return Some(boundary.to_string());
+    }
+    return Some(delimiter);
+}
+
+/// the delimiter of a multipart body without headers, i.e. its first line
+pub fn first_line_delimiter(body: &[u8]) -> Option<String> {
+    let line_end = find_bytes(body, b"\r\n", 0).or_else(|| find_bytes(body, b"\n", 0))?;
+    let delimiter = String::from_utf8_lossy(&body[..line_end]).to_string();
+    if !delimiter.starts_with("--") {
+        return None;
+    }
+    return Some(delimiter);
+}
+
+/// whether the text was found in a text/plain or a text/html part
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TextKind {
+    Plain,
+    Html,
+}
+
+fn find_text(
+    headers: &[(String, String)],
+    body: &[u8],
+    depth: usize,
+) -> Option<(TextKind, String)> {
+    let content_type = header_value(headers, "content-type")
+        .map(ContentType::parse)
+        .unwrap_or_default();
+    trace!("decoding part of type {}", content_type.mime_type);
+
+    let is_multipart = content_type.mime_type.starts_with("multipart/");
+    if is_multipart {
+        let delimiter = content_type
+            .parameter("boundary")
+            .and_then(|boundary| multipart_delimiter(boundary, body));
+        match delimiter {
+            Some(delimiter) => return find_text_multipart(body, &delimiter, depth),
+            // can't be split into parts, the body is read like a single text part
+            None => warn!("multipart without boundary, reading it as text"),
+        }
+    }
+
+    let is_attachment = header_value(headers, "content-disposition")
+        .is_some_and(|d| d.trim().to_ascii_lowercase().starts_with("attachment"));
+    let kind = match content_type.mime_type.as_str() {
+        _ if is_attachment => return None,
+        _ if is_multipart => TextKind::Plain,
+        "text/plain" => TextKind::Plain,
+        "text/html" => TextKind::Html,
+        _ => return None,
+    };
+
+    let encoding = header_value(headers, "content-transfer-encoding")
+        .map(TransferEncoding::parse)
+        .unwrap_or(TransferEncoding::Identity);
+    let bytes = encoding.decode(body);
+    let text = decode_charset(&bytes, content_type.parameter("charset"));
+    return Some((kind, text));
+}
+
+fn find_text_multipart(body: &[u8], delimiter: &str, depth: usize) -> Option<(TextKind, String)> {
+    if depth >= MAX_MULTIPART_DEPTH {
+        warn!("multipart nested too deep, ignoring it");
+        return None;
+    }
+
+    let mut html: Option<String> = None;
+    for part in split_multipart(body, delimiter) {
+        let (headers, part_body) = split_part(part);
+        let headers = parse_headers(&String::from_utf8_lossy(headers));
+        match find_text(&headers, part_body, depth + 1) {
+            Some((TextKind::Plain, text)) => return Some((TextKind::Plain, text)),
+            Some((TextKind::Html, text)) if html.is_none() => html = Some(text),
+            _ => {}
+        }
+    }
+    return html.map(|text| (TextKind::Html, text));
+}
+
+fn to_plain_text(found: Option<(TextKind, String)>) -> Option<String> {
+    return match found? {
+        (TextKind::Plain, text) => Some(text),
+        (TextKind::Html, html) => {
+            debug!("mail has no plain text part, using the html part instead");
+            Some(html_to_text(&html))
+        }
+    };
+}
+
+/// Decodes the text of a mail.
+///
+/// # description
+/// Transfer encodings (base64, quoted-printable) and charsets are decoded per part.
+/// In (nested) multiparts the first text/plain part is used, the first text/html part converted to text otherwise.
+/// Attachments are ignored.
+///
+/// # arguments
+/// * `headers` - the mail headers, at least Content-Type and Content-Transfer-Encoding
+/// * `body` - the raw mail body (BODY[TEXT])
+///
+/// # return value
+/// None, if the mail contains no text part.
+pub fn decode_text(headers: &str, body: &[u8]) -> Option<String> {
+    let headers = parse_headers(headers);
+    return to_plain_text(find_text(&headers, body, 0));
+}
+
+/// Like [decode_text] for a multipart body, whose boundary is unknown (i.e. the headers are missing).
+/// The first line of the body is used as delimiter.
+pub fn decode_multipart_text(body: &[u8]) -> Option<String> {
+    let delimiter = first_line_delimiter(body)?;
+    return to_plain_text(find_text_multipart(body, &delimiter, 0));
+}
+
+#[test]
+fn test_decode_quoted_printable() {
+    assert_eq!(decode_quoted_printable(b"G=C3=B6ttin"), "Göttin".as_bytes());
+    assert_eq!(decode_quoted_printable(b"a=3Db=\r\nc=\nd"), b"a=bcd");
+    assert_eq!(decode_quoted_printable(b"=E4=XX="), b"\xE4=XX=");
+}
+
+#[test]
+fn test_decode_base64() {
+    assert_eq!(
+        decode_base64(b"fn5PcnR+fkfDtnR0aW5+fg==").unwrap(),
+        "~~Ort~~Göttin~~".as_bytes()
+    );
+    assert_eq!(decode_base64(b"fn5P\r\ncnR+").unwrap(), b"~~Ort~");
+    assert!(decode_base64(b"fn5P*").is_none());
+}
+
+#[test]
+fn test_decode_charset() {
+    assert_eq!(decode_charset(b"G\xF6ttin", Some("ISO-8859-1")), "Göttin");
+    assert_eq!(
+        decode_charset(b"\x80 \x84Test\x93", Some("windows-1252")),
+        "€ „Test“"
+    );
+    assert_eq!(decode_charset(b"\xA4", Some("iso-8859-15")), "€");
+    assert_eq!(decode_charset("Göttin".as_bytes(), None), "Göttin");
+    assert_eq!(decode_charset(b"G\xF6ttin", None), "Göttin");
+}
+
+#[test]
+fn test_content_type() {
+    let content_type = ContentType::parse("multipart/Alternative; boundary=\"a;b\"; charset=utf-8");
+    assert_eq!(content_type.mime_type, "multipart/alternative");
+    assert_eq!(content_type.parameter("boundary"), Some("a;b"));
+    assert_eq!(content_type.parameter("charset"), Some("utf-8"));
+    assert_eq!(ContentType::parse("").parameter("charset"), None);
+}
+
+#[test]
+fn test_decode_text_nested_multipart() {
+    const HEADERS: &str = "Content-Type: multipart/mixed;\r\n boundary=\"outer\"\r\n";
+    const BODY: &str = "preamble\r\n--outer\r\nContent-Type: multipart/alternative; boundary=inner\r\n\r\n--inner\r\nContent-Type: text/html; charset=utf-8\r\n\r\n<p>html</p>\r\n--inner\r\nContent-Type: text/plain; charset=ISO-8859-1\r\nContent-Transfer-Encoding: base64\r\n\r\nfn5PcnR+fkf2dHRpbn5+\r\n--inner--\r\n\r\n--outer\r\nContent-Type: application/pdf\r\nContent-Disposition: attachment\r\n\r\n%PDF\r\n--outer--\r\n";
+    assert_eq!(
+        decode_text(HEADERS, BODY.as_bytes()).unwrap(),
+        "~~Ort~~Göttin~~"
+    );
+}
+
+#[test]
+fn test_decode_text_single_part() {
+    const HEADERS: &str = "Content-Type: text/plain; charset=windows-1252\r\nContent-Transfer-Encoding: quoted-printable\r\n";
+    assert_eq!(
+        decode_text(HEADERS, b"~~Strasse~~G=F6risgr=E4ben~~").unwrap(),
+        "~~Strasse~~Görisgräben~~"
+    );
+    assert!(decode_text("Content-Type: image/png", b"PNG").is_none());
+}
+
+#[test]
+fn test_decode_text_empty_boundary() {
+    // an empty boundary can't be split at, the body is read as text
+    const HEADERS: &str = "Content-Type: multipart/mixed; boundary=\"\"\r\n";
+    assert_eq!(
+        decode_text(HEADERS, b"~~Ort~~Teltow~~").unwrap(),
+        "~~Ort~~Teltow~~"
+    );
+    assert_eq!(multipart_delimiter("", b"~~Ort~~Teltow~~"), None);
+    assert!(split_multipart(b"~~Ort~~Teltow~~", "").is_empty());
+    assert_eq!(find_bytes(b"abc", b"", 0), None);
+}
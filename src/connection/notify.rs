@@ -0,0 +1,12 @@
+use std::process::Command;
+
+use log::{error, info, warn};
+
+/// runs a notification command (e.g. a script sending an sms or mail to the admin) with the message as argument
+pub fn run_notify_command(command: &str, message: &str) {
+    match Command::new(command).arg(message).status() {
+        Ok(status) if status.success() => info!("notification sent: {}", message),
+        Ok(status) => warn!("notification command failed: {}", status),
+        Err(e) => error!("couldn't run notification command {}: {}", command, e),
+    }
+}
@@ -0,0 +1,501 @@
+use std::{
+    fs,
+    io::{Read, Write},
+    net::{IpAddr, TcpStream},
+    path::Path,
+    time::Duration,
+};
+
+use imap::Authenticator;
+use log::{debug, info};
+use native_tls::TlsConnector;
+use serde::Deserialize;
+
+use crate::config::config::AuthConfig;
+
+const HTTP_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// the answer of the token endpoint (RFC 6749, section 5.1)
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    /// only set, if the provider rotates refresh tokens
+    refresh_token: Option<String>,
+}
+
+/// the error answer of the token endpoint (RFC 6749, section 5.2)
+#[derive(Debug, Deserialize)]
+struct TokenError {
+    error: String,
+    error_description: Option<String>,
+}
+
+/// SASL XOAUTH2 as used by Gmail and Microsoft 365
+pub struct XOAuth2 {
+    pub user: String,
+    pub access_token: String,
+}
+
+impl Authenticator for XOAuth2 {
+    type Response = String;
+
+    fn process(&self, challenge: &[u8]) -> Self::Response {
+        if !challenge.is_empty() {
+            // the server sends the error as challenge and expects an empty response
+            debug!("xoauth2 error: {}", String::from_utf8_lossy(challenge));
+            return String::new();
+        }
+        return format!(
+            "user={}\x01auth=Bearer {}\x01\x01",
+            self.user, self.access_token
+        );
+    }
+}
+
+/// SASL OAUTHBEARER (RFC 7628)
+pub struct OAuthBearer {
+    pub user: String,
+    pub host: String,
+    pub port: u16,
+    pub access_token: String,
+}
+
+impl Authenticator for OAuthBearer {
+    type Response = String;
+
+    fn process(&self, challenge: &[u8]) -> Self::Response {
+        if !challenge.is_empty() {
+            // the server sends the error as challenge, the client must answer with a single %x01
+            debug!("oauthbearer error: {}", String::from_utf8_lossy(challenge));
+            return "\x01".to_string();
+        }
+        return format!(
+            "n,a={},\x01host={}\x01port={}\x01auth=Bearer {}\x01\x01",
+            self.user, self.host, self.port, self.access_token
+        );
+    }
+}
+
+/// Requests a new access token from the token endpoint using the stored refresh token.
+///
+/// # description
+/// If the provider issues a new refresh token, it replaces the stored one, as the old one may
+/// be invalidated by the provider.
+pub fn refresh_access_token(auth: &AuthConfig) -> Result<String, String> {
+    let endpoint = auth
+        .token_endpoint
+        .as_ref()
+        .ok_or("no oauth token endpoint configured")?;
+    let client_id = auth
+        .client_id
+        .as_ref()
+        .ok_or("no oauth client id configured")?;
+    let refresh_token = fs::read_to_string(&auth.refresh_token_file).map_err(|e| {
+        format!(
+            "couldn't read refresh token from {}: {}",
+            auth.refresh_token_file, e
+        )
+    })?;
+
+    let mut params = vec![
+        ("grant_type", "refresh_token"),
+        ("refresh_token", refresh_token.trim()),
+        ("client_id", client_id.as_str()),
+    ];
+    if let Some(secret) = &auth.client_secret {
+        params.push(("client_secret", secret.as_str()));
+    }
+    if let Some(scope) = &auth.scope {
+        params.push(("scope", scope.as_str()));
+    }
+
+    debug!("requesting access token from {}", endpoint);
+    let (status, body) = post_form(endpoint, &params)?;
+    if !(200..300).contains(&status) {
+        let reason = match serde_json::from_str::<TokenError>(&body) {
+            Ok(e) => format!("{} {}", e.error, e.error_description.unwrap_or_default()),
+            Err(_) => body,
+        };
+        return Err(format!(
+            "token endpoint answered with {}: {}",
+            status, reason
+        ));
+    }
+    let token: TokenResponse =
+        serde_json::from_str(&body).map_err(|e| format!("couldn't parse token response: {}", e))?;
+
+    if let Some(new_refresh_token) = token.refresh_token {
+        if new_refresh_token != refresh_token.trim() {
+            // written atomically, a lost refresh token needs a new interactive login
+            let path = Path::new(&auth.refresh_token_file);
+            let tmp_path = path.with_extension("tmp");
+            fs::write(&tmp_path, new_refresh_token)
+                .and_then(|_| fs::rename(&tmp_path, path))
+                .map_err(|e| format!("couldn't store new refresh token: {}", e))?;
+            info!("stored new refresh token");
+        }
+    }
+    return Ok(token.access_token);
+}
+
+/// `application/x-www-form-urlencoded` encoding of a single value
+fn form_encode(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for b in value.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                encoded.push(b as char)
+            }
+            b' ' => encoded.push('+'),
+            _ => encoded.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    return encoded;
+}
+
+/// whether the host is this machine (`localhost`, `127.0.0.1` or `::1`)
+fn is_loopback(host: &str) -> bool {
+    return host.eq_ignore_ascii_case("localhost")
+        || host.parse::<IpAddr>().is_ok_and(|ip| ip.is_loopback());
+}
+
+/// splits `http(s)://host[:port][/path]` into (tls, host, port, path)
+///
+/// # description
+/// plain http is only allowed for this machine, as the client secret and the tokens are sent in the request.
+/// IPv6 hosts are written in brackets (`[::1]:8080`), the returned host is without them.
+fn parse_url(url: &str) -> Result<(bool, String, u16, String), String> {
+    let (tls, rest) = if let Some(rest) = url.strip_prefix("https://") {
+        (true, rest)
+    } else if let Some(rest) = url.strip_prefix("http://") {
+        (false, rest)
+    } else {
+        return Err(format!("unsupported url {}", url));
+    };
+    let (authority, path) = match rest.find('/') {
+        Some(i) => (&rest[..i], &rest[i..]),
+        None => (rest, "/"),
+    };
+    let (host, port) = match authority.strip_prefix('[') {
+        // the port of an ipv6 host follows the closing bracket
+        Some(rest) => {
+            let (host, port) = rest
+                .split_once(']')
+                .ok_or_else(|| format!("invalid host in url {}", url))?;
+            if port.is_empty() {
+                (host, None)
+            } else {
+                let port = port
+                    .strip_prefix(':')
+                    .ok_or_else(|| format!("invalid port in url {}", url))?;
+                (host, Some(port))
+            }
+        }
+        None => match authority.rsplit_once(':') {
+            Some((host, port)) => (host, Some(port)),
+            None => (authority, None),
+        },
+    };
+    let port = match port {
+        Some(port) => port
+            .parse::<u16>()
+            .map_err(|_| format!("invalid port in url {}", url))?,
+        None => {
+            if tls {
+                443
+            } else {
+                80
+            }
+        }
+    };
+    if !tls && !is_loopback(host) {
+        return Err(format!("refusing to send tokens unencrypted to {}", url));
+    }
+    return Ok((tls, host.to_string(), port, path.to_string()));
+}
+
+/// decodes a `Transfer-Encoding: chunked` body
+fn decode_chunked(body: &[u8]) -> Result<Vec<u8>, String> {
+    let mut decoded = Vec::new();
+    let mut rest = body;
+    loop {
+        let line_end = rest
+            .windows(2)
+            .position(|w| w == b"\r\n")
+            .ok_or("incomplete chunk")?;
+        let size = String::from_utf8_lossy(&rest[..line_end]);
+        let size = size.split(';').next().unwrap_or_default().trim();
+        let size = usize::from_str_radix(size, 16).map_err(|_| "invalid chunk size")?;
+        rest = &rest[line_end + 2..];
+        if size == 0 {
+            return Ok(decoded);
+        }
+        if rest.len() < size {
+            return Err("incomplete chunk".to_string());
+        }
+        decoded.extend_from_slice(&rest[..size]);
+        rest = rest.get(size + 2..).unwrap_or_default();
+    }
+}
+
+/// sends a form as HTTP/1.1 POST request and returns the status code and body of the answer
+fn post_form(url: &str, params: &[(&str, &str)]) -> Result<(u16, String), String> {
+    let (tls, host, port, path) = parse_url(url)?;
+    // ipv6 hosts keep their brackets in the header
+    let host_header = if host.contains(':') {
+        format!("[{}]", host)
+    } else {
+        host.clone()
+    };
+    let body = params
+        .iter()
+        .map(|(k, v)| format!("{}={}", form_encode(k), form_encode(v)))
+        .collect::<Vec<_>>()
+        .join("&");
+    let request = format!(
+        "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/x-www-form-urlencoded\r\n\
+         Content-Length: {}\r\nAccept: application/json\r\nConnection: close\r\n\r\n{}",
+        path,
+        host_header,
+        body.len(),
+        body
+    );
+
+    let stream = TcpStream::connect((host.as_str(), port))
+        .map_err(|e| format!("couldn't connect to {}: {}", url, e))?;
+    stream
+        .set_read_timeout(Some(HTTP_TIMEOUT))
+        .map_err(|e| e.to_string())?;
+    let mut response = Vec::new();
+    let res = if tls {
+        let connector = TlsConnector::new().map_err(|e| e.to_string())?;
+        let mut stream = connector
+            .connect(&host, stream)
+            .map_err(|e| format!("tls handshake with {} failed: {}", host, e))?;
+        stream
+            .write_all(request.as_bytes())
+            .and_then(|_| stream.read_to_end(&mut response))
+    } else {
+        let mut stream = stream;
+        stream
+            .write_all(request.as_bytes())
+            .and_then(|_| stream.read_to_end(&mut response))
+    };
+    res.map_err(|e| format!("request to {} failed: {}", url, e))?;
+
+    let header_end = response
+        .windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .ok_or("invalid http response")?;
+    let head = String::from_utf8_lossy(&response[..header_end]).to_ascii_lowercase();
+    let status = head
+        .split_whitespace()
+        .nth(1)
+        .and_then(|s| s.parse::<u16>().ok())
+        .ok_or("invalid http status line")?;
+    let body = &response[header_end + 4..];
+    let body = if head.contains("transfer-encoding: chunked") {
+        decode_chunked(body)?
+    } else {
+        body.to_vec()
+    };
+    return Ok((status, String::from_utf8_lossy(&body).to_string()));
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        env, fs,
+        io::{Read, Write},
+        net::TcpListener,
+        thread,
+    };
+
+    use imap::Authenticator;
+
+    use super::{
+        decode_chunked, form_encode, parse_url, refresh_access_token, OAuthBearer, XOAuth2,
+    };
+    use crate::config::config::{AuthConfig, AuthMethod};
+
+    /// answers a single token request, returns the received request
+    fn mock_token_server(
+        status: &'static str,
+        body: &'static str,
+    ) -> (String, thread::JoinHandle<String>) {
+        return mock_token_server_at("127.0.0.1:0", status, body);
+    }
+
+    /// like [mock_token_server], listening on the given address
+    fn mock_token_server_at(
+        addr: &str,
+        status: &'static str,
+        body: &'static str,
+    ) -> (String, thread::JoinHandle<String>) {
+        let listener = TcpListener::bind(addr).unwrap();
+        let url = format!("http://{}/oauth2/token", listener.local_addr().unwrap());
+        let handle = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0u8; 1024];
+            // read until the body is complete (the connection stays open for the answer)
+            loop {
+                let n = stream.read(&mut buf).unwrap();
+                request.extend_from_slice(&buf[..n]);
+                let text = String::from_utf8_lossy(&request);
+                let Some((head, body)) = text.split_once("\r\n\r\n") else {
+                    continue;
+                };
+                let length = head
+                    .lines()
+                    .find_map(|l| l.strip_prefix("Content-Length: "))
+                    .and_then(|l| l.parse::<usize>().ok())
+                    .unwrap_or(0);
+                if n == 0 || body.len() >= length {
+                    break;
+                }
+            }
+            let response = format!(
+                "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+                status,
+                body.len(),
+                body
+            );
+            stream.write_all(response.as_bytes()).unwrap();
+            return String::from_utf8(request).unwrap();
+        });
+        return (url, handle);
+    }
+
+    fn auth_config(url: String, token_file: &str) -> AuthConfig {
+        return AuthConfig {
+            method: AuthMethod::XOAuth2,
+            token_endpoint: Some(url),
+            client_id: Some("client".to_string()),
+            client_secret: Some("s3cret/+".to_string()),
+            scope: None,
+            refresh_token_file: env::temp_dir()
+                .join(token_file)
+                .to_string_lossy()
+                .to_string(),
+        };
+    }
+
+    #[test]
+    fn test_refresh_access_token() {
+        let (url, server) = mock_token_server(
+            "200 OK",
+            r#"{"access_token":"access","token_type":"Bearer","expires_in":3599,"refresh_token":"new"}"#,
+        );
+        let auth = auth_config(url, "emergency_mail_test_refresh_token.txt");
+        fs::write(&auth.refresh_token_file, "old\n").unwrap();
+
+        assert_eq!(refresh_access_token(&auth), Ok("access".to_string()));
+        let request = server.join().unwrap();
+        assert!(request.starts_with("POST /oauth2/token HTTP/1.1\r\n"));
+        assert!(request.ends_with(
+            "grant_type=refresh_token&refresh_token=old&client_id=client&client_secret=s3cret%2F%2B"
+        ));
+        // the rotated refresh token is stored for the next refresh
+        assert_eq!(fs::read_to_string(&auth.refresh_token_file).unwrap(), "new");
+        fs::remove_file(&auth.refresh_token_file).unwrap();
+    }
+
+    #[test]
+    fn test_refresh_access_token_ipv6() {
+        let (url, server) = mock_token_server_at(
+            "[::1]:0",
+            "200 OK",
+            r#"{"access_token":"access","token_type":"Bearer","expires_in":3599}"#,
+        );
+        assert!(url.starts_with("http://[::1]:"), "{}", url);
+        let auth = auth_config(url, "emergency_mail_test_refresh_token_ipv6.txt");
+        fs::write(&auth.refresh_token_file, "old").unwrap();
+
+        assert_eq!(refresh_access_token(&auth), Ok("access".to_string()));
+        let request = server.join().unwrap();
+        assert!(request.contains("\r\nHost: [::1]\r\n"), "{}", request);
+        fs::remove_file(&auth.refresh_token_file).unwrap();
+    }
+
+    #[test]
+    fn test_refresh_access_token_error() {
+        let (url, server) = mock_token_server(
+            "400 Bad Request",
+            r#"{"error":"invalid_grant","error_description":"token expired"}"#,
+        );
+        let auth = auth_config(url, "emergency_mail_test_refresh_token_error.txt");
+        fs::write(&auth.refresh_token_file, "old").unwrap();
+
+        let err = refresh_access_token(&auth).unwrap_err();
+        server.join().unwrap();
+        assert!(
+            err.contains("400") && err.contains("invalid_grant"),
+            "{}",
+            err
+        );
+        assert_eq!(fs::read_to_string(&auth.refresh_token_file).unwrap(), "old");
+        fs::remove_file(&auth.refresh_token_file).unwrap();
+    }
+
+    #[test]
+    fn test_authenticators() {
+        let xoauth2 = XOAuth2 {
+            user: "alarm@example.com".to_string(),
+            access_token: "token".to_string(),
+        };
+        assert_eq!(
+            xoauth2.process(b""),
+            "user=alarm@example.com\x01auth=Bearer token\x01\x01"
+        );
+        assert_eq!(xoauth2.process(b"{\"status\":\"401\"}"), "");
+
+        let bearer = OAuthBearer {
+            user: "alarm@example.com".to_string(),
+            host: "imap.example.com".to_string(),
+            port: 993,
+            access_token: "token".to_string(),
+        };
+        assert_eq!(
+            bearer.process(b""),
+            "n,a=alarm@example.com,\x01host=imap.example.com\x01port=993\x01auth=Bearer token\x01\x01"
+        );
+        assert_eq!(bearer.process(b"{\"status\":\"invalid_token\"}"), "\x01");
+    }
+
+    #[test]
+    fn test_http_helpers() {
+        assert_eq!(form_encode("a b&c=d/ä"), "a+b%26c%3Dd%2F%C3%A4");
+        assert_eq!(
+            parse_url("https://login.example.com/tenant/token"),
+            Ok((
+                true,
+                "login.example.com".to_string(),
+                443,
+                "/tenant/token".to_string()
+            ))
+        );
+        assert_eq!(
+            parse_url("http://127.0.0.1:8080"),
+            Ok((false, "127.0.0.1".to_string(), 8080, "/".to_string()))
+        );
+        assert!(parse_url("ftp://example.com").is_err());
+        assert!(parse_url("http://login.example.com/tenant/token").is_err());
+        assert!(parse_url("http://localhost/token").is_ok());
+        assert_eq!(
+            parse_url("http://[::1]:8080/token"),
+            Ok((false, "::1".to_string(), 8080, "/token".to_string()))
+        );
+        assert_eq!(
+            parse_url("https://[2001:db8::1]/token"),
+            Ok((true, "2001:db8::1".to_string(), 443, "/token".to_string()))
+        );
+        assert!(parse_url("http://[::1]8080/token").is_err());
+        assert!(parse_url("http://[::1/token").is_err());
+        assert!(parse_url("http://[2001:db8::1]:8080/token").is_err());
+        assert_eq!(
+            decode_chunked(b"4\r\n{\"a\"\r\n3\r\n:1}\r\n0\r\n\r\n"),
+            Ok(b"{\"a\":1}".to_vec())
+        );
+    }
+}
@@ -0,0 +1,268 @@
+use crate::config::config::SenderFilterConfig;
+
+use super::{mime::header_value, notify::run_notify_command};
+
+/// extracts the address of the From header, e.g. `leitstelle@example.com` from `Leitstelle <leitstelle@example.com>`
+pub fn sender_address(headers: &[(String, String)]) -> Option<String> {
+    let from = header_value(headers, "from")?;
+    let address = match (from.rfind('<'), from.rfind('>')) {
+        (Some(start), Some(end)) if start < end => &from[start + 1..end],
+        _ => from,
+    };
+    let address = address.trim().to_ascii_lowercase();
+    return address.contains('@').then_some(address);
+}
+
+fn domain_of(address: &str) -> &str {
+    return address.rsplit_once('@').map(|(_, d)| d).unwrap_or(address);
+}
+
+/// whether the domain is the expected one or one of its subdomains
+fn domain_matches(domain: &str, expected: &str) -> bool {
+    return domain == expected || domain.ends_with(&format!(".{}", expected));
+}
+
+/// entries are either full addresses or domains (with or without a leading `@`), the address must be lowercase
+pub fn is_allowed(address: &str, allowed: &[String]) -> bool {
+    let domain = domain_of(address);
+    return allowed.iter().any(|entry| {
+        let entry = entry.trim().to_ascii_lowercase();
+        if let Some(entry_domain) = entry.strip_prefix('@') {
+            domain == entry_domain
+        } else if entry.contains('@') {
+            address == entry
+        } else {
+            domain == entry
+        }
+    });
+}
+
+/// the results (`method=result property=value`) of the topmost Authentication-Results header of the configured server
+///
+/// # description
+/// the sender can add Authentication-Results headers himself, so only those added by the configured
+/// server (authserv-id, RFC 8601) are used. The receiving server adds its header on top of the mail,
+/// so only the topmost one is trusted, a header with the same id further down may be forged.
+/// Without a configured server, no header is trusted.
+fn authentication_results<'a>(
+    headers: &'a [(String, String)],
+    config: &SenderFilterConfig,
+) -> Vec<&'a str> {
+    let Some(id) = &config.authserv_id else {
+        return Vec::new();
+    };
+    let results = headers
+        .iter()
+        .filter(|(name, _)| name == "authentication-results")
+        .filter_map(|(_, value)| value.split_once(';'))
+        .find(|(authserv_id, _)| authserv_id.trim().eq_ignore_ascii_case(id))
+        .map(|(_, results)| results);
+    return match results {
+        Some(results) => results.split(';').map(str::trim).collect(),
+        None => Vec::new(),
+    };
+}
+
+/// whether the property (e.g. `header.d=`) of a result is the domain of the sender (or a parent domain)
+fn is_aligned(result: &str, property: &str, sender_domain: &str) -> bool {
+    return result
+        .split_whitespace()
+        .filter_map(|value| value.strip_prefix(property))
+        .any(|value| domain_matches(sender_domain, domain_of(&value.to_ascii_lowercase())));
+}
+
+fn has_result(results: &[&str], method: &str, condition: impl Fn(&str) -> bool) -> bool {
+    let prefix = format!("{}=pass", method);
+    return results
+        .iter()
+        .any(|result| result.to_ascii_lowercase().starts_with(&prefix) && condition(result));
+}
+
+/// Checks, whether the mail was sent by an allowed sender, before it is parsed and printed.
+///
+/// # description
+/// * the address of the From header must be in the allowlist (if configured).
+/// * SPF and DKIM are only checked if required, using the Authentication-Results headers of the receiving server.
+///   The SPF checked envelope sender and the DKIM signature must be from the domain of the sender (or a parent domain).
+/// * one of the topmost Received headers (added by the own servers) containing the configured text
+///   (e.g. `from mail.leitstelle.de`) can be required.
+///
+/// # return value
+/// the reason for rejecting the mail
+pub fn check_sender(
+    headers: &[(String, String)],
+    config: &SenderFilterConfig,
+) -> Result<(), String> {
+    if !config.enabled() {
+        return Ok(());
+    }
+    let Some(sender) = sender_address(headers) else {
+        return Err("mail without sender address".to_string());
+    };
+    if !config.allowed_senders.is_empty() && !is_allowed(&sender, &config.allowed_senders) {
+        return Err(format!("sender {} is not allowed", sender));
+    }
+
+    let results = authentication_results(headers, config);
+    let sender_domain = domain_of(&sender);
+    let spf_aligned = |result: &str| is_aligned(result, "smtp.mailfrom=", sender_domain);
+    if config.require_spf && !has_result(&results, "spf", spf_aligned) {
+        return Err(format!("spf check of sender {} didn't pass", sender));
+    }
+    let dkim_aligned = |result: &str| is_aligned(result, "header.d=", sender_domain);
+    if config.require_dkim && !has_result(&results, "dkim", dkim_aligned) {
+        return Err(format!("no valid dkim signature for sender {}", sender));
+    }
+
+    if let Some(received) = &config.required_received {
+        let received = received.to_ascii_lowercase();
+        let found = headers
+            .iter()
+            .filter(|(name, _)| name == "received")
+            .take(config.received_hops)
+            .any(|(_, value)| value.to_ascii_lowercase().contains(&received));
+        if !found {
+            return Err(format!(
+                "mail of {} wasn't received from {}",
+                sender, received
+            ));
+        }
+    }
+    return Ok(());
+}
+
+/// informs the admin about a rejected mail by running the configured command with the reason as argument
+pub fn notify_admin(config: &SenderFilterConfig, reason: &str) {
+    if let Some(command) = &config.notify_command {
+        run_notify_command(command, reason);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check_sender, sender_address};
+    use crate::config::config::SenderFilterConfig;
+    use crate::connection::mime::parse_headers;
+
+    const HEADERS: &str = "Received: from mail.leitstelle.de (mail.leitstelle.de [192.0.2.1])\r\n\
+\tby mx.feuerwehr.de with ESMTPS\r\n\
+Authentication-Results: evil.example; spf=pass; dkim=pass header.d=leitstelle.de\r\n\
+Authentication-Results: mx.feuerwehr.de;\r\n\
+\tspf=pass smtp.mailfrom=leitstelle.de;\r\n\
+\tdkim=pass header.d=leitstelle.de header.s=mail\r\n\
+From: Leitstelle <Alarm@Leitstelle.de>\r\n\
+Subject: Alarm\r\n";
+
+    fn config() -> SenderFilterConfig {
+        return SenderFilterConfig {
+            allowed_senders: vec!["@leitstelle.de".to_string()],
+            ..Default::default()
+        };
+    }
+
+    #[test]
+    fn test_sender_address() {
+        let headers = parse_headers(HEADERS);
+        assert_eq!(
+            sender_address(&headers),
+            Some("alarm@leitstelle.de".to_string())
+        );
+        let headers = parse_headers("From: alarm@leitstelle.de\r\n");
+        assert_eq!(
+            sender_address(&headers),
+            Some("alarm@leitstelle.de".to_string())
+        );
+        assert_eq!(sender_address(&parse_headers("From: Leitstelle\r\n")), None);
+    }
+
+    #[test]
+    fn test_allowed_senders() {
+        let headers = parse_headers(HEADERS);
+        assert_eq!(
+            check_sender(&headers, &SenderFilterConfig::default()),
+            Ok(())
+        );
+        assert_eq!(check_sender(&headers, &config()), Ok(()));
+
+        let mut config = config();
+        config.allowed_senders = vec!["leitstelle.de".to_string()];
+        assert_eq!(check_sender(&headers, &config), Ok(()));
+        config.allowed_senders = vec!["alarm@leitstelle.de".to_string()];
+        assert_eq!(check_sender(&headers, &config), Ok(()));
+        config.allowed_senders = vec![
+            "other@leitstelle.de".to_string(),
+            "@example.com".to_string(),
+        ];
+        assert!(check_sender(&headers, &config).is_err());
+
+        let headers = parse_headers("Subject: Alarm\r\n");
+        assert!(check_sender(&headers, &config).is_err());
+    }
+
+    #[test]
+    fn test_authentication_results() {
+        let headers = parse_headers(HEADERS);
+        let mut config = config();
+        config.require_spf = true;
+        config.require_dkim = true;
+        config.authserv_id = Some("mx.feuerwehr.de".to_string());
+        assert_eq!(check_sender(&headers, &config), Ok(()));
+
+        // the sender can't forge the results of our server
+        let forged = HEADERS.replace("mx.feuerwehr.de;", "mx.other.de;");
+        assert!(check_sender(&parse_headers(&forged), &config).is_err());
+
+        // the signature must be from the domain of the sender
+        let foreign = HEADERS.replace(
+            "header.d=leitstelle.de header.s",
+            "header.d=example.com header.s",
+        );
+        assert!(check_sender(&parse_headers(&foreign), &config).is_err());
+        config.require_dkim = false;
+        assert_eq!(check_sender(&parse_headers(&foreign), &config), Ok(()));
+
+        let failed = HEADERS.replace("spf=pass smtp", "spf=softfail smtp");
+        assert!(check_sender(&parse_headers(&failed), &config).is_err());
+
+        // spf must be checked for the domain of the sender
+        let foreign = HEADERS.replace("smtp.mailfrom=leitstelle.de", "smtp.mailfrom=example.com");
+        assert!(check_sender(&parse_headers(&foreign), &config).is_err());
+        let bounce = HEADERS.replace(
+            "smtp.mailfrom=leitstelle.de",
+            "smtp.mailfrom=bounce@leitstelle.de",
+        );
+        assert_eq!(check_sender(&parse_headers(&bounce), &config), Ok(()));
+
+        // only the topmost header of our server is trusted
+        let forged_below = failed.replace(
+            "Subject: Alarm",
+            "Authentication-Results: mx.feuerwehr.de; spf=pass smtp.mailfrom=leitstelle.de\r\nSubject: Alarm",
+        );
+        assert!(check_sender(&parse_headers(&forged_below), &config).is_err());
+
+        // nothing is trusted without the id of our server
+        config.authserv_id = None;
+        assert!(check_sender(&headers, &config).is_err());
+    }
+
+    #[test]
+    fn test_required_received() {
+        let headers = parse_headers(HEADERS);
+        let mut config = config();
+        config.required_received = Some("from mail.leitstelle.de".to_string());
+        assert_eq!(check_sender(&headers, &config), Ok(()));
+        config.required_received = Some("from relay.example.com".to_string());
+        assert!(check_sender(&headers, &config).is_err());
+
+        // the sender can add Received headers below those of our servers
+        let forged = format!(
+            "Received: from relay.example.com by mx.feuerwehr.de\r\n{}",
+            HEADERS.replace("by mx.feuerwehr.de", "by relay.example.com")
+        );
+        let headers = parse_headers(&forged);
+        config.required_received = Some("from mail.leitstelle.de".to_string());
+        assert!(check_sender(&headers, &config).is_err());
+        config.received_hops = 2;
+        assert_eq!(check_sender(&headers, &config), Ok(()));
+    }
+}
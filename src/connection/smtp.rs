@@ -0,0 +1,727 @@
+use std::{
+    fs,
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream},
+    sync::{atomic::AtomicUsize, mpsc::Sender, Arc},
+    thread,
+    time::Duration,
+};
+
+use chrono::Local;
+use log::{debug, error, info, trace, warn};
+use native_tls::{Identity, TlsAcceptor, TlsStream};
+
+use crate::config::config::{SmtpConfig, SmtpProtocol, SmtpTlsConfig};
+
+use super::{
+    imap_multipart::get_message_content,
+    message::Message,
+    sender_filter::is_allowed,
+    sources::{MailOutcome, QueuedMail, SessionSlot},
+};
+
+/// clients, that don't send anything for this long, are disconnected (RFC 5321 4.5.3.2)
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+/// the maximum length of a command line including CRLF (RFC 5321 4.5.3.1.4 allows 512)
+const MAX_COMMAND_LENGTH: u64 = 1024;
+/// longer lines of a mail are read in several parts
+const MAX_DATA_CHUNK: u64 = 64 * 1024;
+const MAX_RECIPIENTS: usize = 100;
+
+/// The connection to a client, encrypted after STARTTLS.
+enum Stream {
+    Plain(TcpStream),
+    Tls(TlsStream<TcpStream>),
+}
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        return match self {
+            Stream::Plain(stream) => stream.read(buf),
+            Stream::Tls(stream) => stream.read(buf),
+        };
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        return match self {
+            Stream::Plain(stream) => stream.write(buf),
+            Stream::Tls(stream) => stream.write(buf),
+        };
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        return match self {
+            Stream::Plain(stream) => stream.flush(),
+            Stream::Tls(stream) => stream.flush(),
+        };
+    }
+}
+
+/// what the session does after a command was handled
+enum Next {
+    Continue,
+    StartTls,
+    Quit,
+}
+
+/// reads a line including the line ending, at most `limit` bytes. None if the client closed the connection.
+fn read_line(reader: &mut BufReader<Stream>, limit: u64) -> io::Result<Option<Vec<u8>>> {
+    let mut line = Vec::new();
+    reader.by_ref().take(limit).read_until(b'\n', &mut line)?;
+    return Ok((!line.is_empty()).then_some(line));
+}
+
+fn reply(reader: &mut BufReader<Stream>, text: &str) -> io::Result<()> {
+    trace!("smtp reply: {}", text);
+    let stream = reader.get_mut();
+    stream.write_all(text.as_bytes())?;
+    stream.write_all(b"\r\n")?;
+    return stream.flush();
+}
+
+/// the value of `keyword` (case insensitive), e.g. `FROM:` in `MAIL FROM:<a@b.de> SIZE=1000`
+fn strip_keyword<'a>(text: &'a str, keyword: &str) -> Option<&'a str> {
+    let prefix = text.get(..keyword.len())?;
+    return prefix
+        .eq_ignore_ascii_case(keyword)
+        .then(|| &text[keyword.len()..]);
+}
+
+/// Parses the argument of MAIL and RCPT.
+///
+/// # return value
+/// the lowercase address (empty for the null sender `<>`) and the parameters following it
+fn parse_path<'a>(args: &'a str, keyword: &str) -> Option<(String, &'a str)> {
+    let rest = strip_keyword(args, keyword)?
+        .trim_start()
+        .strip_prefix('<')?;
+    let (path, params) = rest.split_once('>')?;
+    // source routes (`@relay:user@domain`) are obsolete, only the mailbox is used
+    let address = path.rsplit(':').next().unwrap_or(path);
+    return Some((address.trim().to_ascii_lowercase(), params.trim()));
+}
+
+/// the size announced with the SIZE parameter of MAIL (RFC 1870)
+fn announced_size(params: &str) -> Option<usize> {
+    return params
+        .split_whitespace()
+        .find_map(|param| strip_keyword(param, "SIZE="))
+        .and_then(|size| size.parse().ok());
+}
+
+/// A connection of a client, delivering one or more mails.
+struct Session {
+    smtp: Arc<SmtpConfig>,
+    acceptor: Option<TlsAcceptor>,
+    queue: Sender<QueuedMail>,
+    peer: String,
+    helo: Option<String>,
+    tls: bool,
+    sender: Option<String>,
+    recipients: Vec<String>,
+}
+
+impl Session {
+    fn reset(&mut self) {
+        self.sender = None;
+        self.recipients.clear();
+    }
+
+    fn greeting_command(&self) -> &'static str {
+        return match self.smtp.protocol {
+            SmtpProtocol::Smtp => "EHLO",
+            SmtpProtocol::Lmtp => "LHLO",
+        };
+    }
+
+    /// the Received header added to each mail, so the sender filter can check the delivering host
+    fn received_header(&self) -> String {
+        let protocol = match (self.smtp.protocol, self.tls) {
+            (SmtpProtocol::Smtp, false) => "ESMTP",
+            (SmtpProtocol::Smtp, true) => "ESMTPS",
+            (SmtpProtocol::Lmtp, false) => "LMTP",
+            (SmtpProtocol::Lmtp, true) => "LMTPS",
+        };
+        return format!(
+            "Received: from {} ({})\r\n\tby {} with {}; {}\r\n",
+            self.helo.as_deref().unwrap_or("unknown"),
+            self.peer,
+            self.smtp.hostname,
+            protocol,
+            Local::now().to_rfc2822()
+        );
+    }
+
+    fn hello(&mut self, reader: &mut BufReader<Stream>, args: &str) -> io::Result<()> {
+        if args.is_empty() {
+            return reply(reader, "501 5.5.4 hostname required");
+        }
+        self.helo = Some(args.to_string());
+        self.reset();
+        let mut lines = vec![format!("{} Hallo {}", self.smtp.hostname, args)];
+        lines.push(format!("SIZE {}", self.smtp.max_size));
+        lines.push("8BITMIME".to_string());
+        lines.push("ENHANCEDSTATUSCODES".to_string());
+        if self.acceptor.is_some() && !self.tls {
+            lines.push("STARTTLS".to_string());
+        }
+        let last = lines.len() - 1;
+        let lines: Vec<String> = lines
+            .iter()
+            .enumerate()
+            .map(|(i, line)| format!("250{}{}", if i == last { ' ' } else { '-' }, line))
+            .collect();
+        return reply(reader, &lines.join("\r\n"));
+    }
+
+    fn mail(&mut self, reader: &mut BufReader<Stream>, args: &str) -> io::Result<()> {
+        let tls_required = self.smtp.tls.as_ref().is_some_and(|tls| tls.required);
+        if self.helo.is_none() {
+            return reply(
+                reader,
+                &format!("503 5.5.1 send {} first", self.greeting_command()),
+            );
+        }
+        if tls_required && !self.tls {
+            return reply(reader, "530 5.7.0 must issue a STARTTLS command first");
+        }
+        if self.sender.is_some() {
+            return reply(reader, "503 5.5.1 sender already specified");
+        }
+        let Some((sender, params)) = parse_path(args, "FROM:") else {
+            return reply(reader, "501 5.5.4 syntax: MAIL FROM:<address>");
+        };
+        let allowed = &self.smtp.allowed_senders;
+        if !allowed.is_empty() && !is_allowed(&sender, allowed) {
+            warn!("smtp: rejected sender <{}> of {}", sender, self.peer);
+            return reply(reader, "550 5.7.1 sender not allowed");
+        }
+        if announced_size(params).is_some_and(|size| size > self.smtp.max_size) {
+            return reply(reader, "552 5.3.4 message too big");
+        }
+        self.sender = Some(sender);
+        return reply(reader, "250 2.1.0 OK");
+    }
+
+    fn recipient(&mut self, reader: &mut BufReader<Stream>, args: &str) -> io::Result<()> {
+        if self.sender.is_none() {
+            return reply(reader, "503 5.5.1 send MAIL first");
+        }
+        let recipient = match parse_path(args, "TO:") {
+            Some((recipient, _)) if !recipient.is_empty() => recipient,
+            _ => return reply(reader, "501 5.5.4 syntax: RCPT TO:<address>"),
+        };
+        let allowed = &self.smtp.allowed_recipients;
+        if !allowed.is_empty() && !is_allowed(&recipient, allowed) {
+            warn!("smtp: rejected recipient <{}> of {}", recipient, self.peer);
+            return reply(reader, "550 5.1.1 recipient not accepted");
+        }
+        if self.recipients.len() >= MAX_RECIPIENTS {
+            return reply(reader, "452 4.5.3 too many recipients");
+        }
+        self.recipients.push(recipient);
+        return reply(reader, "250 2.1.5 OK");
+    }
+
+    /// Reads the mail after DATA until the terminating `.` line, undoing the dot stuffing.
+    ///
+    /// # return value
+    /// None, if the mail exceeds max_size. It is read completely anyway, to stay in sync with the client.
+    fn read_data(&self, reader: &mut BufReader<Stream>) -> io::Result<Option<Vec<u8>>> {
+        let mut data = Vec::new();
+        let mut too_large = false;
+        let mut line_start = true;
+        loop {
+            let Some(chunk) = read_line(reader, MAX_DATA_CHUNK)? else {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            };
+            let mut chunk = chunk.as_slice();
+            if line_start {
+                if chunk == b".\r\n" || chunk == b".\n" {
+                    break;
+                }
+                chunk = chunk.strip_prefix(b".").unwrap_or(chunk);
+            }
+            line_start = chunk.ends_with(b"\n");
+            if too_large || data.len() + chunk.len() > self.smtp.max_size {
+                too_large = true;
+                data = Vec::new();
+            } else {
+                data.extend_from_slice(chunk);
+            }
+        }
+        return Ok((!too_large).then_some(data));
+    }
+
+    /// queues the mail and waits for its outcome, so the client can retry, if it wasn't processed
+    fn deliver(&self, data: &[u8]) -> &'static str {
+        let Some(content) = get_message_content(Message::from_raw(data)) else {
+            return "554 5.6.0 mail couldn't be decoded";
+        };
+        let (mail, outcome) = QueuedMail::new(self.smtp.source_name(), content);
+        if self.queue.send(mail).is_err() {
+            return "421 4.3.0 shutting down";
+        }
+        return match outcome.recv() {
+            Ok(MailOutcome::Printed | MailOutcome::Duplicate) => "250 2.0.0 alarm received",
+            Ok(MailOutcome::Failed) => "554 5.6.0 alarm couldn't be parsed",
+            Ok(MailOutcome::Rejected) => "550 5.7.1 sender not allowed",
+            Err(_) => "451 4.3.0 mail wasn't processed, try again later",
+        };
+    }
+
+    fn data(&mut self, reader: &mut BufReader<Stream>, args: &str) -> io::Result<()> {
+        if !args.is_empty() {
+            return reply(reader, "501 5.5.4 no parameters allowed");
+        }
+        if self.recipients.is_empty() {
+            return reply(reader, "503 5.5.1 send RCPT first");
+        }
+        reply(reader, "354 end data with <CR><LF>.<CR><LF>")?;
+        let result = match self.read_data(reader)? {
+            Some(data) => {
+                let mut mail = self.received_header().into_bytes();
+                mail.extend_from_slice(&data);
+                info!(
+                    "received mail from <{}> via {} ({} bytes)",
+                    self.sender.as_deref().unwrap_or_default(),
+                    self.peer,
+                    mail.len()
+                );
+                self.deliver(&mail)
+            }
+            None => "552 5.3.4 message too big",
+        };
+        // LMTP replies for each recipient (RFC 2033 4.2), they all share the same outcome
+        let replies = match self.smtp.protocol {
+            SmtpProtocol::Smtp => 1,
+            SmtpProtocol::Lmtp => self.recipients.len(),
+        };
+        for _ in 0..replies {
+            reply(reader, result)?;
+        }
+        self.reset();
+        return Ok(());
+    }
+
+    fn command(&mut self, reader: &mut BufReader<Stream>, line: &str) -> io::Result<Next> {
+        let (verb, args) = line.split_once(' ').unwrap_or((line, ""));
+        let args = args.trim();
+        let verb = verb.to_ascii_uppercase();
+        match (verb.as_str(), self.smtp.protocol) {
+            ("EHLO" | "HELO", SmtpProtocol::Smtp) | ("LHLO", SmtpProtocol::Lmtp) => {
+                self.hello(reader, args)?
+            }
+            ("EHLO" | "HELO", SmtpProtocol::Lmtp) | ("LHLO", SmtpProtocol::Smtp) => {
+                let expected = self.greeting_command();
+                reply(reader, &format!("500 5.5.1 use {}", expected))?
+            }
+            ("STARTTLS", _) => {
+                if self.acceptor.is_none() || self.tls {
+                    reply(reader, "502 5.5.1 STARTTLS not available")?;
+                } else {
+                    reply(reader, "220 2.0.0 ready to start TLS")?;
+                    return Ok(Next::StartTls);
+                }
+            }
+            ("MAIL", _) => self.mail(reader, args)?,
+            ("RCPT", _) => self.recipient(reader, args)?,
+            ("DATA", _) => self.data(reader, args)?,
+            ("RSET", _) => {
+                self.reset();
+                reply(reader, "250 2.0.0 OK")?
+            }
+            ("NOOP", _) => reply(reader, "250 2.0.0 OK")?,
+            ("VRFY", _) => reply(reader, "252 2.5.0 cannot verify")?,
+            ("QUIT", _) => {
+                reply(reader, "221 2.0.0 bye")?;
+                return Ok(Next::Quit);
+            }
+            _ => reply(reader, "500 5.5.2 command not recognized")?,
+        }
+        return Ok(Next::Continue);
+    }
+
+    /// Handles the commands of the client until it quits or the connection fails.
+    ///
+    /// # description
+    /// after STARTTLS, the session starts over (RFC 3207 4.2). Commands the client sent before the
+    /// handshake are discarded, so they can't be injected into the encrypted session.
+    fn run(&mut self, stream: TcpStream) -> io::Result<()> {
+        stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+        stream.set_write_timeout(Some(CLIENT_TIMEOUT))?;
+        let mut reader = BufReader::new(Stream::Plain(stream));
+        let service = match self.smtp.protocol {
+            SmtpProtocol::Smtp => "ESMTP",
+            SmtpProtocol::Lmtp => "LMTP",
+        };
+        reply(
+            &mut reader,
+            &format!("220 {} {} emergency_mail", self.smtp.hostname, service),
+        )?;
+        loop {
+            let Some(line) = read_line(&mut reader, MAX_COMMAND_LENGTH)? else {
+                return Ok(()); // the client closed the connection
+            };
+            if !line.ends_with(b"\n") {
+                reply(&mut reader, "500 5.5.6 line too long")?;
+                return Ok(());
+            }
+            let line = String::from_utf8_lossy(&line);
+            let line = line.trim_end();
+            trace!("smtp command of {}: {}", self.peer, line);
+            match self.command(&mut reader, line)? {
+                Next::Continue => {}
+                Next::Quit => return Ok(()),
+                Next::StartTls => {
+                    let (Stream::Plain(stream), Some(acceptor)) =
+                        (reader.into_inner(), &self.acceptor)
+                    else {
+                        return Ok(());
+                    };
+                    let stream = acceptor
+                        .accept(stream)
+                        .map_err(|e| io::Error::other(e.to_string()))?;
+                    reader = BufReader::new(Stream::Tls(stream));
+                    self.tls = true;
+                    self.helo = None;
+                    self.reset();
+                }
+            }
+        }
+    }
+}
+
+fn tls_acceptor(tls: &SmtpTlsConfig) -> Result<TlsAcceptor, String> {
+    let certificate = fs::read(&tls.certificate)
+        .map_err(|e| format!("couldn't read certificate {}: {}", tls.certificate, e))?;
+    let key = fs::read(&tls.key).map_err(|e| format!("couldn't read key {}: {}", tls.key, e))?;
+    let identity = Identity::from_pkcs8(&certificate, &key)
+        .map_err(|e| format!("invalid certificate or key: {}", e))?;
+    return TlsAcceptor::new(identity).map_err(|e| format!("couldn't set up tls: {}", e));
+}
+
+/// accepts clients until the listener fails, each client is handled in its own thread, at most `max_sessions` at once
+fn serve(
+    listener: TcpListener,
+    smtp: SmtpConfig,
+    acceptor: Option<TlsAcceptor>,
+    queue: Sender<QueuedMail>,
+) {
+    let smtp = Arc::new(smtp);
+    let sessions = Arc::new(AtomicUsize::new(0));
+    for stream in listener.incoming() {
+        let mut stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                warn!("{}: couldn't accept client: {}", smtp.source_name(), e);
+                continue;
+            }
+        };
+        let peer = stream
+            .peer_addr()
+            .map(|addr| addr.to_string())
+            .unwrap_or_else(|_| "unknown".to_string());
+        debug!("{}: client {} connected", smtp.source_name(), peer);
+        let Some(slot) = SessionSlot::acquire(&sessions, smtp.max_sessions) else {
+            warn!(
+                "{}: too many clients, refusing {}",
+                smtp.source_name(),
+                peer
+            );
+            let _ = stream.write_all(b"421 4.3.2 too many connections, try again later\r\n");
+            continue;
+        };
+        let mut session = Session {
+            smtp: smtp.clone(),
+            acceptor: acceptor.clone(),
+            queue: queue.clone(),
+            peer,
+            helo: None,
+            tls: false,
+            sender: None,
+            recipients: Vec::new(),
+        };
+        let spawned = thread::Builder::new()
+            .name(format!("{} {}", smtp.source_name(), session.peer))
+            .spawn(move || {
+                let _slot = slot;
+                if let Err(e) = session.run(stream) {
+                    debug!("smtp session with {} ended: {}", session.peer, e);
+                }
+            });
+        if let Err(e) = spawned {
+            error!("couldn't start smtp session: {}", e);
+        }
+    }
+}
+
+/// Listens for mails delivered with SMTP or LMTP and queues them like received mails.
+///
+/// # description
+/// a mail is only acknowledged, after it was processed. If the processing failed unexpectedly, the
+/// client is asked to retry later, so no alarm gets lost. Unparsable alarms and rejected senders are
+/// rejected permanently, so the sender is notified with a bounce.
+pub fn watch_smtp(smtp: SmtpConfig, queue: Sender<QueuedMail>) {
+    let name = smtp.source_name();
+    let acceptor = match smtp.tls.as_ref().map(tls_acceptor).transpose() {
+        Ok(acceptor) => acceptor,
+        Err(e) => {
+            error!("{}: {}", name, e);
+            return;
+        }
+    };
+    let listener = match TcpListener::bind(&smtp.listen) {
+        Ok(listener) => listener,
+        Err(e) => {
+            error!("{}: couldn't listen on {}: {}", name, smtp.listen, e);
+            return;
+        }
+    };
+    info!("Bereit zum Empfangen der Alarmemails ({}).", name);
+    serve(listener, smtp, acceptor, queue);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufRead, BufReader, Read, Write},
+        net::{TcpListener, TcpStream},
+        sync::mpsc,
+        thread,
+        time::Duration,
+    };
+
+    use native_tls::{Certificate, TlsConnector};
+
+    use super::{parse_path, serve, tls_acceptor};
+    use crate::{
+        config::config::{SmtpConfig, SmtpProtocol},
+        connection::sources::{MailOutcome, QueuedMail},
+    };
+
+    const EMS: &str = include_str!("../../examples/emergency_simple.txt");
+    const CERT: &[u8] = include_bytes!("../../resources/test/localhost.pem");
+    const TLS: &str =
+        "tls:\n  certificate: resources/test/localhost.pem\n  key: resources/test/localhost.key";
+
+    struct Client<S: Read + Write = TcpStream> {
+        reader: BufReader<S>,
+    }
+
+    impl Client {
+        fn connect(smtp: SmtpConfig) -> (Client, mpsc::Receiver<QueuedMail>) {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let addr = listener.local_addr().unwrap();
+            let (queue, receiver) = mpsc::channel();
+            let acceptor = smtp.tls.as_ref().map(|tls| tls_acceptor(tls).unwrap());
+            thread::spawn(move || serve(listener, smtp, acceptor, queue));
+            let client = Client {
+                reader: BufReader::new(TcpStream::connect(addr).unwrap()),
+            };
+            return (client, receiver);
+        }
+
+        /// another client of the same server
+        fn connect_again(&self) -> Client {
+            let addr = self.reader.get_ref().peer_addr().unwrap();
+            return Client {
+                reader: BufReader::new(TcpStream::connect(addr).unwrap()),
+            };
+        }
+    }
+
+    impl<S: Read + Write> Client<S> {
+        /// the reply to the command, multiline replies are joined
+        fn command(&mut self, command: &str) -> String {
+            self.reader
+                .get_mut()
+                .write_all(format!("{}\r\n", command).as_bytes())
+                .unwrap();
+            return self.reply();
+        }
+
+        fn reply(&mut self) -> String {
+            let mut lines = Vec::new();
+            loop {
+                let mut line = String::new();
+                self.reader.read_line(&mut line).unwrap();
+                let last = line.as_bytes().get(3) != Some(&b'-');
+                lines.push(line.trim_end().to_string());
+                if last {
+                    return lines.join("\n");
+                }
+            }
+        }
+    }
+
+    fn config(yaml: &str) -> SmtpConfig {
+        return serde_yaml::from_str(yaml).unwrap();
+    }
+
+    /// answers the next queued mail with the outcome, returns the received text
+    fn process(
+        receiver: mpsc::Receiver<QueuedMail>,
+        outcome: MailOutcome,
+    ) -> thread::JoinHandle<String> {
+        return thread::spawn(move || {
+            let mail = receiver.recv().unwrap();
+            let text = mail.content.text.clone();
+            mail.finish(outcome);
+            text
+        });
+    }
+
+    #[test]
+    fn test_parse_path() {
+        assert_eq!(
+            parse_path("FROM:<Alarm@Leitstelle.de> SIZE=1000", "FROM:"),
+            Some(("alarm@leitstelle.de".to_string(), "SIZE=1000"))
+        );
+        assert_eq!(
+            parse_path("to: <@relay.de:alarm@ff.de>", "TO:"),
+            Some(("alarm@ff.de".to_string(), ""))
+        );
+        assert_eq!(parse_path("FROM:<>", "FROM:"), Some((String::new(), "")));
+        assert_eq!(parse_path("FROM:alarm@ff.de", "FROM:"), None);
+    }
+
+    #[test]
+    fn test_smtp_delivery() {
+        let (mut client, receiver) = Client::connect(config(
+            "listen: 127.0.0.1:0\nallowed_recipients: [\"@ff-teltow.de\"]\nallowed_senders: [\"@leitstelle.de\"]",
+        ));
+        assert!(client.reply().starts_with("220 "));
+        assert!(client
+            .command("MAIL FROM:<alarm@leitstelle.de>")
+            .starts_with("503"));
+        let ehlo = client.command("EHLO leitstelle.de");
+        assert!(ehlo.ends_with("250 ENHANCEDSTATUSCODES"));
+        assert!(!ehlo.contains("STARTTLS")); // no certificate configured
+        assert!(client.command("STARTTLS").starts_with("502"));
+
+        assert!(client
+            .command("MAIL FROM:<spam@example.com>")
+            .starts_with("550"));
+        assert!(client
+            .command("MAIL FROM:<alarm@leitstelle.de> SIZE=99999999")
+            .starts_with("552"));
+        assert!(client
+            .command("MAIL FROM:<alarm@leitstelle.de>")
+            .starts_with("250"));
+        assert!(client
+            .command("RCPT TO:<info@example.com>")
+            .starts_with("550"));
+        assert!(client
+            .command("RCPT TO:<alarm@ff-teltow.de>")
+            .starts_with("250"));
+        assert!(client.command("DATA").starts_with("354"));
+
+        let processed = process(receiver, MailOutcome::Printed);
+        let mail = format!(
+            "From: alarm@leitstelle.de\r\nSubject: Alarm\r\n\r\n{}\r\n.",
+            EMS.replace('\n', "\r\n")
+        );
+        assert!(client.command(&mail).starts_with("250 2.0.0"));
+        assert!(processed.join().unwrap().contains("322088295"));
+        assert!(client.command("QUIT").starts_with("221"));
+    }
+
+    #[test]
+    fn test_lmtp_delivery() {
+        let (mut client, receiver) = Client::connect(config("listen: 127.0.0.1:0\nprotocol: lmtp"));
+        assert!(client.reply().starts_with("220 "));
+        assert!(client.command("EHLO localhost").starts_with("500"));
+        assert!(client.command("LHLO localhost").starts_with("250"));
+        assert!(client.command("MAIL FROM:<>").starts_with("250"));
+        assert!(client
+            .command("RCPT TO:<alarm1@localhost>")
+            .starts_with("250"));
+        assert!(client
+            .command("RCPT TO:<alarm2@localhost>")
+            .starts_with("250"));
+        assert!(client.command("DATA").starts_with("354"));
+
+        let processed = process(receiver, MailOutcome::Failed);
+        // dot stuffed lines are restored
+        let reply = client.command("Subject: Test\r\n\r\n..kein Alarm\r\n.");
+        assert!(reply.starts_with("554"));
+        assert!(client.reply().starts_with("554")); // one reply per recipient
+        assert!(processed.join().unwrap().starts_with(".kein Alarm"));
+
+        assert_eq!(SmtpProtocol::Lmtp, config("protocol: LMTP").protocol);
+    }
+
+    #[test]
+    fn test_starttls() {
+        let (mut client, receiver) = Client::connect(config(&format!(
+            "listen: 127.0.0.1:0\n{}\n  required: true",
+            TLS
+        )));
+        assert!(client.reply().starts_with("220 "));
+        assert!(client
+            .command("EHLO leitstelle.de")
+            .ends_with("250 STARTTLS"));
+        assert!(client
+            .command("MAIL FROM:<alarm@leitstelle.de>")
+            .starts_with("530"));
+        assert!(client.command("STARTTLS").starts_with("220"));
+
+        let connector = TlsConnector::builder()
+            .add_root_certificate(Certificate::from_pem(CERT).unwrap())
+            .build()
+            .unwrap();
+        let stream = connector
+            .connect("localhost", client.reader.into_inner())
+            .unwrap();
+        let mut client = Client {
+            reader: BufReader::new(stream),
+        };
+        // the session starts over
+        assert!(client
+            .command("MAIL FROM:<alarm@leitstelle.de>")
+            .starts_with("503"));
+        let ehlo = client.command("EHLO leitstelle.de");
+        assert!(!ehlo.contains("STARTTLS"));
+        assert!(client.command("STARTTLS").starts_with("502"));
+        assert!(client
+            .command("MAIL FROM:<alarm@leitstelle.de>")
+            .starts_with("250"));
+        assert!(client
+            .command("RCPT TO:<alarm@ff-teltow.de>")
+            .starts_with("250"));
+        assert!(client.command("DATA").starts_with("354"));
+
+        let processed = thread::spawn(move || {
+            let mail = receiver.recv().unwrap();
+            let received = mail.content.headers[0].1.clone();
+            mail.finish(MailOutcome::Printed);
+            received
+        });
+        let mail = format!(
+            "From: alarm@leitstelle.de\r\nSubject: Alarm\r\n\r\n{}\r\n.",
+            EMS.replace('\n', "\r\n")
+        );
+        assert!(client.command(&mail).starts_with("250 2.0.0"));
+        assert!(processed.join().unwrap().contains("with ESMTPS"));
+        assert!(client.command("QUIT").starts_with("221"));
+    }
+
+    #[test]
+    fn test_max_sessions() {
+        let (mut client, _receiver) =
+            Client::connect(config("listen: 127.0.0.1:0\nmax_sessions: 1"));
+        assert!(client.reply().starts_with("220 "));
+        assert!(client.connect_again().reply().starts_with("421 4.3.2"));
+
+        // the session is released, after the client quit
+        assert!(client.command("QUIT").starts_with("221"));
+        let accepted = (0..50).any(|_| {
+            thread::sleep(Duration::from_millis(10));
+            return client.connect_again().reply().starts_with("220 ");
+        });
+        assert!(accepted);
+    }
+}
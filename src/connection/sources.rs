@@ -0,0 +1,367 @@
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    panic::{catch_unwind, AssertUnwindSafe},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc::{self, Receiver, Sender},
+        Arc,
+    },
+    thread::{self, sleep},
+    time::{Duration, Instant},
+};
+
+use log::{debug, error, info, trace, warn};
+
+use crate::{
+    config::{
+        config::{IMAPConfig, IMAPModes},
+        Config,
+    },
+    models::emergency::Emergency,
+};
+
+use super::{
+    hybrid::HybridMode,
+    imap::IMAPConnection,
+    imap_multipart::MailContent,
+    smtp::watch_smtp,
+    spool::watch_spool,
+    supervisor::{ConnectionState, Supervisor},
+    webhook::watch_webhook,
+};
+
+/// How the processing of a queued mail ended, decides what happens with the mail on the server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MailOutcome {
+    Printed,
+    /// the same alarm was already received (e.g. on the backup mailbox)
+    Duplicate,
+    /// the mail couldn't be parsed
+    Failed,
+    /// the sender isn't allowed to trigger alarms
+    Rejected,
+}
+
+/// A mail of one of the sources, waiting to be processed.
+pub struct QueuedMail {
+    /// the source the mail was received on (see [IMAPConfig::source_name])
+    pub source: String,
+    pub content: MailContent,
+    /// alarms received as json are already parsed, the text isn't parsed again
+    pub emergency: Option<Emergency>,
+    /// the source authenticated the sender itself (e.g. the webhook secret), the sender filter is skipped
+    pub authenticated: bool,
+    outcome: Sender<MailOutcome>,
+}
+
+impl QueuedMail {
+    /// the receiver gets the outcome, once the mail was processed
+    pub fn new(source: String, content: MailContent) -> (Self, Receiver<MailOutcome>) {
+        let (outcome, receiver) = mpsc::channel();
+        let mail = QueuedMail {
+            source,
+            content,
+            emergency: None,
+            authenticated: false,
+            outcome,
+        };
+        return (mail, receiver);
+    }
+
+    /// reports the result back to the source, which flags or moves the mail accordingly
+    pub fn finish(self, outcome: MailOutcome) {
+        if self.outcome.send(outcome).is_err() {
+            debug!("source {} is reconnecting, outcome dropped", self.source);
+        }
+    }
+}
+
+/// One of the limited concurrent sessions of a listener (smtp, webhook), released when dropped.
+pub struct SessionSlot {
+    sessions: Arc<AtomicUsize>,
+}
+
+impl SessionSlot {
+    /// None, if `max_sessions` sessions are already running
+    pub fn acquire(sessions: &Arc<AtomicUsize>, max_sessions: usize) -> Option<SessionSlot> {
+        let acquired = sessions.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |running| {
+            (running < max_sessions).then_some(running + 1)
+        });
+        return acquired.ok().map(|_| SessionSlot {
+            sessions: sessions.clone(),
+        });
+    }
+}
+
+impl Drop for SessionSlot {
+    fn drop(&mut self) {
+        self.sessions.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// how often a mail is fetched (or read) again after its processing failed unexpectedly (e.g. a panic), before it is given up
+pub const MAX_DELIVERY_ATTEMPTS: u32 = 3;
+
+/// Counts the unexpectedly failed processing attempts per mail (uid or spool file), across reconnects.
+pub struct DeliveryAttempts<K> {
+    failed: HashMap<K, u32>,
+}
+
+impl<K: Hash + Eq + Clone> DeliveryAttempts<K> {
+    pub fn new() -> Self {
+        return DeliveryAttempts {
+            failed: HashMap::new(),
+        };
+    }
+
+    /// counts a failed attempt, true if the mail should be given up
+    pub fn failed(&mut self, mail: &K) -> bool {
+        let attempts = self.failed.entry(mail.clone()).or_insert(0);
+        *attempts += 1;
+        if *attempts < MAX_DELIVERY_ATTEMPTS {
+            return false;
+        }
+        self.failed.remove(mail);
+        return true;
+    }
+
+    pub fn finished(&mut self, mail: &K) {
+        self.failed.remove(mail);
+    }
+}
+
+enum SourceError {
+    Failed(String),
+    ConnectionLost,
+}
+
+fn poll_new_mails(
+    connection: &mut IMAPConnection,
+    interval: Duration,
+) -> Result<Vec<Option<MailContent>>, ()> {
+    loop {
+        trace!("polling tick!");
+        let res = connection.load_new_mails();
+        if res.is_err() || res.as_ref().is_ok_and(|v| !v.is_empty()) {
+            break res; // either we have new mails or an error
+        }
+        sleep(interval);
+    }
+}
+
+/// Waits for new mails in the hybrid mode: IDLE for at most `interval`, followed by a check
+/// for missed mails. Polls every `interval` instead, while IDLE is unreliable (see [HybridMode]).
+fn hybrid_new_mails(
+    connection: &mut IMAPConnection,
+    hybrid: &mut HybridMode,
+    supervisor: &mut Supervisor,
+    interval: Duration,
+) -> Result<Vec<Option<MailContent>>, ()> {
+    loop {
+        let mut notified = None;
+        if hybrid.use_idle(Instant::now()) {
+            supervisor.transition(ConnectionState::Idling);
+            match connection.idle_with_timeout(interval) {
+                Ok(n) => notified = Some(n),
+                Err(_) => hybrid.idle_failed(Instant::now()),
+            }
+        } else {
+            supervisor.transition(ConnectionState::Polling);
+            sleep(interval);
+        }
+
+        let mails = connection.check_new_mails()?;
+        match notified {
+            Some(false) if !mails.is_empty() => {
+                warn!("idle missed {} new mails", mails.len());
+                hybrid.idle_failed(Instant::now());
+            }
+            Some(_) => hybrid.idle_succeeded(),
+            None => {}
+        }
+        if !mails.is_empty() {
+            return Ok(mails);
+        }
+    }
+}
+
+/// Watches a single source and queues its new mails until the connection fails.
+///
+/// # description
+/// waits for the outcome of each mail, to flag or move it on the server. The progress is only saved
+/// after the whole batch was processed, so mails are fetched again, if the processing didn't finish.
+/// A mail, whose processing failed [MAX_DELIVERY_ATTEMPTS] times, is handled like an unparsable
+/// mail, so it can't block the source.
+fn run_source(
+    imap_cfg: &IMAPConfig,
+    queue: &Sender<QueuedMail>,
+    supervisor: &mut Supervisor,
+    hybrid: &mut Option<HybridMode>,
+    attempts: &mut DeliveryAttempts<u32>,
+) -> Result<(), SourceError> {
+    let name = imap_cfg.source_name();
+    supervisor.transition(ConnectionState::Connecting);
+    let mut connection = IMAPConnection::connect(imap_cfg).map_err(SourceError::Failed)?;
+    supervisor.connected();
+    info!("Bereit zum Empfangen der Alarmemails ({}).", name);
+    loop {
+        let interval = imap_cfg.mode.interval_as_duration();
+        let new_mails = match imap_cfg.mode.method {
+            IMAPModes::Idle => {
+                supervisor.transition(ConnectionState::Idling);
+                connection.idle_for_new_mails()
+            }
+            IMAPModes::Poll => {
+                supervisor.transition(ConnectionState::Polling);
+                poll_new_mails(&mut connection, interval)
+            }
+            IMAPModes::Hybrid => {
+                // kept across reconnects, so the failures of previous connections count
+                let hybrid =
+                    hybrid.get_or_insert_with(|| HybridMode::new(name.clone(), &imap_cfg.mode));
+                hybrid_new_mails(&mut connection, hybrid, supervisor, interval)
+            }
+        };
+        supervisor.transition(ConnectionState::Authenticated);
+        let Ok(new_mails) = new_mails else {
+            connection.end();
+            return Err(SourceError::ConnectionLost);
+        };
+
+        for mail in new_mails {
+            let Some(mail) = mail else {
+                debug!("mail is none");
+                continue;
+            };
+            let uid = mail.uid;
+            let (queued, outcome_receiver) = QueuedMail::new(name.clone(), mail);
+            if queue.send(queued).is_err() {
+                return Ok(()); // the processing stopped
+            }
+            let outcome = match (outcome_receiver.recv(), uid) {
+                (Ok(outcome), _) => outcome,
+                (Err(_), Some(uid)) if attempts.failed(&uid) => {
+                    error!(
+                        "{}: mail {} wasn't processed after {} attempts, giving up",
+                        name, uid, MAX_DELIVERY_ATTEMPTS
+                    );
+                    MailOutcome::Failed
+                }
+                (Err(_), _) => {
+                    connection.end();
+                    return Err(SourceError::Failed("mail wasn't processed".to_string()));
+                }
+            };
+            let Some(uid) = uid else {
+                continue;
+            };
+            attempts.finished(&uid);
+            match outcome {
+                MailOutcome::Printed | MailOutcome::Duplicate => connection.mark_processed(uid),
+                MailOutcome::Failed => connection.mark_failed(uid),
+                MailOutcome::Rejected => connection.mark_quarantined(uid),
+            }
+        }
+        connection.save_state();
+    }
+}
+
+/// watches the source until the processing stops, reconnecting on errors and panics (see [Supervisor])
+fn watch_source(imap_cfg: IMAPConfig, queue: Sender<QueuedMail>) {
+    let name = imap_cfg.source_name();
+    let mut supervisor = Supervisor::new(&imap_cfg);
+    let mut hybrid = None;
+    let mut attempts = DeliveryAttempts::new();
+    loop {
+        let res = catch_unwind(AssertUnwindSafe(|| {
+            run_source(
+                &imap_cfg,
+                &queue,
+                &mut supervisor,
+                &mut hybrid,
+                &mut attempts,
+            )
+        }));
+        match res {
+            Ok(Ok(())) => {
+                info!("stopped watching {}", name);
+                return;
+            }
+            Ok(Err(SourceError::ConnectionLost)) => {
+                info!("lost connection to {}", name);
+            }
+            Ok(Err(SourceError::Failed(e))) => {
+                error!("{} ({:?}): {}", name, supervisor.state(), e);
+            }
+            Err(panic) => {
+                info!("caught panic while watching {}, restarting", name);
+                trace!("panic: {:?}", panic);
+            }
+        }
+        supervisor.backoff();
+    }
+}
+
+/// Starts watching all configured sources (imap, spool directories, the smtp listener and the webhook) concurrently, each in its own thread.
+///
+/// # return value
+/// the queue receiving the mails of all sources
+pub fn watch_sources(config: &Config) -> Receiver<QueuedMail> {
+    let (queue, receiver) = mpsc::channel();
+    for imap_cfg in &config.imap {
+        let imap_cfg = imap_cfg.clone();
+        let queue = queue.clone();
+        thread::Builder::new()
+            .name(imap_cfg.source_name())
+            .spawn(move || watch_source(imap_cfg, queue))
+            .expect("couldn't start source thread");
+    }
+    for spool in &config.spool {
+        let spool = spool.clone();
+        let queue = queue.clone();
+        thread::Builder::new()
+            .name(spool.source_name())
+            .spawn(move || watch_spool(spool, queue))
+            .expect("couldn't start spool thread");
+    }
+    if let Some(smtp) = &config.smtp {
+        let smtp = smtp.clone();
+        let queue = queue.clone();
+        thread::Builder::new()
+            .name(smtp.source_name())
+            .spawn(move || watch_smtp(smtp, queue))
+            .expect("couldn't start smtp thread");
+    }
+    if let Some(webhook) = &config.webhook {
+        let webhook = webhook.clone();
+        let queue = queue.clone();
+        thread::Builder::new()
+            .name(webhook.source_name())
+            .spawn(move || watch_webhook(webhook, queue))
+            .expect("couldn't start webhook thread");
+    }
+    return receiver;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DeliveryAttempts, MAX_DELIVERY_ATTEMPTS};
+
+    #[test]
+    fn test_delivery_attempts() {
+        let mut attempts = DeliveryAttempts::new();
+        for _ in 1..MAX_DELIVERY_ATTEMPTS {
+            assert!(!attempts.failed(&42));
+        }
+        assert!(!attempts.failed(&7)); // counted per mail
+        assert!(attempts.failed(&42));
+        // counting starts over, if the mail is fetched again nevertheless
+        assert!(!attempts.failed(&42));
+
+        attempts.finished(&7);
+        for _ in 1..MAX_DELIVERY_ATTEMPTS {
+            assert!(!attempts.failed(&7));
+        }
+    }
+}
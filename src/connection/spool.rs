@@ -0,0 +1,302 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::mpsc::Sender,
+    thread::sleep,
+    time::{Duration, SystemTime},
+};
+
+use log::{debug, error, info, warn};
+
+use crate::config::config::SpoolConfig;
+
+use super::{
+    imap_multipart::{get_message_content, MailContent},
+    message::Message,
+    sources::{DeliveryAttempts, MailOutcome, QueuedMail, MAX_DELIVERY_ATTEMPTS},
+};
+
+/// files modified more recently might still be written (plain directories aren't delivered atomically)
+const SETTLE_TIME: Duration = Duration::from_secs(1);
+
+/// separates the file name from the Maildir info (flags), `:` isn't allowed in file names on windows
+const MAILDIR_INFO_SEPARATOR: char = if cfg!(windows) { '!' } else { ':' };
+
+/// whether the directory is a Maildir, i.e. contains `new` and `cur`
+fn is_maildir(path: &Path) -> bool {
+    return path.join("new").is_dir() && path.join("cur").is_dir();
+}
+
+fn has_extension(path: &Path, extension: &str) -> bool {
+    return path
+        .extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case(extension));
+}
+
+/// Lists the files waiting to be processed, the oldest first.
+///
+/// # description
+/// in a Maildir, all files in `new` are mails. In a plain directory, only `.eml` (whole mails) and
+/// `.txt` (the alarm text only) files are processed.
+pub fn pending_files(spool: &SpoolConfig) -> Result<Vec<PathBuf>, String> {
+    let dir = Path::new(&spool.path);
+    let maildir = is_maildir(dir);
+    let dir = if maildir {
+        dir.join("new")
+    } else {
+        dir.to_path_buf()
+    };
+    let entries =
+        fs::read_dir(&dir).map_err(|e| format!("couldn't read directory {:?}: {}", dir, e))?;
+
+    let now = SystemTime::now();
+    let mut files: Vec<(SystemTime, PathBuf)> = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let metadata = entry.metadata().ok()?;
+            let path = entry.path();
+            let is_mail = maildir || has_extension(&path, "eml") || has_extension(&path, "txt");
+            if !metadata.is_file() || !is_mail {
+                return None;
+            }
+            let modified = metadata.modified().unwrap_or(now);
+            let settled = now
+                .duration_since(modified)
+                .is_ok_and(|age| age >= SETTLE_TIME);
+            return (maildir || settled).then_some((modified, path));
+        })
+        .collect();
+    files.sort();
+    return Ok(files.into_iter().map(|(_, path)| path).collect());
+}
+
+/// Reads and decodes a file the same way as a fetched mail (see [get_message_content]).
+pub fn read_mail(path: &Path) -> Option<MailContent> {
+    let raw = match fs::read(path) {
+        Ok(raw) => raw,
+        Err(e) => {
+            error!("couldn't read {:?}: {}", path, e);
+            return None;
+        }
+    };
+    let message = if has_extension(path, "txt") {
+        Message::from_text(&raw)
+    } else {
+        Message::from_raw(&raw)
+    };
+    return get_message_content(message);
+}
+
+/// a path in `dir` named like `file_name`, that doesn't exist yet
+fn free_path(dir: &Path, file_name: &str) -> PathBuf {
+    let path = dir.join(file_name);
+    if !path.exists() {
+        return path;
+    }
+    let (stem, extension) = match file_name.rsplit_once('.') {
+        Some((stem, extension)) => (stem, format!(".{}", extension)),
+        None => (file_name, String::new()),
+    };
+    let mut n = 1;
+    loop {
+        let path = dir.join(format!("{}_{}{}", stem, n, extension));
+        if !path.exists() {
+            return path;
+        }
+        n += 1;
+    }
+}
+
+/// Moves a processed file out of the way, so it isn't processed again.
+///
+/// # description
+/// in a Maildir, the file is moved to `cur` and marked as seen (`S`), failed and rejected mails are
+/// additionally flagged (`F`). In a plain directory, it is moved to the processed or failed folder.
+///
+/// # return value
+/// the new path of the file
+pub fn finish_file(
+    spool: &SpoolConfig,
+    path: &Path,
+    outcome: MailOutcome,
+) -> Result<PathBuf, String> {
+    let dir = Path::new(&spool.path);
+    let file_name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .ok_or_else(|| format!("{:?} is not a file", path))?;
+    let succeeded = matches!(outcome, MailOutcome::Printed | MailOutcome::Duplicate);
+
+    let target = if is_maildir(dir) {
+        let flags = if succeeded { "S" } else { "FS" };
+        let unique = file_name
+            .split(MAILDIR_INFO_SEPARATOR)
+            .next()
+            .unwrap_or(&file_name);
+        let name = format!("{}{}2,{}", unique, MAILDIR_INFO_SEPARATOR, flags);
+        free_path(&dir.join("cur"), &name)
+    } else {
+        let folder = if succeeded {
+            &spool.processed_folder
+        } else {
+            &spool.failed_folder
+        };
+        let folder = dir.join(folder);
+        fs::create_dir_all(&folder)
+            .map_err(|e| format!("couldn't create directory {:?}: {}", folder, e))?;
+        free_path(&folder, &file_name)
+    };
+    fs::rename(path, &target)
+        .map_err(|e| format!("couldn't move {:?} to {:?}: {}", path, target, e))?;
+    return Ok(target);
+}
+
+/// Watches a Maildir or plain directory and queues each new file like a received mail.
+///
+/// # description
+/// the directory is checked every interval. Each file is moved after its outcome is known (see [finish_file]),
+/// files, that can't be decoded or whose processing failed unexpectedly [MAX_DELIVERY_ATTEMPTS] times,
+/// are treated as failed. Errors (e.g. a missing network share) are retried with the next check,
+/// the watch only ends when the processing stopped.
+pub fn watch_spool(spool: SpoolConfig, queue: Sender<QueuedMail>) {
+    let name = spool.source_name();
+    let mut attempts = DeliveryAttempts::new();
+    info!("Bereit zum Empfangen der Alarmemails ({}).", name);
+    loop {
+        let files = match pending_files(&spool) {
+            Ok(files) => files,
+            Err(e) => {
+                error!("{}: {}", name, e);
+                Vec::new()
+            }
+        };
+        for path in files {
+            debug!("processing {:?}", path);
+            let outcome = match read_mail(&path) {
+                Some(content) => {
+                    let (mail, outcome) = QueuedMail::new(name.clone(), content);
+                    if queue.send(mail).is_err() {
+                        info!("stopped watching {}", name);
+                        return;
+                    }
+                    match outcome.recv() {
+                        Ok(outcome) => outcome,
+                        Err(_) if attempts.failed(&path) => {
+                            error!(
+                                "{:?} wasn't processed after {} attempts, giving up",
+                                path, MAX_DELIVERY_ATTEMPTS
+                            );
+                            MailOutcome::Failed
+                        }
+                        Err(_) => {
+                            // processed again with the next check
+                            warn!("{:?} wasn't processed", path);
+                            continue;
+                        }
+                    }
+                }
+                None => MailOutcome::Failed,
+            };
+            attempts.finished(&path);
+            match finish_file(&spool, &path, outcome) {
+                Ok(target) => debug!("moved {:?} to {:?}", path, target),
+                Err(e) => error!("{}: {}", name, e),
+            }
+        }
+        sleep(spool.interval_as_duration());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, path::PathBuf, str::FromStr};
+
+    use super::{finish_file, pending_files, read_mail};
+    use crate::{
+        config::config::SpoolConfig, connection::sources::MailOutcome, models::emergency::Emergency,
+    };
+
+    const EMS: &str = include_str!("../../examples/emergency_simple.txt");
+
+    fn spool_dir(name: &str) -> (PathBuf, SpoolConfig) {
+        let dir = env::temp_dir().join(name);
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let spool = serde_yaml::from_str(&format!("path: {:?}", dir.to_string_lossy())).unwrap();
+        return (dir, spool);
+    }
+
+    /// the modification time is set in the past, so the files are considered completely written
+    fn write_settled(path: &PathBuf, content: &[u8]) {
+        fs::write(path, content).unwrap();
+        let file = fs::File::options().write(true).open(path).unwrap();
+        file.set_modified(std::time::SystemTime::now() - std::time::Duration::from_secs(10))
+            .unwrap();
+    }
+
+    #[test]
+    fn test_plain_directory() {
+        let (dir, spool) = spool_dir("emergency_mail_test_spool_plain");
+        let eml = format!(
+            "From: Leitstelle <alarm@leitstelle.de>\r\nSubject: Alarm\r\n\
+Content-Type: text/plain; charset=utf-8\r\nContent-Transfer-Encoding: 8bit\r\n\r\n{}",
+            EMS
+        );
+        write_settled(&dir.join("alarm.eml"), eml.as_bytes());
+        write_settled(&dir.join("test.txt"), EMS.as_bytes());
+        write_settled(&dir.join("notes.doc"), b"ignored");
+        fs::write(dir.join("writing.eml"), b"still being written").unwrap();
+
+        let files = pending_files(&spool).unwrap();
+        assert_eq!(files.len(), 2);
+        for path in &files {
+            let content = read_mail(path).unwrap();
+            let ems = Emergency::from_str(&content.text).unwrap();
+            assert_eq!(ems.emergency_number, 322088295);
+        }
+        let content = read_mail(&dir.join("alarm.eml")).unwrap();
+        assert_eq!(content.uid, None);
+        assert!(content
+            .headers
+            .iter()
+            .any(|(name, value)| name == "from" && value.contains("alarm@leitstelle.de")));
+
+        let processed = finish_file(&spool, &dir.join("alarm.eml"), MailOutcome::Printed).unwrap();
+        assert_eq!(processed, dir.join("processed").join("alarm.eml"));
+        let failed = finish_file(&spool, &dir.join("test.txt"), MailOutcome::Failed).unwrap();
+        assert_eq!(failed, dir.join("failed").join("test.txt"));
+        assert!(pending_files(&spool).unwrap().is_empty());
+
+        // a resend of the same file doesn't overwrite the processed one
+        write_settled(&dir.join("alarm.eml"), eml.as_bytes());
+        let processed =
+            finish_file(&spool, &dir.join("alarm.eml"), MailOutcome::Duplicate).unwrap();
+        assert_eq!(processed, dir.join("processed").join("alarm_1.eml"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_maildir() {
+        let (dir, spool) = spool_dir("emergency_mail_test_spool_maildir");
+        for sub in ["new", "cur", "tmp"] {
+            fs::create_dir(dir.join(sub)).unwrap();
+        }
+        let eml = format!("Subject: Alarm\r\n\r\n{}", EMS);
+        fs::write(dir.join("new").join("1700000000.M1P2.alarmpc"), &eml).unwrap();
+
+        let files = pending_files(&spool).unwrap();
+        assert_eq!(files.len(), 1); // delivered atomically, no need to wait
+        let ems = Emergency::from_str(&read_mail(&files[0]).unwrap().text).unwrap();
+        assert_eq!(ems.emergency_number, 322088295);
+
+        let target = finish_file(&spool, &files[0], MailOutcome::Rejected).unwrap();
+        let separator = if cfg!(windows) { '!' } else { ':' };
+        assert_eq!(
+            target,
+            dir.join("cur")
+                .join(format!("1700000000.M1P2.alarmpc{}2,FS", separator))
+        );
+        assert!(pending_files(&spool).unwrap().is_empty());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
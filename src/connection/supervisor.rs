@@ -0,0 +1,259 @@
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    thread::sleep,
+    time::{Duration, Instant},
+};
+
+use log::{debug, error, info};
+
+use crate::config::config::{IMAPConfig, ReconnectConfig};
+
+use super::notify::run_notify_command;
+
+/// a connection, that was up at least this long, is considered healthy. The backoff starts over after it failed.
+const STABLE_CONNECTION: Duration = Duration::from_secs(60);
+
+/// The states of the connection to a source.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionState {
+    /// establishing the connection and logging in
+    Connecting,
+    /// logged in and the mailbox selected, e.g. while the new mails are processed
+    Authenticated,
+    /// waiting for new mails using IMAP IDLE
+    Idling,
+    /// checking for new mails periodically
+    Polling,
+    /// waiting before connecting again after a failure
+    Backoff,
+}
+
+/// Exponentially increasing delays between connection attempts, capped at a maximum.
+///
+/// # description
+/// each delay is randomized between half and the full exponential delay ("equal jitter"), so that
+/// several sources (or several installations) don't reconnect to a recovering server at the same time.
+pub struct Backoff {
+    initial: Duration,
+    max: Duration,
+    attempt: u32,
+}
+
+impl Backoff {
+    /// the exponent is limited, so the delay can't overflow before it is capped
+    const MAX_EXPONENT: u32 = 16;
+
+    pub fn new(initial: Duration, max: Duration) -> Self {
+        return Backoff {
+            initial,
+            max,
+            attempt: 0,
+        };
+    }
+
+    /// a random duration between zero and max
+    fn jitter(max: Duration) -> Duration {
+        // the std hasher is seeded randomly for every RandomState, which is good enough for a jitter
+        let random = RandomState::new().build_hasher().finish();
+        return max.mul_f64(random as f64 / u64::MAX as f64);
+    }
+
+    /// the delay before the next attempt, increases the delay of the following attempt
+    pub fn next_delay(&mut self) -> Duration {
+        let exponential = self
+            .initial
+            .saturating_mul(1 << self.attempt.min(Backoff::MAX_EXPONENT))
+            .min(self.max);
+        self.attempt = self.attempt.saturating_add(1);
+        let half = exponential / 2;
+        return half + Backoff::jitter(exponential - half);
+    }
+
+    /// starts over with the initial delay
+    pub fn reset(&mut self) {
+        self.attempt = 0;
+    }
+}
+
+/// Tracks, since when a source is offline, to alert once if it stays offline too long.
+pub struct OfflineMonitor {
+    threshold: Option<Duration>,
+    offline_since: Option<Instant>,
+    alerted: bool,
+}
+
+impl OfflineMonitor {
+    /// without a threshold, no alert is raised
+    pub fn new(threshold: Option<Duration>) -> Self {
+        return OfflineMonitor {
+            threshold,
+            offline_since: None,
+            alerted: false,
+        };
+    }
+
+    /// remembers the time of the first failure, further failures don't change it
+    pub fn went_offline(&mut self, now: Instant) {
+        self.offline_since.get_or_insert(now);
+    }
+
+    /// the time offline, if the alert should be raised now. Only returned once per outage.
+    pub fn check(&mut self, now: Instant) -> Option<Duration> {
+        let (Some(threshold), Some(since)) = (self.threshold, self.offline_since) else {
+            return None;
+        };
+        let offline = now.duration_since(since);
+        if self.alerted || offline < threshold {
+            return None;
+        }
+        self.alerted = true;
+        return Some(offline);
+    }
+
+    /// ends the outage, returns whether an alert was raised for it
+    pub fn back_online(&mut self) -> bool {
+        self.offline_since = None;
+        return std::mem::replace(&mut self.alerted, false);
+    }
+}
+
+/// Supervises the connection to a single source: tracks its state, delays reconnects and alerts
+/// the admin if the source is offline too long.
+///
+/// # description
+/// the source is retried forever, as the alarms of a provider outage must be printed once the
+/// provider is back. The delays increase exponentially up to the configured maximum, so the
+/// server isn't hammered during an outage.
+pub struct Supervisor {
+    name: String,
+    state: ConnectionState,
+    backoff: Backoff,
+    offline: OfflineMonitor,
+    alert_command: Option<String>,
+    connected_at: Option<Instant>,
+}
+
+impl Supervisor {
+    pub fn new(imap_cfg: &IMAPConfig) -> Self {
+        let reconnect: &ReconnectConfig = &imap_cfg.reconnect;
+        return Supervisor {
+            name: imap_cfg.source_name(),
+            state: ConnectionState::Connecting,
+            backoff: Backoff::new(
+                reconnect.initial_delay_as_duration(),
+                reconnect.max_delay_as_duration(),
+            ),
+            offline: OfflineMonitor::new(reconnect.offline_alert_as_duration()),
+            alert_command: reconnect.alert_command.clone(),
+            connected_at: None,
+        };
+    }
+
+    pub fn state(&self) -> ConnectionState {
+        return self.state;
+    }
+
+    pub fn transition(&mut self, state: ConnectionState) {
+        if self.state != state {
+            debug!("{}: {:?} -> {:?}", self.name, self.state, state);
+            self.state = state;
+        }
+    }
+
+    fn alert(&self, message: &str) {
+        error!("{}", message);
+        if let Some(command) = &self.alert_command {
+            run_notify_command(command, message);
+        }
+    }
+
+    /// the connection was established and the mailbox selected
+    pub fn connected(&mut self) {
+        self.transition(ConnectionState::Authenticated);
+        self.connected_at = Some(Instant::now());
+        if self.offline.back_online() {
+            self.alert(&format!("Verbindung zu {} wiederhergestellt", self.name));
+        }
+    }
+
+    /// Waits before the next connection attempt, after the connection failed or was lost.
+    ///
+    /// # description
+    /// a connection, that was stable for a while, starts over with the initial delay. Otherwise
+    /// the delay increases, so a server accepting the login but failing afterwards isn't hammered either.
+    pub fn backoff(&mut self) {
+        let now = Instant::now();
+        let stable = self
+            .connected_at
+            .take()
+            .is_some_and(|at| now.duration_since(at) >= STABLE_CONNECTION);
+        if stable {
+            self.backoff.reset();
+        }
+        self.offline.went_offline(now);
+        if let Some(offline) = self.offline.check(now) {
+            self.alert(&format!(
+                "{} ist seit {} Minuten nicht erreichbar, Alarmemails werden nicht empfangen!",
+                self.name,
+                offline.as_secs() / 60
+            ));
+        }
+
+        let delay = self.backoff.next_delay();
+        self.transition(ConnectionState::Backoff);
+        info!("reconnecting to {} in {} s", self.name, delay.as_secs());
+        sleep(delay);
+        self.transition(ConnectionState::Connecting);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::{Backoff, OfflineMonitor};
+
+    #[test]
+    fn test_backoff() {
+        let mut backoff = Backoff::new(Duration::from_secs(5), Duration::from_secs(60));
+        let expected = [5, 10, 20, 40, 60, 60];
+        for exponential in expected {
+            let delay = backoff.next_delay();
+            let exponential = Duration::from_secs(exponential);
+            assert!(delay >= exponential / 2, "{:?} < {:?}", delay, exponential);
+            assert!(delay <= exponential, "{:?} > {:?}", delay, exponential);
+        }
+        // the cap holds for many failures
+        for _ in 0..100 {
+            assert!(backoff.next_delay() <= Duration::from_secs(60));
+        }
+
+        backoff.reset();
+        assert!(backoff.next_delay() <= Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_offline_monitor() {
+        let start = Instant::now();
+        let mut monitor = OfflineMonitor::new(Some(Duration::from_secs(600)));
+        monitor.went_offline(start);
+        assert_eq!(monitor.check(start + Duration::from_secs(300)), None);
+        // later failures don't restart the outage
+        monitor.went_offline(start + Duration::from_secs(300));
+        assert_eq!(
+            monitor.check(start + Duration::from_secs(660)),
+            Some(Duration::from_secs(660))
+        );
+        assert_eq!(monitor.check(start + Duration::from_secs(900)), None); // only alerted once
+        assert!(monitor.back_online());
+
+        monitor.went_offline(start + Duration::from_secs(1000));
+        assert_eq!(monitor.check(start + Duration::from_secs(1100)), None);
+        assert!(!monitor.back_online());
+
+        let mut disabled = OfflineMonitor::new(None);
+        disabled.went_offline(start);
+        assert_eq!(disabled.check(start + Duration::from_secs(86400)), None);
+    }
+}
@@ -1,6 +1,6 @@
 use crate::connection::{
     imap_multipart::{get_message_body, test::MULTIPART_BODY, test::MULTIPART_BODY_PLAIN_TEXT},
-    message::Message,
+    message::{mail_str_decode_unicode, Message},
 };
 
 const HEADER_CONTENT_TYPE: &str = "Content-Type: text/plain\r\n";
@@ -12,12 +12,14 @@ const BODY_PLAIN_TEXT: &str = "~~Ort~~Brandenburg an der Havel~~\r\n\r\n\r\n~~Or
 fn test_get_message_body_plain_text() {
     let example = Message {
         seq: 1,
+        internal_date: None,
         uid: Some(1),
         header: Some(HEADER_CONTENT_TYPE.as_bytes().to_vec()),
         text: Some(BODY_PLAIN_TEXT.as_bytes().to_vec()),
     };
     let body = get_message_body(example);
-    assert_eq!(body, Some(BODY_PLAIN_TEXT.to_string()));
+    // no transfer encoding given, the body is decoded as quoted-printable (as sent by the dispatch centre)
+    assert_eq!(body, Some(mail_str_decode_unicode(BODY_PLAIN_TEXT)));
 }
 
 #[test]
@@ -25,10 +27,47 @@ pub fn test_get_message_body_multipart() {
     // the get_message_body function should return the plain text part of the multipart mail
     let example = Message {
         seq: 1,
+        internal_date: None,
         uid: Some(1),
         header: Some(HEADER_CONTENT_TYPE_MULTIPART.as_bytes().to_vec()),
         text: Some(MULTIPART_BODY.as_bytes().to_vec()),
     };
     let body = get_message_body(example);
-    assert_eq!(body, Some(MULTIPART_BODY_PLAIN_TEXT.to_string()));
+    // the line break before the separator belongs to the separator (RFC 2046)
+    let expected = mail_str_decode_unicode(MULTIPART_BODY_PLAIN_TEXT);
+    assert_eq!(body.as_deref(), expected.strip_suffix("\r\n"));
+}
+
+#[test]
+pub fn test_get_message_body_base64_latin1() {
+    let example = Message {
+        seq: 1,
+        internal_date: None,
+        uid: Some(1),
+        header: Some(
+            "Content-Type: text/plain; charset=ISO-8859-1\r\nContent-Transfer-Encoding: base64\r\n"
+                .as_bytes()
+                .to_vec(),
+        ),
+        text: Some(b"fn5PcnR+fkf2dHRpbn5+\r\n".to_vec()),
+    };
+    assert_eq!(
+        get_message_body(example),
+        Some("~~Ort~~Göttin~~".to_string())
+    );
+}
+
+#[test]
+pub fn test_get_message_body_multipart_without_headers() {
+    let example = Message {
+        seq: 1,
+        internal_date: None,
+        uid: Some(1),
+        header: None,
+        text: Some(MULTIPART_BODY.as_bytes().to_vec()),
+    };
+    let body = get_message_body(example);
+    // the line break before the separator belongs to the separator (RFC 2046)
+    let expected = mail_str_decode_unicode(MULTIPART_BODY_PLAIN_TEXT);
+    assert_eq!(body.as_deref(), expected.strip_suffix("\r\n"));
 }
@@ -0,0 +1,276 @@
+use std::{
+    fs,
+    io::{Read, Write},
+    net::TcpStream,
+};
+
+use imap::{Client, ImapConnection};
+use log::{debug, info, trace, warn};
+use native_tls::{Certificate, TlsConnector, TlsStream};
+use sha2::{Digest, Sha256};
+
+use crate::config::config::{IMAPConfig, TlsConfig, TlsMode};
+
+/// lower case hex without separators, e.g. `ab01...` for `AB:01:...`
+fn normalize_fingerprint(fingerprint: &str) -> String {
+    return fingerprint
+        .chars()
+        .filter(|c| c.is_ascii_hexdigit())
+        .map(|c| c.to_ascii_lowercase())
+        .collect();
+}
+
+/// checks the SHA-256 fingerprint of the server certificate against the configured pin
+fn check_pin(stream: &TlsStream<TcpStream>, pin: &str) -> Result<(), String> {
+    let certificate = stream
+        .peer_certificate()
+        .map_err(|e| format!("couldn't get server certificate: {}", e))?
+        .ok_or("server didn't send a certificate")?;
+    let der = certificate
+        .to_der()
+        .map_err(|e| format!("couldn't encode server certificate: {}", e))?;
+    let fingerprint: String = Sha256::digest(&der)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    if fingerprint != normalize_fingerprint(pin) {
+        return Err(format!(
+            "server certificate doesn't match the pinned one (sha256 {}), refusing connection",
+            fingerprint
+        ));
+    }
+    debug!("server certificate matches the pin");
+    return Ok(());
+}
+
+/// TLS handshake verifying the certificate chain (with the additional CA) and the pin
+fn handshake(host: &str, tcp: TcpStream, tls: &TlsConfig) -> Result<TlsStream<TcpStream>, String> {
+    let mut builder = TlsConnector::builder();
+    if let Some(ca_file) = &tls.ca_file {
+        let pem = fs::read(ca_file).map_err(|e| format!("couldn't read {}: {}", ca_file, e))?;
+        let certificate = Certificate::from_pem(&pem)
+            .map_err(|e| format!("invalid ca certificate {}: {}", ca_file, e))?;
+        builder.add_root_certificate(certificate);
+    }
+    let connector = builder
+        .build()
+        .map_err(|e| format!("couldn't create tls connector: {}", e))?;
+    let stream = connector
+        .connect(host, tcp)
+        .map_err(|e| format!("tls handshake with {} failed: {}", host, e))?;
+    if let Some(pin) = &tls.pin_sha256 {
+        check_pin(&stream, pin)?;
+    }
+    return Ok(stream);
+}
+
+/// reads a line byte by byte, so that nothing after it (i.e. the tls handshake) is consumed
+fn read_line(tcp: &mut TcpStream) -> Result<String, String> {
+    let mut line = Vec::new();
+    let mut byte = [0u8; 1];
+    while !line.ends_with(b"\r\n") {
+        let n = tcp
+            .read(&mut byte)
+            .map_err(|e| format!("STARTTLS failed: {}", e))?;
+        if n == 0 {
+            return Err("STARTTLS failed: connection closed".to_string());
+        }
+        line.push(byte[0]);
+    }
+    return Ok(String::from_utf8_lossy(&line).to_string());
+}
+
+/// sends the command and returns the untagged responses, fails if the server doesn't answer OK
+fn command(tcp: &mut TcpStream, tag: &str, command: &str) -> Result<Vec<String>, String> {
+    tcp.write_all(format!("{} {}\r\n", tag, command).as_bytes())
+        .map_err(|e| format!("STARTTLS failed: {}", e))?;
+    let mut untagged = Vec::new();
+    loop {
+        let line = read_line(tcp)?;
+        let Some(status) = line.strip_prefix(&format!("{} ", tag)) else {
+            untagged.push(line);
+            continue;
+        };
+        if !status.to_ascii_uppercase().starts_with("OK") {
+            return Err(format!("{} failed: {}", command, status.trim()));
+        }
+        return Ok(untagged);
+    }
+}
+
+/// Upgrades the plain connection with STARTTLS (RFC 3501, section 6.2.1).
+///
+/// The imap crate only offers STARTTLS through its ClientBuilder, which doesn't allow to configure
+/// the certificate checks, so the few commands before the handshake are sent manually.
+fn starttls(tcp: &mut TcpStream, host: &str) -> Result<(), String> {
+    let greeting = read_line(tcp)?;
+    trace!("greeting: {}", greeting.trim());
+    let capabilities = command(tcp, "s1", "CAPABILITY")?;
+    let supported = capabilities
+        .iter()
+        .filter(|l| l.to_ascii_uppercase().starts_with("* CAPABILITY"))
+        .flat_map(|l| l.split_whitespace())
+        .any(|c| c.eq_ignore_ascii_case("STARTTLS"));
+    if !supported {
+        return Err(format!("{} doesn't support STARTTLS", host));
+    }
+    command(tcp, "s2", "STARTTLS")?;
+    return Ok(());
+}
+
+/// Connects to the IMAP server using the configured TLS mode and reads the greeting.
+///
+/// # description
+/// The certificate chain is always verified, an additional CA can be configured for internal servers.
+/// If a pin is configured, the server certificate must additionally match it.
+/// STARTTLS never falls back to an unencrypted connection.
+pub fn connect_client(imap_cfg: &IMAPConfig) -> Result<Client<Box<dyn ImapConnection>>, String> {
+    let tls = &imap_cfg.tls;
+    let host = imap_cfg.host.as_str();
+    let tcp = TcpStream::connect((host, imap_cfg.port))
+        .map_err(|e| format!("couldn't connect to {}:{}: {}", host, imap_cfg.port, e))?;
+
+    let mut client: Client<Box<dyn ImapConnection>> = match tls.mode {
+        TlsMode::Tls => Client::new(Box::new(handshake(host, tcp, tls)?)),
+        TlsMode::StartTls => {
+            let mut tcp = tcp;
+            starttls(&mut tcp, host)?;
+            let mut client = Client::new(Box::new(handshake(host, tcp, tls)?) as Box<_>);
+            client.greeting_read = true; // the greeting was sent before the upgrade
+            client
+        }
+        TlsMode::Plaintext => {
+            warn!("using an unencrypted connection to {}", host);
+            Client::new(Box::new(tcp))
+        }
+    };
+    if tls.mode != TlsMode::StartTls {
+        client
+            .read_greeting()
+            .map_err(|e| format!("couldn't read greeting: {}", e))?;
+    }
+    info!("connected to {} ({:?})", host, tls.mode);
+    return Ok(client);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufRead, BufReader, Write},
+        net::{TcpListener, TcpStream},
+        thread,
+    };
+
+    use native_tls::{Identity, TlsAcceptor};
+
+    use super::{connect_client, normalize_fingerprint};
+    use crate::config::config::{IMAPConfig, TlsConfig, TlsMode};
+
+    const CERT: &[u8] = include_bytes!("../../resources/test/localhost.pem");
+    const KEY: &[u8] = include_bytes!("../../resources/test/localhost.key");
+    const CERT_SHA256: &str =
+        "DD:DD:BE:FE:62:3B:E7:8F:89:91:88:D7:54:DE:5C:78:E0:11:A7:02:B9:C6:CB:48:BC:22:50:FC:20:07:27:82";
+    const GREETING: &[u8] = b"* OK IMAP4rev1 ready\r\n";
+
+    fn acceptor() -> TlsAcceptor {
+        let identity = Identity::from_pkcs8(CERT, KEY).unwrap();
+        return TlsAcceptor::new(identity).unwrap();
+    }
+
+    /// answers a single connection with the greeting, optionally after STARTTLS
+    fn mock_server(starttls: bool) -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        thread::spawn(move || {
+            let (mut tcp, _) = listener.accept().unwrap();
+            if starttls {
+                tcp.write_all(GREETING).unwrap();
+                let mut reader = BufReader::new(tcp.try_clone().unwrap());
+                for response in [
+                    "* CAPABILITY IMAP4rev1 STARTTLS\r\n{} OK done\r\n",
+                    "{} OK begin tls\r\n",
+                ] {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    let tag = line.split_whitespace().next().unwrap().to_string();
+                    tcp.write_all(response.replace("{}", &tag).as_bytes())
+                        .unwrap();
+                }
+                let _ = acceptor().accept(tcp); // the handshake fails, if the client rejects the certificate
+            } else if let Ok(mut tls) = acceptor().accept(tcp) {
+                tls.write_all(GREETING).unwrap();
+            }
+        });
+        return port;
+    }
+
+    fn imap_config(port: u16, tls: TlsConfig) -> IMAPConfig {
+        let yaml = format!(
+            "host: localhost\nport: {}\nusername: user\npassword: pass\n",
+            port
+        );
+        let mut config: IMAPConfig = serde_yaml::from_str(&yaml).unwrap();
+        config.tls = tls;
+        return config;
+    }
+
+    fn trusted(mode: TlsMode, pin: Option<&str>) -> TlsConfig {
+        return TlsConfig {
+            mode,
+            ca_file: Some("resources/test/localhost.pem".to_string()),
+            pin_sha256: pin.map(str::to_string),
+            ..Default::default()
+        };
+    }
+
+    #[test]
+    fn test_normalize_fingerprint() {
+        assert_eq!(normalize_fingerprint("AB:cd:01"), "abcd01");
+    }
+
+    #[test]
+    fn test_connect_tls() {
+        let port = mock_server(false);
+        let config = imap_config(port, trusted(TlsMode::Tls, Some(CERT_SHA256)));
+        assert!(connect_client(&config).is_ok());
+    }
+
+    #[test]
+    fn test_connect_tls_untrusted() {
+        let port = mock_server(false);
+        let config = imap_config(port, TlsConfig::default()); // the certificate isn't signed by a known CA
+        assert!(connect_client(&config).is_err());
+    }
+
+    #[test]
+    fn test_connect_tls_wrong_pin() {
+        let port = mock_server(false);
+        let wrong_pin = CERT_SHA256.replace("DD:DD", "00:00");
+        let config = imap_config(port, trusted(TlsMode::Tls, Some(&wrong_pin)));
+        let err = connect_client(&config).err().unwrap();
+        assert!(err.contains("pinned"), "{}", err);
+    }
+
+    #[test]
+    fn test_connect_starttls() {
+        let port = mock_server(true);
+        let config = imap_config(port, trusted(TlsMode::StartTls, Some(CERT_SHA256)));
+        assert!(connect_client(&config).is_ok());
+    }
+
+    #[test]
+    fn test_connect_plaintext() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        thread::spawn(move || {
+            let (mut tcp, _): (TcpStream, _) = listener.accept().unwrap();
+            tcp.write_all(GREETING).unwrap();
+        });
+        let tls = TlsConfig {
+            mode: TlsMode::Plaintext,
+            allow_plaintext: true,
+            ..Default::default()
+        };
+        assert!(connect_client(&imap_config(port, tls)).is_ok());
+    }
+}
@@ -0,0 +1,502 @@
+use std::{
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream},
+    sync::{
+        atomic::AtomicUsize,
+        mpsc::{Receiver, Sender},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use log::{debug, error, info, trace, warn};
+use sha2::Sha256;
+use subtle::ConstantTimeEq;
+
+use crate::{
+    config::config::{WebhookAuth, WebhookConfig},
+    models::emergency::Emergency,
+};
+
+use super::{
+    imap_multipart::{get_message_content, MailContent},
+    message::Message,
+    mime::header_value,
+    sources::{MailOutcome, QueuedMail, SessionSlot},
+};
+
+/// clients, that don't send anything for this long, are disconnected
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(30);
+/// the maximum length of the request line and of each header line
+const MAX_LINE_LENGTH: u64 = 8 * 1024;
+const MAX_HEADERS: usize = 100;
+/// in seconds, signed requests with an older (or newer) timestamp are rejected, so they can't be replayed later
+const MAX_TIMESTAMP_AGE: i64 = 5 * 60;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// None, if the text isn't an even number of hex digits
+fn decode_hex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) || !text.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    return (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&text[i..i + 2], 16).ok())
+        .collect();
+}
+
+/// Checks the signature of a request, the HMAC-SHA256 of `<timestamp>.<body>` keyed with the secret.
+///
+/// # description
+/// the timestamp (unix time in seconds) is signed as well, so a recorded request is only accepted
+/// for [MAX_TIMESTAMP_AGE]. The signature is compared in constant time.
+fn is_signed(secret: &str, timestamp: &str, signature: &str, body: &[u8]) -> bool {
+    let Ok(time) = timestamp.parse::<i64>() else {
+        return false;
+    };
+    if (Utc::now().timestamp() - time).abs() > MAX_TIMESTAMP_AGE {
+        return false;
+    }
+    let Some(signature) = decode_hex(signature) else {
+        return false;
+    };
+    let Ok(mut mac) = HmacSha256::new_from_slice(secret.as_bytes()) else {
+        return false;
+    };
+    mac.update(timestamp.as_bytes());
+    mac.update(b".");
+    mac.update(body);
+    return mac.verify_slice(&signature).is_ok();
+}
+
+/// Checks the secret of a request.
+///
+/// # description
+/// * token: the `Authorization: Bearer <secret>` header must contain the secret. As the secret is
+///   sent unencrypted, it is only allowed for a webhook listening on the loopback interface.
+/// * hmac: the `X-Signature: sha256=<hex>` header must contain the signature of the `X-Timestamp`
+///   header and the body (see [is_signed]). The signature proves, that the body wasn't changed,
+///   and the secret is never sent.
+fn is_authorized(webhook: &WebhookConfig, headers: &[(String, String)], body: &[u8]) -> bool {
+    return match webhook.auth {
+        WebhookAuth::Token => header_value(headers, "authorization")
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .is_some_and(|token| {
+                // compared in constant time, so the secret can't be guessed from the response times
+                token
+                    .trim()
+                    .as_bytes()
+                    .ct_eq(webhook.secret.as_bytes())
+                    .into()
+            }),
+        WebhookAuth::Hmac => {
+            let timestamp = header_value(headers, "x-timestamp").unwrap_or_default();
+            header_value(headers, "x-signature").is_some_and(|signature| {
+                let signature = signature.trim();
+                let signature = signature.strip_prefix("sha256=").unwrap_or(signature);
+                is_signed(&webhook.secret, timestamp.trim(), signature, body)
+            })
+        }
+    };
+}
+
+/// Converts the body into a queued mail, the receiver gets its outcome.
+///
+/// # description
+/// json (by content type or if the body starts with `{`) is read as an [Emergency], everything
+/// else is decoded like the text of a mail and parsed with the configured parser.
+fn queued_mail(
+    source: String,
+    body: &[u8],
+    content_type: Option<&str>,
+) -> Result<(QueuedMail, Receiver<MailOutcome>), String> {
+    let is_json = content_type.is_some_and(|t| t.to_ascii_lowercase().contains("json"))
+        || body.iter().find(|b| !b.is_ascii_whitespace()) == Some(&b'{');
+    if !is_json {
+        let content =
+            get_message_content(Message::from_text(body)).ok_or("the alarm text is empty")?;
+        return Ok(QueuedMail::new(source, content));
+    }
+    let ems: Emergency =
+        serde_json::from_slice(body).map_err(|e| format!("invalid emergency json: {}", e))?;
+    let content = MailContent {
+        uid: None,
+        headers: Vec::new(),
+        text: String::from_utf8_lossy(body).to_string(),
+        attachments: Vec::new(),
+    };
+    let (mut mail, outcome) = QueuedMail::new(source, content);
+    mail.emergency = Some(ems);
+    return Ok((mail, outcome));
+}
+
+fn reason(status: u16) -> &'static str {
+    return match status {
+        200 => "OK",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        411 => "Length Required",
+        413 => "Content Too Large",
+        422 => "Unprocessable Content",
+        431 => "Request Header Fields Too Large",
+        _ => "Service Unavailable",
+    };
+}
+
+fn respond(stream: &mut TcpStream, status: u16, text: &str) -> io::Result<()> {
+    trace!("webhook response: {} {}", status, text);
+    let response = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: text/plain; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        reason(status),
+        text.len(),
+        text
+    );
+    stream.write_all(response.as_bytes())?;
+    return stream.flush();
+}
+
+/// reads a line without the line ending, None if the client closed the connection or the line is too long
+fn read_line(reader: &mut BufReader<TcpStream>) -> io::Result<Option<String>> {
+    let mut line = Vec::new();
+    reader
+        .by_ref()
+        .take(MAX_LINE_LENGTH)
+        .read_until(b'\n', &mut line)?;
+    if !line.ends_with(b"\n") {
+        return Ok(None);
+    }
+    return Ok(Some(String::from_utf8_lossy(&line).trim_end().to_string()));
+}
+
+/// A connection of a client, posting a single alarm.
+struct Session {
+    webhook: Arc<WebhookConfig>,
+    queue: Sender<QueuedMail>,
+    peer: String,
+}
+
+impl Session {
+    /// queues the alarm and waits for its outcome, so the client can retry, if it wasn't processed
+    fn deliver(&self, body: &[u8], content_type: Option<&str>) -> (u16, String) {
+        let source = self.webhook.source_name();
+        let (mut mail, outcome) = match queued_mail(source, body, content_type) {
+            Ok(queued) => queued,
+            Err(e) => return (400, e),
+        };
+        info!("received alarm via webhook from {}", self.peer);
+        mail.authenticated = true;
+        if self.queue.send(mail).is_err() {
+            return (503, "shutting down".to_string());
+        }
+        let (status, text) = match outcome.recv() {
+            Ok(MailOutcome::Printed) => (200, "printed"),
+            Ok(MailOutcome::Duplicate) => (200, "duplicate"),
+            Ok(MailOutcome::Failed) => (422, "alarm couldn't be parsed"),
+            Ok(MailOutcome::Rejected) => (403, "rejected"),
+            Err(_) => (503, "alarm wasn't processed, try again later"),
+        };
+        return (status, text.to_string());
+    }
+
+    /// Reads the request and answers it, the connection is closed afterwards.
+    ///
+    /// # description
+    /// only requests with a Content-Length are accepted, the size is checked before the body is
+    /// read. Tokens are checked before reading the body as well, signatures can only be checked after.
+    fn run(&self, stream: TcpStream) -> io::Result<()> {
+        stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+        stream.set_write_timeout(Some(CLIENT_TIMEOUT))?;
+        let mut reader = BufReader::new(stream);
+        let Some(request_line) = read_line(&mut reader)? else {
+            return Ok(());
+        };
+        trace!("webhook request of {}: {}", self.peer, request_line);
+        let mut parts = request_line.split_whitespace();
+        let (Some(method), Some(target), Some(_version)) =
+            (parts.next(), parts.next(), parts.next())
+        else {
+            return respond(reader.get_mut(), 400, "invalid request line");
+        };
+        let mut headers = Vec::new();
+        loop {
+            let Some(line) = read_line(&mut reader)? else {
+                return respond(reader.get_mut(), 431, "header line too long");
+            };
+            if line.is_empty() {
+                break;
+            }
+            if headers.len() >= MAX_HEADERS {
+                return respond(reader.get_mut(), 431, "too many headers");
+            }
+            let Some((name, value)) = line.split_once(':') else {
+                return respond(reader.get_mut(), 400, "invalid header");
+            };
+            headers.push((name.trim().to_ascii_lowercase(), value.trim().to_string()));
+        }
+
+        let path = target.split('?').next().unwrap_or(target);
+        if path != self.webhook.path {
+            return respond(reader.get_mut(), 404, "not found");
+        }
+        if method != "POST" {
+            return respond(reader.get_mut(), 405, "only POST is allowed");
+        }
+        let length = match header_value(&headers, "content-length").map(str::parse::<usize>) {
+            _ if header_value(&headers, "transfer-encoding").is_some() => None,
+            Some(Ok(length)) => Some(length),
+            _ => None,
+        };
+        let Some(length) = length else {
+            return respond(reader.get_mut(), 411, "Content-Length required");
+        };
+        if length > self.webhook.max_size {
+            return respond(reader.get_mut(), 413, "alarm too large");
+        }
+        if self.webhook.auth == WebhookAuth::Token && !is_authorized(&self.webhook, &headers, &[]) {
+            warn!("webhook: unauthorized request of {}", self.peer);
+            return respond(reader.get_mut(), 401, "unauthorized");
+        }
+        if header_value(&headers, "expect").is_some_and(|e| e.eq_ignore_ascii_case("100-continue"))
+        {
+            reader
+                .get_mut()
+                .write_all(b"HTTP/1.1 100 Continue\r\n\r\n")?;
+        }
+
+        let mut body = Vec::with_capacity(length);
+        reader.by_ref().take(length as u64).read_to_end(&mut body)?;
+        if body.len() < length {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        if !is_authorized(&self.webhook, &headers, &body) {
+            warn!("webhook: unauthorized request of {}", self.peer);
+            return respond(reader.get_mut(), 401, "unauthorized");
+        }
+        let (status, text) = self.deliver(&body, header_value(&headers, "content-type"));
+        return respond(reader.get_mut(), status, &text);
+    }
+}
+
+/// accepts clients until the listener fails, each client is handled in its own thread, at most `max_sessions` at once
+fn serve(listener: TcpListener, webhook: WebhookConfig, queue: Sender<QueuedMail>) {
+    let webhook = Arc::new(webhook);
+    let sessions = Arc::new(AtomicUsize::new(0));
+    for stream in listener.incoming() {
+        let mut stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                warn!("{}: couldn't accept client: {}", webhook.source_name(), e);
+                continue;
+            }
+        };
+        let peer = stream
+            .peer_addr()
+            .map(|addr| addr.to_string())
+            .unwrap_or_else(|_| "unknown".to_string());
+        debug!("{}: client {} connected", webhook.source_name(), peer);
+        let Some(slot) = SessionSlot::acquire(&sessions, webhook.max_sessions) else {
+            warn!(
+                "{}: too many requests, refusing {}",
+                webhook.source_name(),
+                peer
+            );
+            let _ = respond(&mut stream, 503, "too many requests, try again later");
+            continue;
+        };
+        let session = Session {
+            webhook: webhook.clone(),
+            queue: queue.clone(),
+            peer,
+        };
+        let spawned = thread::Builder::new()
+            .name(format!("{} {}", webhook.source_name(), session.peer))
+            .spawn(move || {
+                let _slot = slot;
+                if let Err(e) = session.run(stream) {
+                    debug!("webhook request of {} failed: {}", session.peer, e);
+                }
+            });
+        if let Err(e) = spawned {
+            error!("couldn't start webhook session: {}", e);
+        }
+    }
+}
+
+/// Listens for alarms posted over HTTP and queues them like received mails.
+///
+/// # description
+/// the body is either the `~~` text of the dispatch centre or the json form of an [Emergency]. The
+/// response is only sent, after the alarm was processed: `200` if it was printed (or is a duplicate),
+/// `422` if it couldn't be parsed and `503` if it should be posted again later.
+pub fn watch_webhook(webhook: WebhookConfig, queue: Sender<QueuedMail>) {
+    let name = webhook.source_name();
+    let listener = match TcpListener::bind(&webhook.listen) {
+        Ok(listener) => listener,
+        Err(e) => {
+            error!("{}: couldn't listen on {}: {}", name, webhook.listen, e);
+            return;
+        }
+    };
+    info!("Bereit zum Empfangen der Alarme ({}).", name);
+    serve(listener, webhook, queue);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{Read, Write},
+        net::{SocketAddr, TcpListener, TcpStream},
+        sync::mpsc,
+        thread,
+    };
+
+    use chrono::Utc;
+    use hmac::Mac;
+
+    use super::{decode_hex, serve, HmacSha256};
+    use crate::{
+        config::config::WebhookConfig,
+        connection::sources::{MailOutcome, QueuedMail},
+    };
+
+    const EMS: &str = include_str!("../../examples/emergency_simple.txt");
+    const EMS_JSON: &str = include_str!("../../examples/emergency_simple.json");
+
+    fn start(yaml: &str) -> (SocketAddr, mpsc::Receiver<QueuedMail>) {
+        let webhook: WebhookConfig = serde_yaml::from_str(yaml).unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (queue, receiver) = mpsc::channel();
+        thread::spawn(move || serve(listener, webhook, queue));
+        return (addr, receiver);
+    }
+
+    /// the whole response, the connection is closed by the server
+    fn post(addr: SocketAddr, path: &str, headers: &[String], body: &str) -> String {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        let mut request = format!(
+            "POST {} HTTP/1.1\r\nHost: localhost\r\nContent-Length: {}\r\n",
+            path,
+            body.len()
+        );
+        for header in headers {
+            request.push_str(&format!("{}\r\n", header));
+        }
+        request.push_str("\r\n");
+        request.push_str(body);
+        stream.write_all(request.as_bytes()).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        return response;
+    }
+
+    /// the signature headers of a request sent at `time` (unix time in seconds)
+    fn signed(secret: &str, time: i64, body: &str) -> Vec<String> {
+        let mut mac = HmacSha256::new_from_slice(secret.as_bytes()).unwrap();
+        mac.update(format!("{}.{}", time, body).as_bytes());
+        let signature: String = mac
+            .finalize()
+            .into_bytes()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+        return vec![
+            format!("X-Timestamp: {}", time),
+            format!("X-Signature: sha256={}", signature),
+        ];
+    }
+
+    #[test]
+    fn test_decode_hex() {
+        assert_eq!(decode_hex("00ff7A"), Some(vec![0x00, 0xff, 0x7a]));
+        assert_eq!(decode_hex("0"), None);
+        assert_eq!(decode_hex("zz"), None);
+        assert_eq!(decode_hex("+1"), None);
+        assert_eq!(decode_hex("ää"), None);
+    }
+
+    #[test]
+    fn test_token() {
+        let (addr, receiver) = start("listen: 127.0.0.1:8080\nsecret: geheim\nauth: token");
+        let token = vec!["Authorization: Bearer geheim".to_string()];
+        assert!(post(addr, "/alarm", &[], EMS).starts_with("HTTP/1.1 401"));
+        let wrong = vec!["Authorization: Bearer falsch".to_string()];
+        assert!(post(addr, "/alarm", &wrong, EMS).starts_with("HTTP/1.1 401"));
+        assert!(post(addr, "/other", &token, EMS).starts_with("HTTP/1.1 404"));
+
+        let processing = thread::spawn(move || {
+            let mail = receiver.recv().unwrap();
+            assert!(mail.authenticated);
+            assert!(mail.emergency.is_none());
+            assert!(mail.content.text.contains("322088295"));
+            mail.finish(MailOutcome::Printed);
+        });
+        let response = post(addr, "/alarm?drill=1", &token, EMS);
+        assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+        assert!(response.ends_with("printed"));
+        processing.join().unwrap();
+    }
+
+    #[test]
+    fn test_hmac_json() {
+        let (addr, receiver) = start("listen: 127.0.0.1:0\npath: /els\nsecret: geheim\nauth: hmac");
+        let now = Utc::now().timestamp();
+        let mut headers = signed("geheim", now, EMS_JSON);
+        headers.push("Content-Type: application/json".to_string());
+        // the signature doesn't match a changed body
+        let changed = EMS_JSON.replace("322088295", "1");
+        assert!(post(addr, "/els", &headers, &changed).starts_with("HTTP/1.1 401"));
+        // nor a changed timestamp
+        let mut replayed = headers.clone();
+        replayed[0] = format!("X-Timestamp: {}", now + 1);
+        assert!(post(addr, "/els", &replayed, EMS_JSON).starts_with("HTTP/1.1 401"));
+        // old requests can't be replayed
+        let stale = signed("geheim", now - 10 * 60, EMS_JSON);
+        assert!(post(addr, "/els", &stale, EMS_JSON).starts_with("HTTP/1.1 401"));
+        let unsigned = vec!["X-Signature: sha256=00".to_string()];
+        assert!(post(addr, "/els", &unsigned, EMS_JSON).starts_with("HTTP/1.1 401"));
+        let invalid = signed("geheim", now, "{");
+        assert!(post(addr, "/els", &invalid, "{").starts_with("HTTP/1.1 400"));
+
+        let processing = thread::spawn(move || {
+            let mail = receiver.recv().unwrap();
+            assert_eq!(mail.emergency.as_ref().unwrap().emergency_number, 322088295);
+            mail.finish(MailOutcome::Duplicate);
+        });
+        let response = post(addr, "/els", &headers, EMS_JSON);
+        assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+        assert!(response.ends_with("duplicate"));
+        processing.join().unwrap();
+    }
+
+    #[test]
+    fn test_max_sessions() {
+        let (addr, receiver) =
+            start("listen: 127.0.0.1:8080\nsecret: geheim\nauth: token\nmax_sessions: 1");
+        let token = vec!["Authorization: Bearer geheim".to_string()];
+        let first = {
+            let token = token.clone();
+            thread::spawn(move || post(addr, "/alarm", &token, EMS))
+        };
+        // the first request waits for its outcome
+        let mail = receiver.recv().unwrap();
+        // refused without reading the request, a request sent meanwhile could reset the connection
+        let mut response = String::new();
+        TcpStream::connect(addr)
+            .unwrap()
+            .read_to_string(&mut response)
+            .unwrap();
+        assert!(response.starts_with("HTTP/1.1 503"), "{}", response);
+
+        mail.finish(MailOutcome::Printed);
+        assert!(first.join().unwrap().starts_with("HTTP/1.1 200"));
+    }
+}
@@ -1,90 +1,175 @@
-use std::panic::catch_unwind;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::path::Path;
 use std::str::FromStr;
-use std::thread::sleep;
-use std::time::Duration;
 
 use config::logging;
 use config::Config;
 
 use log::trace;
-use log::{debug, error, info};
+use log::{debug, error, info, warn};
 
-use crate::config::config::IMAPModes::Idle;
 use ctrlc;
 
-use crate::connection::imap::IMAPConnection;
 use crate::connection::message::mail_str_decode_unicode;
+use crate::connection::sender_filter::{check_sender, notify_admin};
+use crate::connection::sources::{watch_sources, MailOutcome, QueuedMail};
+use crate::models::alarm_history::{AlarmHistory, AlarmStatus};
 use crate::models::emergency::Emergency;
+use crate::models::emergency_diff::EmergencyDiff;
+use crate::models::emergency_parser::{parse_mail, validate_emergency};
+use crate::models::emergency_writing::write_drill_mail;
+use crate::models::parse_report::{ParseReport, Severity};
 use crate::printing::com;
-use crate::printing::print_ems::print_emergency;
+use crate::printing::print_ems::{print_emergency, print_update};
 use crate::winprio::set_process_priority;
 
 mod config;
 mod connection;
+mod metrics;
 mod models;
 mod printing;
 mod winprio;
 
-fn poll_new_mails(
-    connection: &mut IMAPConnection,
-    interval: Duration,
-) -> Result<Vec<Option<String>>, ()> {
-    loop {
-        trace!("polling tick!");
-        let res = connection.load_new_mails();
-        if res.is_err() || res.as_ref().is_ok_and(|v| !v.is_empty()) {
-            break res; // either we have new mails or an error
-        }
-        sleep(interval);
+/// logs the issues found while parsing a mail. Incomplete mails are additionally logged
+/// as yaml, so they can be picked up by monitoring tools.
+fn log_parse_report(report: &ParseReport) {
+    for issue in &report.issues {
+        debug!("{}", issue);
+    }
+    if !report.is_incomplete() {
+        return;
+    }
+    match serde_yaml::to_string(report) {
+        Ok(yaml) => warn!("alarm mail incomplete, parse report:\n{}", yaml),
+        Err(e) => error!("couldn't serialize parse report: {}", e),
     }
 }
 
-fn run_mail_loop(config: &Config) {
-    let mut connection = IMAPConnection::connect(config).expect("couldn't connect to imap server");
-    info!("Bereit zum Empfangen der Alarmemails.");
-    loop {
-        // todo: move config check out of loop
-        let new_mails = if config.imap.mode.method == Idle {
-            connection.reconnecting_await_new_mail()
-        } else {
-            poll_new_mails(&mut connection, config.interval_as_duration())
-        };
-        let Ok(new_mails) = new_mails else {
-            connection.end();
-            info!("reconnecting to imap server");
-            let reconnection = IMAPConnection::connect(config);
-            let Ok(reconnection) = reconnection else {
-                error!("couldn't reconnect to imap server");
-                return;
-            };
-            info!("bereit zum empfangen der alarmemails.");
-            connection = reconnection; // will never panic, see check above
-            continue;
-        };
+/// the reason sent to the admin, when the parser rejected a mail
+fn parse_rejection_reason(report: &ParseReport) -> String {
+    let issues: Vec<String> = report
+        .issues
+        .iter()
+        .filter(|issue| issue.severity >= Severity::Warning)
+        .map(|issue| issue.to_string())
+        .collect();
+    return format!(
+        "alarm mail rejected (unknown format or strict parsing mode): {}",
+        issues.join("; ")
+    );
+}
+
+/// checks, parses and prints a mail of the queue, reporting the outcome back to its source
+fn process_mail(mail: QueuedMail, history: &mut AlarmHistory, config: &Config) {
+    let content = &mail.content;
+    if mail.authenticated {
+        debug!("sender was authenticated by {}", mail.source);
+    } else if let Err(reason) = check_sender(&content.headers, &config.sender_filter) {
+        error!("alarm mail rejected: {}", reason);
+        notify_admin(&config.sender_filter, &reason);
+        mail.finish(MailOutcome::Rejected);
+        return;
+    }
+    let mail_str = content.text.as_str();
 
-        for mail in new_mails {
-            let Some(mail_str) = mail else {
-                debug!("mail is none");
-                continue;
-            };
-
-            let mail_str = mail_str_decode_unicode(mail_str.as_str());
-            trace!("decoded mail: {}", mail_str);
-            #[cfg(debug_assertions)]
-            {
-                use std::fs::write;
-                write("debug_message_escaped.txt", mail_str.as_str())
-                    .expect("couldn't write debug message");
+    // the body was already decoded (transfer encoding and charset) by get_message_body
+    trace!("decoded mail from {}: {}", mail.source, mail_str);
+    #[cfg(debug_assertions)]
+    {
+        use std::fs::write;
+        write("debug_message_escaped.txt", mail_str).expect("couldn't write debug message");
+    }
+    let parsed = match &mail.emergency {
+        // received as json, nothing to parse, but the mandatory fields are checked like for mails
+        Some(ems) => validate_emergency(ems.clone(), &config.parsing).to_lenient_result(),
+        None => parse_mail(mail_str, &config.parsing).to_lenient_result(),
+    };
+    let (ems, report) = match parsed {
+        Ok(parsed) => parsed,
+        Err(report) => {
+            let reason = parse_rejection_reason(&report);
+            error!("{}", reason);
+            log_parse_report(&report);
+            notify_admin(&config.sender_filter, &reason);
+            mail.finish(MailOutcome::Failed);
+            return;
+        }
+    };
+    debug!("decoded ems id {:?}", ems.emergency_number);
+    match history.check(&ems) {
+        AlarmStatus::New => {}
+        AlarmStatus::Duplicate => {
+            info!(
+                "skipping duplicate of emergency {} from {}",
+                ems.emergency_number, mail.source
+            );
+            mail.finish(MailOutcome::Duplicate);
+            return;
+        }
+        AlarmStatus::Update(previous) => {
+            info!("received update of emergency {}", ems.emergency_number);
+            let diff = EmergencyDiff::between(&previous, &ems);
+            if diff.is_empty() {
+                info!("update contains no relevant changes, not printing it");
+                history.record(&ems);
+                mail.finish(MailOutcome::Duplicate);
+            } else {
+                history.record(&ems);
+                let printed = catch_unwind(AssertUnwindSafe(|| print_update(&ems, &diff, config)));
+                finish_printed(mail, printed, &ems, config);
             }
-            let ems = Emergency::from_str(mail_str.as_str()).unwrap();
-            debug!("decoded ems id {:?}", ems.emergency_number);
-            print_emergency(ems, &config);
+            return;
         }
     }
+    log_parse_report(&report);
+    // remembered before printing, so a printout that failed halfway isn't repeated by the retries of the source
+    history.record(&ems);
+    let printed = catch_unwind(AssertUnwindSafe(|| {
+        print_emergency(ems.clone(), &report, &content.attachments, config)
+    }));
+    finish_printed(mail, printed, &ems, config);
+}
+
+/// Reports the outcome of the printout to the source.
+///
+/// # description
+/// a printout, that failed unexpectedly (panic), may already be printed partially. It is reported as
+/// failed to the admin instead of being fetched and printed again.
+fn finish_printed(
+    mail: QueuedMail,
+    printed: std::thread::Result<()>,
+    ems: &Emergency,
+    config: &Config,
+) {
+    if printed.is_ok() {
+        mail.finish(MailOutcome::Printed);
+        return;
+    }
+    let reason = format!(
+        "printing emergency {} from {} failed",
+        ems.emergency_number, mail.source
+    );
+    error!("{}", reason);
+    notify_admin(&config.sender_filter, &reason);
+    mail.finish(MailOutcome::Failed);
 }
 
 fn main() {
     logging::init_logging();
+
+    // `--write-drill <emergency.json> <mail.txt>` writes a drill alarm instead of starting the service
+    let args: Vec<String> = std::env::args().collect();
+    if let [_, flag, json_path, mail_path] = args.as_slice() {
+        if flag == "--write-drill" {
+            if let Err(e) = write_drill_mail(Path::new(json_path), Path::new(mail_path)) {
+                error!("{}", e);
+                std::process::exit(1);
+            }
+            info!("wrote drill alarm to {}", mail_path);
+            return;
+        }
+    }
+
     info!("starting up");
     let config_path = std::env::var("EM_CONFIG").unwrap_or("config.yaml".to_string());
     trace!("config path: {}", config_path);
@@ -107,22 +192,128 @@ fn main() {
     let ems = include_str!("../examples/emergency_many_units.txt");
     let ems = mail_str_decode_unicode(ems);
     let ems = Emergency::from_str(ems.as_str()).unwrap();
-    print_emergency(ems, &config);
-    let mut is_first = true;
-    loop {
-        let res = catch_unwind(|| run_mail_loop(&config)); // catch panics and restart
-        if res.is_err() {
-            info!("caught panic, restarting");
-            trace!("panic: {:?}", res);
-            if is_first {
-                is_first = false;
-                continue;
-            }
-            // wait for 10 seconds before restarting
-            // this is to slow a panic loop down, so that the log messages can be read
-            sleep(Duration::from_secs(10));
-        } else {
-            is_first = true;
+    print_emergency(ems, &ParseReport::default(), &[], &config);
+    metrics::init(config.metrics_file.as_deref());
+    let queue = watch_sources(&config);
+    let mut history = AlarmHistory::new(config.deduplication.window_as_duration());
+    for mail in queue {
+        // catch panics, the source fetches the mail again, as it didn't get an outcome
+        let res = catch_unwind(AssertUnwindSafe(|| {
+            process_mail(mail, &mut history, &config)
+        }));
+        if let Err(panic) = res {
+            info!("caught panic while processing a mail");
+            trace!("panic: {:?}", panic);
         }
     }
+    error!("all sources stopped");
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, str::FromStr, time::Duration};
+
+    use crate::config::Config;
+    use crate::connection::imap_multipart::MailContent;
+    use crate::connection::message::mail_str_decode_unicode;
+    use crate::connection::sources::{MailOutcome, QueuedMail};
+    use crate::models::alarm_history::AlarmHistory;
+    use crate::models::emergency::Emergency;
+
+    use super::{finish_printed, process_mail};
+
+    const EMS: &str = include_str!("../examples/emergency_simple.txt");
+
+    #[cfg(unix)]
+    #[test]
+    fn test_strict_rejection_notifies_admin() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = env::temp_dir().join("emergency_mail_test_strict_notify");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let notified = dir.join("notified.txt");
+        let script = dir.join("notify.sh");
+        fs::write(
+            &script,
+            format!("#!/bin/sh\nprintf '%s' \"$1\" > {:?}\n", notified),
+        )
+        .unwrap();
+        fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+        let yaml = format!(
+            "spool:\n  path: {:?}\nparsing:\n  mode: strict\nsender_filter:\n  notify_command: {:?}\n\
+printing:\n  min_copies: 1\n  printer: \"\"\n  amt: 1\n  sumatra_path: \"\"\n",
+            dir, script
+        );
+        let config = Config::from_str(&yaml).unwrap();
+
+        // the town is mandatory
+        let text = EMS.replace("~~Ort~~Brandenburg an der Havel~~", "");
+        let content = MailContent {
+            uid: None,
+            headers: Vec::new(),
+            text: mail_str_decode_unicode(&text),
+            attachments: Vec::new(),
+        };
+        let (mail, outcome) = QueuedMail::new("test".to_string(), content);
+        let mut history = AlarmHistory::new(Duration::from_secs(60));
+        process_mail(mail, &mut history, &config);
+
+        assert_eq!(outcome.recv().unwrap(), MailOutcome::Failed);
+        let reason = fs::read_to_string(&notified).unwrap();
+        assert!(reason.starts_with("alarm mail rejected"), "{}", reason);
+        assert!(
+            reason.contains("missing mandatory field Town"),
+            "{}",
+            reason
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_strict_json_rejection() {
+        let yaml = "spool:\n  path: spool\nparsing:\n  mode: strict\n\
+printing:\n  min_copies: 1\n  printer: \"\"\n  amt: 1\n  sumatra_path: \"\"\n";
+        let config = Config::from_str(yaml).unwrap();
+
+        // alarms received as json must have the mandatory fields as well
+        let mut ems = Emergency::from_str(&mail_str_decode_unicode(EMS)).unwrap();
+        ems.town = String::new();
+        let content = MailContent {
+            uid: None,
+            headers: Vec::new(),
+            text: serde_json::to_string(&ems).unwrap(),
+            attachments: Vec::new(),
+        };
+        let (mut mail, outcome) = QueuedMail::new("test".to_string(), content);
+        mail.emergency = Some(ems);
+        mail.authenticated = true;
+        let mut history = AlarmHistory::new(Duration::from_secs(60));
+        process_mail(mail, &mut history, &config);
+
+        assert_eq!(outcome.recv().unwrap(), MailOutcome::Failed);
+    }
+
+    #[test]
+    fn test_failed_printout() {
+        let yaml = "spool:\n  path: spool\n\
+printing:\n  min_copies: 1\n  printer: \"\"\n  amt: 1\n  sumatra_path: \"\"\n";
+        let config = Config::from_str(yaml).unwrap();
+        let ems = Emergency::from_str(&mail_str_decode_unicode(EMS)).unwrap();
+        let content = || MailContent {
+            uid: None,
+            headers: Vec::new(),
+            text: String::new(),
+            attachments: Vec::new(),
+        };
+
+        let (mail, outcome) = QueuedMail::new("test".to_string(), content());
+        finish_printed(mail, Ok(()), &ems, &config);
+        assert_eq!(outcome.recv().unwrap(), MailOutcome::Printed);
+
+        // the source must not fetch it again, it might have been printed partially
+        let (mail, outcome) = QueuedMail::new("test".to_string(), content());
+        finish_printed(mail, Err(Box::new("printer failed")), &ems, &config);
+        assert_eq!(outcome.recv().unwrap(), MailOutcome::Failed);
+    }
 }
@@ -0,0 +1,137 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    fs,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use log::warn;
+
+/// The kind of a metric, as declared in the Prometheus text format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MetricKind {
+    /// only increases, e.g. the number of mode switches
+    Counter,
+    /// the current value, e.g. whether a source is polling
+    Gauge,
+}
+
+impl MetricKind {
+    fn name(&self) -> &'static str {
+        return match self {
+            MetricKind::Counter => "counter",
+            MetricKind::Gauge => "gauge",
+        };
+    }
+}
+
+/// The metrics of all sources, identified by the metric name and the source.
+#[derive(Default)]
+struct Metrics {
+    file: Option<PathBuf>,
+    values: BTreeMap<&'static str, (MetricKind, BTreeMap<String, u64>)>,
+}
+
+impl Metrics {
+    /// renders the metrics in the Prometheus text format
+    fn render(&self) -> String {
+        let mut text = String::new();
+        for (name, (kind, sources)) in &self.values {
+            let _ = writeln!(text, "# TYPE {} {}", name, kind.name());
+            for (source, value) in sources {
+                let source = source.replace('\\', "\\\\").replace('"', "\\\"");
+                let _ = writeln!(text, "{}{{source=\"{}\"}} {}", name, source, value);
+            }
+        }
+        return text;
+    }
+
+    fn update(
+        &mut self,
+        name: &'static str,
+        kind: MetricKind,
+        source: &str,
+        f: impl Fn(u64) -> u64,
+    ) {
+        let (_, sources) = self
+            .values
+            .entry(name)
+            .or_insert_with(|| (kind, BTreeMap::new()));
+        let value = sources.entry(source.to_string()).or_insert(0);
+        *value = f(*value);
+        if let Some(file) = &self.file {
+            Metrics::save(file, &self.render());
+        }
+    }
+
+    /// replaces the file only after it was written completely, so the collector never reads a partial file
+    fn save(path: &Path, text: &str) {
+        let tmp_path = path.with_extension("tmp");
+        let res = fs::write(&tmp_path, text).and_then(|_| fs::rename(&tmp_path, path));
+        if let Err(e) = res {
+            warn!("couldn't write metrics to {:?}: {}", path, e);
+        }
+    }
+}
+
+static METRICS: Mutex<Option<Metrics>> = Mutex::new(None);
+
+fn with_metrics<T>(f: impl FnOnce(&mut Metrics) -> T) -> T {
+    let mut metrics = METRICS.lock().unwrap_or_else(|e| e.into_inner());
+    return f(metrics.get_or_insert_with(Metrics::default));
+}
+
+/// sets the file the metrics are written to on every change, without a file they are only kept in memory
+pub fn init(file: Option<&str>) {
+    with_metrics(|metrics| metrics.file = file.map(PathBuf::from));
+}
+
+pub fn increment(name: &'static str, source: &str) {
+    with_metrics(|metrics| metrics.update(name, MetricKind::Counter, source, |v| v + 1));
+}
+
+pub fn set_gauge(name: &'static str, source: &str, value: u64) {
+    with_metrics(|metrics| metrics.update(name, MetricKind::Gauge, source, |_| value));
+}
+
+/// the current value of a metric, None if it was never set
+#[cfg(test)]
+pub fn get(name: &str, source: &str) -> Option<u64> {
+    return with_metrics(|metrics| {
+        metrics
+            .values
+            .get(name)
+            .and_then(|(_, sources)| sources.get(source).copied())
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MetricKind, Metrics};
+
+    #[test]
+    fn test_render() {
+        let mut metrics = Metrics::default();
+        metrics.update(
+            "test_switches_total",
+            MetricKind::Counter,
+            "a@host/INBOX",
+            |v| v + 1,
+        );
+        metrics.update(
+            "test_switches_total",
+            MetricKind::Counter,
+            "a@host/INBOX",
+            |v| v + 1,
+        );
+        metrics.update("test_polling", MetricKind::Gauge, "b@host/\"Alarm\"", |_| 1);
+        assert_eq!(
+            metrics.render(),
+            "# TYPE test_polling gauge\n\
+test_polling{source=\"b@host/\\\"Alarm\\\"\"} 1\n\
+# TYPE test_switches_total counter\n\
+test_switches_total{source=\"a@host/INBOX\"} 2\n"
+        );
+    }
+}
@@ -1,6 +1,16 @@
 pub mod emergency;
+pub mod emergency_diff;
+pub mod emergency_field;
+pub mod emergency_parser;
 pub mod emergency_parsing;
+pub mod emergency_writing;
+pub mod parse_report;
 
+pub mod alarm_history;
+pub mod alarm_time;
+pub mod alarmfax_parsing;
+pub mod attachment;
+pub mod coordinates;
 pub mod either;
 pub mod radio_identifier;
 pub mod recoverable;
@@ -0,0 +1,236 @@
+use std::{
+    collections::{hash_map::DefaultHasher, VecDeque},
+    hash::{Hash, Hasher},
+    time::{Duration, Instant},
+};
+
+use chrono::NaiveDateTime;
+
+use super::emergency::Emergency;
+
+/// How a received alarm relates to the alarms received before.
+#[derive(Debug, PartialEq)]
+pub enum AlarmStatus {
+    New,
+    /// the same alarm was already received (resend or another source)
+    Duplicate,
+    /// the alarm was already received, but its content changed (e.g. additional units).
+    /// Contains the latest previous version.
+    Update(Box<Emergency>),
+}
+
+struct SeenAlarm {
+    emergency_number: u64,
+    alarm_time: NaiveDateTime,
+    content_hash: u64,
+    received: Instant,
+    ems: Emergency,
+}
+
+impl SeenAlarm {
+    /// alarms without emergency number and alarm time can only be recognized by their content
+    fn is_identifiable(&self) -> bool {
+        return self.emergency_number != 0 || self.alarm_time != NaiveDateTime::default();
+    }
+
+    fn is_same_alarm(&self, other: &SeenAlarm) -> bool {
+        return self.is_identifiable()
+            && self.emergency_number == other.emergency_number
+            && self.alarm_time == other.alarm_time;
+    }
+}
+
+/// Remembers the recently received alarms, so that an alarm is only printed once, even if the
+/// dispatch centre sends it again or it is received on several sources.
+///
+/// # description
+/// alarms are identified by their emergency number and alarm time. The content is compared using
+/// the parsed emergency, so differences of the mail formatting (line endings, empty lines) are ignored.
+pub struct AlarmHistory {
+    window: Duration,
+    seen: VecDeque<SeenAlarm>,
+}
+
+impl AlarmHistory {
+    pub fn new(window: Duration) -> Self {
+        return AlarmHistory {
+            window,
+            seen: VecDeque::new(),
+        };
+    }
+
+    fn content_hash(ems: &Emergency) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        serde_json::to_string(ems)
+            .expect("emergency is always serializable")
+            .hash(&mut hasher);
+        return hasher.finish();
+    }
+
+    fn seen_alarm(ems: &Emergency, received: Instant) -> SeenAlarm {
+        return SeenAlarm {
+            emergency_number: ems.emergency_number,
+            alarm_time: ems.alarm_time,
+            content_hash: AlarmHistory::content_hash(ems),
+            received,
+            ems: ems.clone(),
+        };
+    }
+
+    /// forgets the alarms received before the window
+    fn expire(&mut self, now: Instant) {
+        while let Some(alarm) = self.seen.front() {
+            if now.duration_since(alarm.received) <= self.window {
+                break;
+            }
+            self.seen.pop_front();
+        }
+    }
+
+    /// Classifies the alarm using the alarms recorded within the configured window.
+    ///
+    /// # description
+    /// the alarm isn't remembered, call [AlarmHistory::record] once it is handled. Alarms, that are
+    /// checked but not handled (e.g. the processing stopped), are new when their source fetches them again.
+    /// With an empty window, every alarm is new.
+    pub fn check(&mut self, ems: &Emergency) -> AlarmStatus {
+        let now = Instant::now();
+        self.expire(now);
+        if self.window.is_zero() {
+            return AlarmStatus::New;
+        }
+
+        let alarm = AlarmHistory::seen_alarm(ems, now);
+        if self
+            .seen
+            .iter()
+            .any(|a| a.content_hash == alarm.content_hash)
+        {
+            return AlarmStatus::Duplicate;
+        }
+        let previous = self.seen.iter().rev().find(|a| a.is_same_alarm(&alarm));
+        return match previous {
+            Some(previous) => AlarmStatus::Update(Box::new(previous.ems.clone())),
+            None => AlarmStatus::New,
+        };
+    }
+
+    /// Remembers the handled alarm for the configured window.
+    ///
+    /// # description
+    /// an update is remembered as well, so that a resend of the update is a duplicate.
+    pub fn record(&mut self, ems: &Emergency) {
+        let now = Instant::now();
+        self.expire(now);
+        if self.window.is_zero() {
+            return;
+        }
+        let alarm = AlarmHistory::seen_alarm(ems, now);
+        if self
+            .seen
+            .iter()
+            .all(|a| a.content_hash != alarm.content_hash)
+        {
+            self.seen.push_back(alarm);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{thread::sleep, time::Duration};
+
+    use chrono::NaiveDate;
+
+    use super::{AlarmHistory, AlarmStatus};
+    use crate::models::{either::Either, emergency::Emergency};
+
+    fn emergency(number: u64) -> Emergency {
+        return Emergency {
+            emergency_number: number,
+            alarm_time: NaiveDate::from_ymd_opt(2024, 5, 1)
+                .unwrap()
+                .and_hms_opt(12, 30, 0)
+                .unwrap(),
+            keyword: "B:BMA".to_string(),
+            dispatched_units: vec![Either::Right("FL BRB 01/16-01".to_string())],
+            ..Default::default()
+        };
+    }
+
+    /// the alarm is printed successfully
+    fn receive(history: &mut AlarmHistory, ems: &Emergency) -> AlarmStatus {
+        let status = history.check(ems);
+        history.record(ems);
+        return status;
+    }
+
+    #[test]
+    fn test_duplicate_and_update() {
+        let mut history = AlarmHistory::new(Duration::from_secs(60));
+        assert_eq!(receive(&mut history, &emergency(1234)), AlarmStatus::New);
+        assert_eq!(
+            receive(&mut history, &emergency(1234)),
+            AlarmStatus::Duplicate
+        );
+        assert_eq!(receive(&mut history, &emergency(1235)), AlarmStatus::New);
+
+        // the resend alarms additional units
+        let mut update = emergency(1234);
+        update
+            .dispatched_units
+            .push(Either::Right("FL BRB 01/46-01".to_string()));
+        assert_eq!(
+            receive(&mut history, &update),
+            AlarmStatus::Update(Box::new(emergency(1234)))
+        );
+        assert_eq!(receive(&mut history, &update), AlarmStatus::Duplicate);
+
+        // the same emergency number at another time is another alarm
+        let mut other = emergency(1234);
+        other.alarm_time += chrono::Duration::days(1);
+        assert_eq!(receive(&mut history, &other), AlarmStatus::New);
+    }
+
+    #[test]
+    fn test_unidentifiable_alarms() {
+        let mut history = AlarmHistory::new(Duration::from_secs(60));
+        let first = Emergency {
+            keyword: "B:BMA".to_string(),
+            ..Default::default()
+        };
+        let second = Emergency {
+            keyword: "H:Klein".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(receive(&mut history, &first), AlarmStatus::New);
+        assert_eq!(receive(&mut history, &second), AlarmStatus::New);
+        assert_eq!(receive(&mut history, &second), AlarmStatus::Duplicate);
+    }
+
+    #[test]
+    fn test_window() {
+        let mut history = AlarmHistory::new(Duration::from_millis(10));
+        assert_eq!(receive(&mut history, &emergency(1234)), AlarmStatus::New);
+        sleep(Duration::from_millis(20));
+        assert_eq!(receive(&mut history, &emergency(1234)), AlarmStatus::New);
+        assert_eq!(
+            receive(&mut history, &emergency(1234)),
+            AlarmStatus::Duplicate
+        );
+
+        let mut disabled = AlarmHistory::new(Duration::ZERO);
+        assert_eq!(receive(&mut disabled, &emergency(1234)), AlarmStatus::New);
+        assert_eq!(receive(&mut disabled, &emergency(1234)), AlarmStatus::New);
+    }
+
+    #[test]
+    fn test_check_without_record() {
+        let mut history = AlarmHistory::new(Duration::from_secs(60));
+        assert_eq!(history.check(&emergency(1234)), AlarmStatus::New);
+        // not handled, the retry of the source must be printed
+        assert_eq!(history.check(&emergency(1234)), AlarmStatus::New);
+        history.record(&emergency(1234));
+        assert_eq!(history.check(&emergency(1234)), AlarmStatus::Duplicate);
+    }
+}
@@ -0,0 +1,110 @@
+use chrono::{DateTime, Duration, FixedOffset, LocalResult, NaiveDateTime, NaiveTime, TimeZone};
+use chrono_tz::Tz;
+
+/// the timezone of the dispatch centre, all times in the alarm mails are local times in this timezone
+pub const DISPATCH_TIMEZONE: Tz = chrono_tz::Europe::Berlin;
+
+/// unit alarm times are transmitted without a date, a unit time this far from the emergency's
+/// alarm time is assumed to be on the previous or next day.
+const MAX_UNIT_TIME_DEVIATION_HOURS: i64 = 12;
+
+/// Resolves a local time of the dispatch centre to a timezone aware timestamp.
+///
+/// # description
+/// Around DST transitions local times are not unique:
+/// * times occurring twice (end of DST) resolve to the one closest to `near`, or the earlier one without `near`.
+/// * times skipped (start of DST) are interpreted with the offset before the transition, as the clock
+///   of the dispatch centre was not adjusted yet.
+pub fn resolve_local(local: NaiveDateTime, near: Option<DateTime<Tz>>) -> Option<DateTime<Tz>> {
+    return match DISPATCH_TIMEZONE.from_local_datetime(&local) {
+        LocalResult::Single(time) => Some(time),
+        LocalResult::Ambiguous(earlier, later) => {
+            let Some(near) = near else {
+                return Some(earlier);
+            };
+            if (earlier - near).abs() <= (later - near).abs() {
+                Some(earlier)
+            } else {
+                Some(later)
+            }
+        }
+        LocalResult::None => {
+            // the gap is one hour long, so one hour earlier is always before the transition
+            let before = DISPATCH_TIMEZONE
+                .from_local_datetime(&(local - Duration::hours(1)))
+                .earliest()?;
+            Some(before + Duration::hours(1))
+        }
+    };
+}
+
+/// Resolves a unit alarm time (e.g. `08:21`) relative to the emergency's alarm time.
+///
+/// # description
+/// The unit time is put on the date of the emergency's alarm. If it is more than 12 hours before or after the
+/// alarm, it belongs to the next or previous day (e.g. an emergency at 23:58 with units alarmed at 00:03).
+///
+/// # return value
+/// None, if the unit time can't be parsed.
+pub fn resolve_unit_time(raw: &str, alarm: DateTime<Tz>) -> Option<DateTime<FixedOffset>> {
+    let time = NaiveTime::parse_from_str(raw.trim(), "%H:%M").ok()?;
+    let alarm_local = alarm.naive_local();
+    let mut local = alarm_local.date().and_time(time);
+
+    let max_deviation = Duration::hours(MAX_UNIT_TIME_DEVIATION_HOURS);
+    if local < alarm_local - max_deviation {
+        local += Duration::days(1);
+    } else if local > alarm_local + max_deviation {
+        local -= Duration::days(1);
+    }
+
+    return resolve_local(local, Some(alarm)).map(|t| t.fixed_offset());
+}
+
+#[cfg(test)]
+fn local(s: &str) -> NaiveDateTime {
+    return NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M").unwrap();
+}
+
+#[test]
+fn test_resolve_unit_time_same_day() {
+    let alarm = resolve_local(local("2022-09-29 08:23"), None).unwrap();
+    let unit = resolve_unit_time("08:21", alarm).unwrap();
+    assert_eq!(unit.to_rfc3339(), "2022-09-29T08:21:00+02:00");
+}
+
+#[test]
+fn test_resolve_unit_time_midnight_rollover() {
+    let alarm = resolve_local(local("2023-01-31 23:58"), None).unwrap();
+    let unit = resolve_unit_time("00:03", alarm).unwrap();
+    assert_eq!(unit.to_rfc3339(), "2023-02-01T00:03:00+01:00");
+
+    let alarm = resolve_local(local("2023-02-01 00:01"), None).unwrap();
+    let unit = resolve_unit_time("23:59", alarm).unwrap();
+    assert_eq!(unit.to_rfc3339(), "2023-01-31T23:59:00+01:00");
+}
+
+#[test]
+fn test_resolve_unit_time_dst() {
+    // end of DST: 02:30 occurs twice, the unit time is closest to the alarm in summer time
+    let alarm = resolve_local(local("2023-10-29 02:40"), None).unwrap();
+    assert_eq!(alarm.to_rfc3339(), "2023-10-29T02:40:00+02:00");
+    let unit = resolve_unit_time("02:30", alarm).unwrap();
+    assert_eq!(unit.to_rfc3339(), "2023-10-29T02:30:00+02:00");
+    // alarmed after the switch to winter time
+    let unit = resolve_unit_time("03:05", alarm).unwrap();
+    assert_eq!(unit.to_rfc3339(), "2023-10-29T03:05:00+01:00");
+
+    // start of DST: 02:30 doesn't exist
+    let alarm = resolve_local(local("2023-03-26 01:55"), None).unwrap();
+    let unit = resolve_unit_time("02:30", alarm).unwrap();
+    assert_eq!(unit.to_rfc3339(), "2023-03-26T03:30:00+02:00");
+    assert_eq!((unit - alarm.fixed_offset()).num_minutes(), 35);
+}
+
+#[test]
+fn test_resolve_unit_time_invalid() {
+    let alarm = resolve_local(local("2022-09-29 08:23"), None).unwrap();
+    assert!(resolve_unit_time("", alarm).is_none());
+    assert!(resolve_unit_time("8 Uhr", alarm).is_none());
+}
@@ -0,0 +1,274 @@
+use chrono::NaiveDateTime;
+use log::trace;
+
+use super::{
+    coordinates::{Coordinates, Wgs84Position},
+    emergency::Emergency,
+    emergency_field::EmergencyField,
+    emergency_parser::EmergencyParser,
+    emergency_parsing::parse_unit_id,
+    parse_report::{ParseIssueKind, ParseReport},
+    recoverable::Recoverable,
+};
+
+/// labels longer than this are assumed to be a sentence containing a colon
+const MAX_LABEL_LENGTH: usize = 30;
+const ALARM_TIME_FORMATS: &[&str] = &["%d.%m.%Y %H:%M:%S", "%d.%m.%Y %H:%M", "%d.%m.%y %H:%M"];
+
+/// The plain text "Alarmfax" layout with one `Label: value` line per field (see examples/alarmfax_simple.txt).
+///
+/// # description
+/// * lines before the first label (e.g. the name of the dispatch centre) are ignored.
+/// * lines without a label continue the value of the previous label (e.g. multi-line notes).
+/// * `Einsatzort` (`Straße Nr, PLZ Ort - Ortsteil`) only fills address fields not given by their own label.
+/// * unknown labels are kept in [Emergency::extra_fields].
+///
+/// The layout has no alarm table, so unit alarm times are never reported as missing.
+pub struct AlarmfaxParser;
+
+/// a `Label: value` entry, including its continuation lines
+struct LabeledValue {
+    line: u64,
+    label: String,
+    value: String,
+}
+
+/// splits `Label: value`, None if the line has no label
+fn split_label(line: &str) -> Option<(&str, &str)> {
+    if line.starts_with(char::is_whitespace) {
+        return None; // indented lines continue the previous value
+    }
+    let (label, value) = line.split_once(':')?;
+    let is_label = !label.is_empty()
+        && label.chars().count() <= MAX_LABEL_LENGTH
+        && label
+            .chars()
+            .all(|c| c.is_alphabetic() || c == ' ' || c == '.' || c == '-' || c == '/');
+    if !is_label {
+        return None;
+    }
+    return Some((label.trim(), value.trim()));
+}
+
+fn read_labeled_values(mail: &str) -> Vec<LabeledValue> {
+    let mut values: Vec<LabeledValue> = Vec::new();
+    for (i, line) in mail.lines().enumerate() {
+        let line_nr = i as u64 + 1;
+        if line.trim().is_empty() {
+            continue;
+        }
+        if let Some((label, value)) = split_label(line) {
+            values.push(LabeledValue {
+                line: line_nr,
+                label: label.to_string(),
+                value: value.to_string(),
+            });
+        } else if let Some(last) = values.last_mut() {
+            if !last.value.is_empty() {
+                last.value.push('\n');
+            }
+            last.value.push_str(line.trim());
+        } else {
+            trace!("ignoring header line {}: {}", line_nr, line);
+        }
+    }
+    return values;
+}
+
+fn optional(value: &str) -> Option<String> {
+    return (!value.is_empty()).then(|| value.to_string());
+}
+
+/// parses `52.33823, 12.48627` (latitude, longitude), optionally with N/E and degree signs
+fn parse_coordinates(value: &str) -> Option<Coordinates> {
+    let numbers: Vec<f64> = value
+        .split(|c: char| c == ',' || c == ';' || c.is_whitespace())
+        .map(|s| s.trim_matches(|c: char| c == '°' || c == 'N' || c == 'E'))
+        .filter(|s| !s.is_empty())
+        .map(|s| s.parse::<f64>())
+        .collect::<Result<_, _>>()
+        .ok()?;
+    let [latitude, longitude] = numbers[..] else {
+        return None;
+    };
+    if !(-90.0..=90.0).contains(&latitude) || !(-180.0..=180.0).contains(&longitude) {
+        return None;
+    }
+    return Some(Coordinates::from_wgs84(Wgs84Position {
+        latitude,
+        longitude,
+    }));
+}
+
+/// fills the address fields from `Straße Nr, PLZ Ort - Ortsteil`, keeping fields already set
+fn apply_address(ems: &mut Emergency, address: &str) {
+    let (street_part, town_part) = address.split_once(',').unwrap_or((address, ""));
+
+    let street_part = street_part.trim();
+    let (street, house_number) = match street_part.rsplit_once(' ') {
+        Some((street, number)) if number.starts_with(|c: char| c.is_ascii_digit()) => {
+            (street, number)
+        }
+        _ => (street_part, ""),
+    };
+
+    let town_part = town_part.trim();
+    let town_part = match town_part.split_once(' ') {
+        Some((postcode, town))
+            if postcode.len() == 5 && postcode.chars().all(|c| c.is_ascii_digit()) =>
+        {
+            town
+        }
+        _ => town_part,
+    };
+    let (town, district) = town_part.split_once(" - ").unwrap_or((town_part, ""));
+
+    for (field, value) in [
+        (&mut ems.street, street),
+        (&mut ems.house_number, house_number),
+        (&mut ems.town, town),
+        (&mut ems.district, district),
+    ] {
+        if field.is_empty() {
+            *field = value.trim().to_string();
+        }
+    }
+}
+
+impl EmergencyParser for AlarmfaxParser {
+    fn name(&self) -> &'static str {
+        return "Alarmfax";
+    }
+
+    fn detect(&self, mail: &str) -> bool {
+        let has_label = |label: &str| {
+            mail.lines()
+                .filter_map(split_label)
+                .any(|(l, _)| l == label)
+        };
+        return has_label("Stichwort") && has_label("Einsatzort");
+    }
+
+    fn parse_with_report(&self, mail: &str) -> Recoverable<(Emergency, ParseReport), ParseReport> {
+        let mut ems = Emergency::default();
+        let mut report = ParseReport::default();
+        let mut address: Option<String> = None;
+
+        for LabeledValue { line, label, value } in read_labeled_values(mail) {
+            match label.as_str() {
+                "Einsatznummer" => {
+                    if let Ok(number) = value.parse::<u64>() {
+                        ems.emergency_number = number;
+                    } else {
+                        report.error(
+                            line,
+                            ParseIssueKind::ConversionFailed {
+                                field: label,
+                                value,
+                            },
+                            format!("failed to convert Einsatznummer in line {}", line),
+                        );
+                    }
+                }
+                "Alarmzeit" => {
+                    let time = ALARM_TIME_FORMATS
+                        .iter()
+                        .find_map(|f| NaiveDateTime::parse_from_str(&value, f).ok());
+                    if let Some(time) = time {
+                        ems.alarm_time = time;
+                    } else {
+                        report.warning(
+                            line,
+                            ParseIssueKind::ConversionFailed {
+                                field: label,
+                                value: value.clone(),
+                            },
+                            format!("Failed to parse alarm time {} in line {}!", value, line),
+                        );
+                    }
+                }
+                "Stichwort" => ems.keyword = value,
+                "Einsatzart" => ems.emergency_type = value,
+                "Sondersignal" => ems.code3 = value,
+                "Einsatzort" => address = Some(value),
+                "Straße" | "Strasse" => ems.street = value,
+                "Hausnummer" => ems.house_number = value,
+                "Ort" => ems.town = value,
+                "Ortsteil" => ems.district = value,
+                "Ortslage" => ems.location = value,
+                "sonst. Ortsangaben" | "Einsatzortzusatz" => {
+                    ems.location_addition = optional(&value);
+                }
+                "Objekt" => ems.object = optional(&value),
+                "Objektteil" => ems.object_part = optional(&value),
+                "FWPlan" | "FWPlan-Nr" | "Feuerwehrplan" => {
+                    ems.fire_department_plan = optional(&value);
+                }
+                "Objektnummer" => {
+                    if let Ok(number) = value.parse::<i64>() {
+                        ems.object_number = Some(number);
+                    } else if !value.is_empty() {
+                        report.warning(
+                            line,
+                            ParseIssueKind::ConversionFailed {
+                                field: label,
+                                value,
+                            },
+                            format!("failed to convert Objektnummer in line {}", line),
+                        );
+                    }
+                }
+                "Bemerkung" | "Hinweise" | "Besonderheiten" => ems.note = optional(&value),
+                "Patient" | "Name" => ems.patient_name = optional(&value),
+                "Einsatzmittel" => {
+                    ems.dispatched_units = value
+                        .split([',', '\n'])
+                        .map(str::trim)
+                        .filter(|u| !u.is_empty())
+                        .map(parse_unit_id)
+                        .collect();
+                }
+                "Koordinaten" => {
+                    ems.coordinates = parse_coordinates(&value);
+                    if ems.coordinates.is_none() {
+                        report.warning(
+                            line,
+                            ParseIssueKind::ConversionFailed {
+                                field: label,
+                                value: value.clone(),
+                            },
+                            format!("failed to parse coordinates {} in line {}", value, line),
+                        );
+                    }
+                }
+                _ => {
+                    report.info(
+                        line,
+                        ParseIssueKind::UnknownProperty {
+                            property: label.clone(),
+                        },
+                        format!(
+                            "Unknown label {} detected in line {}, keeping it as extra field",
+                            label, line
+                        ),
+                    );
+                    ems.extra_fields.insert(label, value);
+                }
+            }
+        }
+
+        if let Some(address) = address {
+            apply_address(&mut ems, &address);
+        }
+
+        return if report.issues.is_empty() {
+            Recoverable::Ok((ems, report))
+        } else {
+            Recoverable::Recoverable((ems, report))
+        };
+    }
+
+    fn provides(&self, field: EmergencyField) -> bool {
+        return field != EmergencyField::UnitAlarmTimes;
+    }
+}
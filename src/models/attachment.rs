@@ -0,0 +1,33 @@
+/// A file attached to the alarm mail (e.g. the official Alarmfax PDF or a map image).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Attachment {
+    /// the file name given by the sender, if any
+    pub filename: Option<String>,
+    /// lower case, e.g. `application/pdf`
+    pub mime_type: String,
+    /// the decoded content
+    pub data: Vec<u8>,
+}
+
+impl Attachment {
+    pub fn is_pdf(&self) -> bool {
+        return self.mime_type == "application/pdf";
+    }
+
+    pub fn is_image(&self) -> bool {
+        return self.mime_type.starts_with("image/");
+    }
+
+    /// the file extension matching the mime type (used to pick the image decoder)
+    pub fn extension(&self) -> &str {
+        return match self.mime_type.as_str() {
+            "application/pdf" => "pdf",
+            "image/png" => "png",
+            "image/jpeg" | "image/jpg" => "jpg",
+            "image/bmp" => "bmp",
+            "image/gif" => "gif",
+            "image/tiff" => "tif",
+            _ => "bin",
+        };
+    }
+}
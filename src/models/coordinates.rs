@@ -0,0 +1,218 @@
+use std::f64::consts::PI;
+
+use serde::{Deserialize, Serialize};
+
+/// WGS84 ellipsoid, semi-major axis in metres
+const WGS84_A: f64 = 6_378_137.0;
+/// WGS84 ellipsoid flattening
+const WGS84_F: f64 = 1.0 / 298.257_223_563;
+/// UTM scale factor on the central meridian
+const UTM_K0: f64 = 0.9996;
+const UTM_FALSE_EASTING: f64 = 500_000.0;
+/// central meridian of UTM zone 33 (EPSG:25833) in degrees
+const UTM33_CENTRAL_MERIDIAN: f64 = 15.0;
+
+/// maximum distance (in metres) between the positions of two coordinate systems
+/// for them to still be considered the same location.
+pub const MAX_COORDINATE_DEVIATION: f64 = 50.0;
+
+/// A position in WGS84 (EPSG:4326) given in decimal degrees.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Wgs84Position {
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+/// A position in ETRS89 / UTM zone 33N (EPSG:25833) given in metres.
+///
+/// ETRS89 and WGS84 differ by less than a metre, which is irrelevant for our use cases,
+/// therefore the conversion treats both as the same datum.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Utm33Position {
+    pub easting: f64,
+    pub northing: f64,
+}
+
+/// The location of an emergency in both coordinate systems transmitted by the dispatch centre.
+///
+/// Only constructed, if all transmitted positions agree (see [Coordinates::from_candidates]).
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Coordinates {
+    pub wgs84: Wgs84Position,
+    pub utm33: Utm33Position,
+}
+
+/// Krüger series coefficients (see https://en.wikipedia.org/wiki/Universal_Transverse_Mercator_coordinate_system)
+struct KruegerSeries {
+    a: f64,
+    n: f64,
+    alpha: [f64; 3],
+    beta: [f64; 3],
+    delta: [f64; 3],
+}
+
+impl KruegerSeries {
+    fn wgs84() -> Self {
+        let n = WGS84_F / (2.0 - WGS84_F);
+        let n2 = n * n;
+        let n3 = n2 * n;
+        return KruegerSeries {
+            a: WGS84_A / (1.0 + n) * (1.0 + n2 / 4.0 + n2 * n2 / 64.0),
+            n,
+            alpha: [
+                n / 2.0 - 2.0 * n2 / 3.0 + 5.0 * n3 / 16.0,
+                13.0 * n2 / 48.0 - 3.0 * n3 / 5.0,
+                61.0 * n3 / 240.0,
+            ],
+            beta: [
+                n / 2.0 - 2.0 * n2 / 3.0 + 37.0 * n3 / 96.0,
+                n2 / 48.0 + n3 / 15.0,
+                17.0 * n3 / 480.0,
+            ],
+            delta: [
+                2.0 * n - 2.0 * n2 / 3.0 - 2.0 * n3,
+                7.0 * n2 / 3.0 - 8.0 * n3 / 5.0,
+                56.0 * n3 / 15.0,
+            ],
+        };
+    }
+}
+
+impl Wgs84Position {
+    /// projects the position into UTM zone 33N.
+    ///
+    /// The projection is also used outside of zone 33 (e.g. for western Germany), where
+    /// it gets less precise, but stays consistent, which is all we need for comparisons.
+    pub fn to_utm33(self) -> Utm33Position {
+        let k = KruegerSeries::wgs84();
+        let phi = self.latitude.to_radians();
+        let lambda = (self.longitude - UTM33_CENTRAL_MERIDIAN).to_radians();
+
+        let c = 2.0 * k.n.sqrt() / (1.0 + k.n);
+        let t = (phi.sin().atanh() - c * (c * phi.sin()).atanh()).sinh();
+        let xi_p = (t / lambda.cos()).atan();
+        let eta_p = (lambda.sin() / (1.0 + t * t).sqrt()).atanh();
+
+        let mut easting = eta_p;
+        let mut northing = xi_p;
+        for (j, alpha) in k.alpha.iter().enumerate() {
+            let j2 = 2.0 * (j + 1) as f64;
+            easting += alpha * (j2 * xi_p).cos() * (j2 * eta_p).sinh();
+            northing += alpha * (j2 * xi_p).sin() * (j2 * eta_p).cosh();
+        }
+
+        return Utm33Position {
+            easting: UTM_FALSE_EASTING + UTM_K0 * k.a * easting,
+            northing: UTM_K0 * k.a * northing,
+        };
+    }
+}
+
+impl Utm33Position {
+    /// converts the position back into WGS84 decimal degrees (northern hemisphere only).
+    pub fn to_wgs84(self) -> Wgs84Position {
+        let k = KruegerSeries::wgs84();
+        let xi = self.northing / (UTM_K0 * k.a);
+        let eta = (self.easting - UTM_FALSE_EASTING) / (UTM_K0 * k.a);
+
+        let mut xi_p = xi;
+        let mut eta_p = eta;
+        for (j, beta) in k.beta.iter().enumerate() {
+            let j2 = 2.0 * (j + 1) as f64;
+            xi_p -= beta * (j2 * xi).sin() * (j2 * eta).cosh();
+            eta_p -= beta * (j2 * xi).cos() * (j2 * eta).sinh();
+        }
+
+        let chi = (xi_p.sin() / eta_p.cosh()).asin();
+        let mut phi = chi;
+        for (j, delta) in k.delta.iter().enumerate() {
+            phi += delta * (2.0 * (j + 1) as f64 * chi).sin();
+        }
+        let lambda = (eta_p.sinh() / xi_p.cos()).atan();
+
+        return Wgs84Position {
+            latitude: phi * 180.0 / PI,
+            longitude: UTM33_CENTRAL_MERIDIAN + lambda * 180.0 / PI,
+        };
+    }
+
+    /// euclidean distance in metres (exact enough within one zone)
+    pub fn distance(&self, other: &Utm33Position) -> f64 {
+        return (self.easting - other.easting).hypot(self.northing - other.northing);
+    }
+}
+
+impl Coordinates {
+    pub fn from_wgs84(wgs84: Wgs84Position) -> Self {
+        return Coordinates {
+            wgs84,
+            utm33: wgs84.to_utm33(),
+        };
+    }
+
+    /// Combines all positions transmitted by the dispatch centre into one location.
+    ///
+    /// # description
+    /// The first WGS84 candidate is used as reference, every other candidate (WGS84 or UTM) has to be within
+    /// [MAX_COORDINATE_DEVIATION] metres of it. If only a UTM position was transmitted, the WGS84 position is
+    /// calculated from it and vice versa.
+    ///
+    /// # return value
+    /// None, if no candidates were given or the candidates disagree.
+    pub fn from_candidates(wgs84: &[Wgs84Position], utm33: &[Utm33Position]) -> Option<Self> {
+        let reference = if let Some(first) = wgs84.first() {
+            Coordinates::from_wgs84(*first)
+        } else {
+            let first = utm33.first()?;
+            Coordinates {
+                wgs84: first.to_wgs84(),
+                utm33: *first,
+            }
+        };
+
+        let projected = wgs84.iter().copied().map(Wgs84Position::to_utm33);
+        let agree = projected
+            .chain(utm33.iter().copied())
+            .all(|p| p.distance(&reference.utm33) <= MAX_COORDINATE_DEVIATION);
+
+        return agree.then_some(reference);
+    }
+}
+
+#[test]
+fn test_wgs84_to_utm33() {
+    // reference values calculated with the Snyder series (Brandenburg an der Havel)
+    let pos = Wgs84Position {
+        latitude: 52.33823333,
+        longitude: 12.48626667,
+    };
+    let utm = pos.to_utm33();
+    assert!((utm.easting - 328_748.3).abs() < 1.0, "{:?}", utm);
+    assert!((utm.northing - 5_801_633.3).abs() < 1.0, "{:?}", utm);
+}
+
+#[test]
+fn test_utm33_round_trip() {
+    let pos = Wgs84Position {
+        latitude: 52.40333889,
+        longitude: 13.22015556,
+    };
+    let back = pos.to_utm33().to_wgs84();
+    assert!((back.latitude - pos.latitude).abs() < 1e-7);
+    assert!((back.longitude - pos.longitude).abs() < 1e-7);
+}
+
+#[test]
+fn test_coordinates_from_disagreeing_candidates() {
+    let a = Wgs84Position {
+        latitude: 52.0,
+        longitude: 13.0,
+    };
+    let b = Wgs84Position {
+        latitude: 52.1,
+        longitude: 13.0,
+    };
+    assert!(Coordinates::from_candidates(&[a, b], &[]).is_none());
+    assert!(Coordinates::from_candidates(&[a], &[a.to_utm33()]).is_some());
+    assert!(Coordinates::from_candidates(&[], &[]).is_none());
+}
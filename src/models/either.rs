@@ -1,4 +1,10 @@
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+use serde::{Deserialize, Serialize};
+
+/// Either serializes untagged, i.e. as the contained value itself.
+/// Deserializing tries `A` first, so the representations of `A` and `B` must not overlap
+/// (e.g. a struct and a string).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(untagged)]
 pub enum Either<A, B> {
     Left(A),
     Right(B),
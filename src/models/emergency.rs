@@ -1,8 +1,21 @@
-use chrono::NaiveDateTime;
+use chrono::{DateTime, NaiveDateTime};
+use chrono_tz::Tz;
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
 
-use super::{either::Either, radio_identifier::RadioIdentifier, unit_alarm_time::UnitAlarmTime};
+use super::{
+    alarm_time::resolve_local, coordinates::Coordinates, either::Either,
+    emergency_field::EmergencyField, radio_identifier::RadioIdentifier,
+    unit_alarm_time::UnitAlarmTime,
+};
 
-#[derive(Debug, Default)]
+/// A parsed alarm mail.
+///
+/// The serde representation is the JSON schema documented in `doc/emergency_json.md`,
+/// renaming or removing fields is a breaking change for all consumers of the JSON.
+/// Missing fields are filled with their default value when deserializing.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Emergency {
     pub town: String,
     pub district: String,
@@ -23,20 +36,40 @@ pub struct Emergency {
     pub dispatched_units: Vec<Either<RadioIdentifier, String>>,
     pub unit_alarm_times: Vec<UnitAlarmTime>,
     pub alarm_time: NaiveDateTime,
+    /// None, if no coordinates were transmitted or the transmitted coordinate systems disagree
+    pub coordinates: Option<Coordinates>,
+    /// all properties unknown to the parser (key -> value), in the order of the mail
+    pub extra_fields: IndexMap<String, String>,
 }
 
 impl Emergency {
-    fn verify_minimum_fields(&mut self) -> bool {
-        return !self.town.is_empty()
-            && !self.location.is_empty()
-            && !self.street.is_empty()
-            && !self.house_number.is_empty()
-            && !self.emergency_type.is_empty()
-            && !self.keyword.is_empty()
-            && !self.code3.is_empty()
-            && !self.dispatched_units.is_empty()
-            && !self.unit_alarm_times.is_empty()
-            && self.emergency_number != 0;
+    /// checks the mandatory fields, returns the ones the parser didn't fill
+    ///
+    /// # description
+    /// the mandatory fields differ between dispatch centres, [EmergencyField::DEFAULT_REQUIRED] is the minimum
+    /// used if nothing else is configured.
+    pub fn verify_minimum_fields(&self, required: &[EmergencyField]) -> Vec<EmergencyField> {
+        return required
+            .iter()
+            .filter(|field| !field.is_present(self))
+            .copied()
+            .collect();
+    }
+
+    /// the alarm time in the timezone of the dispatch centre, None if no alarm time was transmitted
+    pub fn alarm_time_local(&self) -> Option<DateTime<Tz>> {
+        if self.alarm_time == NaiveDateTime::default() {
+            return None;
+        }
+        return resolve_local(self.alarm_time, None);
+    }
+
+    /// the unit alarm times sorted by their resolved timestamp, units without a timestamp are last
+    pub fn unit_alarm_times_by_time(&self) -> Vec<&UnitAlarmTime> {
+        let mut units: Vec<&UnitAlarmTime> = self.unit_alarm_times.iter().collect();
+        // stable sort, so units alarmed at the same time keep the order of the mail
+        units.sort_by_key(|u| (u.alarm_timestamp.is_none(), u.alarm_timestamp));
+        return units;
     }
 
     fn count_units_from_town(&self, town: u8) -> u64 {
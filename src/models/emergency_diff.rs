@@ -0,0 +1,232 @@
+use super::{
+    either::Either, emergency::Emergency, emergency_field::EmergencyField,
+    radio_identifier::RadioIdentifier, unit_alarm_time::UnitAlarmTime,
+};
+
+/// the fields compared by [EmergencyDiff::between], the units are compared separately
+const COMPARED_FIELDS: &[EmergencyField] = &[
+    EmergencyField::Keyword,
+    EmergencyField::Code3,
+    EmergencyField::EmergencyType,
+    EmergencyField::Town,
+    EmergencyField::District,
+    EmergencyField::Location,
+    EmergencyField::Street,
+    EmergencyField::HouseNumber,
+    EmergencyField::LocationAddition,
+    EmergencyField::Object,
+    EmergencyField::ObjectPart,
+    EmergencyField::ObjectNumber,
+    EmergencyField::FireDepartmentPlan,
+    EmergencyField::PatientName,
+    EmergencyField::Note,
+];
+
+/// A field, whose value differs between two versions of an emergency.
+#[derive(Debug, PartialEq, Eq)]
+pub struct FieldChange {
+    /// the label of the field, or the key for extra fields
+    pub label: String,
+    /// None, if the field was empty
+    pub old: Option<String>,
+    /// None, if the field was removed
+    pub new: Option<String>,
+}
+
+/// The changes of an emergency, that was sent again by the dispatch centre (Nachalarmierung).
+#[derive(Debug)]
+pub struct EmergencyDiff<'a> {
+    /// entries of the alarm table, whose unit wasn't alarmed before
+    pub new_unit_alarm_times: Vec<&'a UnitAlarmTime>,
+    /// newly dispatched units without an entry in the alarm table
+    pub new_dispatched_units: Vec<&'a Either<RadioIdentifier, String>>,
+    pub changed_fields: Vec<FieldChange>,
+}
+
+pub fn unit_name(unit: &Either<RadioIdentifier, String>) -> String {
+    return match unit {
+        Either::Left(id) => id.to_string(),
+        Either::Right(id) => id.clone(),
+    };
+}
+
+fn non_empty(text: &str) -> Option<String> {
+    return (!text.is_empty()).then(|| text.to_string());
+}
+
+/// the value of a field as text, None if the field is empty
+fn field_text(field: EmergencyField, ems: &Emergency) -> Option<String> {
+    return match field {
+        EmergencyField::Town => non_empty(&ems.town),
+        EmergencyField::District => non_empty(&ems.district),
+        EmergencyField::Location => non_empty(&ems.location),
+        EmergencyField::LocationAddition => ems.location_addition.clone(),
+        EmergencyField::Street => non_empty(&ems.street),
+        EmergencyField::HouseNumber => non_empty(&ems.house_number),
+        EmergencyField::Object => ems.object.clone(),
+        EmergencyField::FireDepartmentPlan => ems.fire_department_plan.clone(),
+        EmergencyField::ObjectPart => ems.object_part.clone(),
+        EmergencyField::ObjectNumber => ems.object_number.map(|n| n.to_string()),
+        EmergencyField::EmergencyType => non_empty(&ems.emergency_type),
+        EmergencyField::Keyword => non_empty(&ems.keyword),
+        EmergencyField::Code3 => non_empty(&ems.code3),
+        EmergencyField::EmergencyNumber => Some(ems.emergency_number.to_string()),
+        EmergencyField::Note => ems.note.clone(),
+        EmergencyField::PatientName => ems.get_patient_name(),
+        EmergencyField::AlarmTime => Some(ems.alarm_time.format("%d.%m.%Y %H:%M").to_string()),
+        EmergencyField::DispatchedUnits
+        | EmergencyField::UnitAlarmTimes
+        | EmergencyField::Coordinates => None,
+    };
+}
+
+impl<'a> EmergencyDiff<'a> {
+    /// Compares the previous version of an emergency with the update.
+    ///
+    /// # description
+    /// units are compared by their name, units removed by the update are ignored, as the crews
+    /// are only interested in additional units. Coordinates are not compared, as they only change
+    /// together with the address.
+    pub fn between(old: &Emergency, new: &'a Emergency) -> Self {
+        let old_units: Vec<String> = old
+            .unit_alarm_times
+            .iter()
+            .map(|u| unit_name(&u.unit_id))
+            .chain(old.dispatched_units.iter().map(unit_name))
+            .collect();
+        let new_unit_alarm_times: Vec<&UnitAlarmTime> = new
+            .unit_alarm_times
+            .iter()
+            .filter(|u| !old_units.contains(&unit_name(&u.unit_id)))
+            .collect();
+        let new_dispatched_units = new
+            .dispatched_units
+            .iter()
+            .filter(|u| {
+                let name = unit_name(u);
+                !old_units.contains(&name)
+                    && !new_unit_alarm_times
+                        .iter()
+                        .any(|t| unit_name(&t.unit_id) == name)
+            })
+            .collect();
+
+        let mut changed_fields: Vec<FieldChange> = COMPARED_FIELDS
+            .iter()
+            .filter_map(|field| {
+                let (old, new) = (field_text(*field, old), field_text(*field, new));
+                return (old != new).then(|| FieldChange {
+                    label: field.label().to_string(),
+                    old,
+                    new,
+                });
+            })
+            .collect();
+        let extra_keys = new.extra_fields.keys().chain(
+            old.extra_fields
+                .keys()
+                .filter(|k| !new.extra_fields.contains_key(*k)),
+        );
+        for key in extra_keys {
+            let (old, new) = (old.extra_fields.get(key), new.extra_fields.get(key));
+            if old != new {
+                changed_fields.push(FieldChange {
+                    label: key.clone(),
+                    old: old.cloned(),
+                    new: new.cloned(),
+                });
+            }
+        }
+
+        return EmergencyDiff {
+            new_unit_alarm_times,
+            new_dispatched_units,
+            changed_fields,
+        };
+    }
+
+    /// whether the update contains nothing relevant for the crews (e.g. only departure times changed)
+    pub fn is_empty(&self) -> bool {
+        return self.new_unit_alarm_times.is_empty()
+            && self.new_dispatched_units.is_empty()
+            && self.changed_fields.is_empty();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::{unit_name, EmergencyDiff, FieldChange};
+    use crate::models::{either::Either, emergency::Emergency, unit_alarm_time::UnitAlarmTime};
+
+    const EMS: &str = include_str!("../../examples/emergency_bgebg.txt");
+
+    #[test]
+    fn test_unchanged() {
+        let old = Emergency::from_str(EMS).unwrap();
+        let new = Emergency::from_str(EMS).unwrap();
+        assert!(EmergencyDiff::between(&old, &new).is_empty());
+    }
+
+    #[test]
+    fn test_new_units() {
+        let old = Emergency::from_str(EMS).unwrap();
+        let mut new = Emergency::from_str(EMS).unwrap();
+        new.unit_alarm_times.push(UnitAlarmTime::from_values(
+            "FL PM 01/46-01".to_string(),
+            "FW Kleinmachnow".to_string(),
+            "12:45".to_string(),
+        ));
+        new.dispatched_units
+            .push(Either::Right("FL PM 01/46-01".to_string()));
+        new.dispatched_units
+            .push(Either::Right("RTW Teltow".to_string()));
+
+        let diff = EmergencyDiff::between(&old, &new);
+        assert_eq!(diff.new_unit_alarm_times.len(), 1);
+        assert_eq!(
+            unit_name(&diff.new_unit_alarm_times[0].unit_id),
+            "FL PM 01/46-01"
+        );
+        // listed once, as part of the alarm table
+        assert_eq!(diff.new_dispatched_units.len(), 1);
+        assert_eq!(unit_name(diff.new_dispatched_units[0]), "RTW Teltow");
+        assert!(diff.changed_fields.is_empty());
+    }
+
+    #[test]
+    fn test_changed_fields() {
+        let old = Emergency::from_str(EMS).unwrap();
+        let mut new = Emergency::from_str(EMS).unwrap();
+        new.note = Some("Zufahrt über Hof".to_string());
+        new.house_number = "12a".to_string();
+        new.extra_fields
+            .insert("Meldender".to_string(), "Nachbar".to_string());
+
+        let diff = EmergencyDiff::between(&old, &new);
+        assert!(diff.new_unit_alarm_times.is_empty());
+        assert_eq!(diff.changed_fields.len(), 3);
+        assert_eq!(
+            diff.changed_fields[0],
+            FieldChange {
+                label: "Hausnummer".to_string(),
+                old: Some(old.house_number.clone()),
+                new: Some("12a".to_string()),
+            }
+        );
+        assert_eq!(diff.changed_fields[1].label, "Hinweise");
+        assert_eq!(
+            diff.changed_fields[1].new,
+            Some("Zufahrt über Hof".to_string())
+        );
+        assert_eq!(
+            diff.changed_fields[2],
+            FieldChange {
+                label: "Meldender".to_string(),
+                old: None,
+                new: Some("Nachbar".to_string()),
+            }
+        );
+    }
+}
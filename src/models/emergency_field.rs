@@ -0,0 +1,123 @@
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+
+use super::emergency::Emergency;
+
+/// The fields of an alarm mail, named by their key in the `~~Key~~Value~~` format.
+///
+/// Used to configure, which fields are mandatory for a dispatch centre.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum EmergencyField {
+    #[serde(rename = "Ort")]
+    Town,
+    #[serde(rename = "Ortsteil")]
+    District,
+    #[serde(rename = "Ortslage")]
+    Location,
+    #[serde(rename = "Einsatzortzusatz")]
+    LocationAddition,
+    #[serde(rename = "Strasse")]
+    Street,
+    #[serde(rename = "Hausnummer")]
+    HouseNumber,
+    #[serde(rename = "Objekt")]
+    Object,
+    #[serde(rename = "FWPlan")]
+    FireDepartmentPlan,
+    #[serde(rename = "Objektteil")]
+    ObjectPart,
+    #[serde(rename = "Objektnummer")]
+    ObjectNumber,
+    #[serde(rename = "Einsatzart")]
+    EmergencyType,
+    #[serde(rename = "Alarmgrund")]
+    Keyword,
+    #[serde(rename = "Sondersignal")]
+    Code3,
+    #[serde(rename = "Einsatznummer")]
+    EmergencyNumber,
+    #[serde(rename = "Besonderheiten")]
+    Note,
+    #[serde(rename = "Name")]
+    PatientName,
+    #[serde(rename = "EMListe")]
+    DispatchedUnits,
+    #[serde(rename = "ALARM")]
+    UnitAlarmTimes,
+    #[serde(rename = "Alarmzeit")]
+    AlarmTime,
+    #[serde(rename = "Koordinaten")]
+    Coordinates,
+}
+
+impl EmergencyField {
+    /// the fields required by [Emergency::verify_minimum_fields], used if nothing else is configured
+    pub const DEFAULT_REQUIRED: &'static [EmergencyField] = &[
+        EmergencyField::Town,
+        EmergencyField::Location,
+        EmergencyField::Street,
+        EmergencyField::HouseNumber,
+        EmergencyField::EmergencyType,
+        EmergencyField::Keyword,
+        EmergencyField::Code3,
+        EmergencyField::DispatchedUnits,
+        EmergencyField::UnitAlarmTimes,
+        EmergencyField::EmergencyNumber,
+    ];
+
+    pub fn default_required() -> Vec<EmergencyField> {
+        return Self::DEFAULT_REQUIRED.to_vec();
+    }
+
+    /// the label used on the printout
+    pub fn label(&self) -> &'static str {
+        return match self {
+            EmergencyField::Town => "Ort",
+            EmergencyField::District => "Ortsteil",
+            EmergencyField::Location => "Ortslage",
+            EmergencyField::LocationAddition => "sonst. Ortsangaben",
+            EmergencyField::Street => "Straße",
+            EmergencyField::HouseNumber => "Hausnummer",
+            EmergencyField::Object => "Objekt",
+            EmergencyField::FireDepartmentPlan => "FWPlan-Nr",
+            EmergencyField::ObjectPart => "Objektteil",
+            EmergencyField::ObjectNumber => "Objektnummer",
+            EmergencyField::EmergencyType => "Einsatzart",
+            EmergencyField::Keyword => "Stichwort",
+            EmergencyField::Code3 => "Sondersignal",
+            EmergencyField::EmergencyNumber => "Einsatznummer",
+            EmergencyField::Note => "Hinweise",
+            EmergencyField::PatientName => "Patient",
+            EmergencyField::DispatchedUnits => "Einsatzmittel",
+            EmergencyField::UnitAlarmTimes => "Alarmierungen",
+            EmergencyField::AlarmTime => "Alarmzeit",
+            EmergencyField::Coordinates => "Koordinaten",
+        };
+    }
+
+    /// whether the field was filled by the parser (i.e. is not empty or a default value)
+    pub fn is_present(&self, ems: &Emergency) -> bool {
+        return match self {
+            EmergencyField::Town => !ems.town.is_empty(),
+            EmergencyField::District => !ems.district.is_empty(),
+            EmergencyField::Location => !ems.location.is_empty(),
+            EmergencyField::LocationAddition => ems.location_addition.is_some(),
+            EmergencyField::Street => !ems.street.is_empty(),
+            EmergencyField::HouseNumber => !ems.house_number.is_empty(),
+            EmergencyField::Object => ems.object.is_some(),
+            EmergencyField::FireDepartmentPlan => ems.fire_department_plan.is_some(),
+            EmergencyField::ObjectPart => ems.object_part.is_some(),
+            EmergencyField::ObjectNumber => ems.object_number.is_some(),
+            EmergencyField::EmergencyType => !ems.emergency_type.is_empty(),
+            EmergencyField::Keyword => !ems.keyword.is_empty(),
+            EmergencyField::Code3 => !ems.code3.is_empty(),
+            EmergencyField::EmergencyNumber => ems.emergency_number != 0,
+            EmergencyField::Note => ems.note.is_some(),
+            EmergencyField::PatientName => ems.patient_name.is_some(),
+            EmergencyField::DispatchedUnits => !ems.dispatched_units.is_empty(),
+            EmergencyField::UnitAlarmTimes => !ems.unit_alarm_times.is_empty(),
+            EmergencyField::AlarmTime => ems.alarm_time != NaiveDateTime::default(),
+            EmergencyField::Coordinates => ems.coordinates.is_some(),
+        };
+    }
+}
@@ -0,0 +1,162 @@
+use log::debug;
+
+use crate::config::config::{MailFormat, ParsingConfig, ParsingMode};
+
+use super::{
+    alarmfax_parsing::AlarmfaxParser,
+    emergency::Emergency,
+    emergency_field::EmergencyField,
+    parse_report::{ParseIssueKind, ParseReport},
+    recoverable::Recoverable,
+};
+
+/// A parser for the alarm mails of one dispatch centre export format.
+pub trait EmergencyParser {
+    /// the name used in log messages
+    fn name(&self) -> &'static str;
+
+    /// whether the mail looks like it is in this format (used for auto detection, no full parse)
+    fn detect(&self, mail: &str) -> bool;
+
+    /// Parses the mail and reports every part of it, that couldn't be used.
+    ///
+    /// # return value
+    /// Recoverable::Ok, if the whole mail was understood.
+    /// Recoverable::Recoverable, if issues were found (see the report for details).
+    fn parse_with_report(&self, mail: &str) -> Recoverable<(Emergency, ParseReport), ParseReport>;
+
+    /// whether the format can transmit the field at all, fields it can't are never reported as missing
+    fn provides(&self, _field: EmergencyField) -> bool {
+        return true;
+    }
+
+    /// Parses the mail according to the configured parsing mode.
+    ///
+    /// # description
+    /// * lenient: same as [EmergencyParser::parse_with_report], the required fields are not checked.
+    /// * validate: missing required fields are added to the report, so they can be marked on the printout.
+    /// * strict: like validate, but the mail is rejected if any information was lost or a required field is missing.
+    ///
+    /// # return value
+    /// Recoverable::Unrecoverable (containing the report) if the mail couldn't be parsed or, in strict mode, is incomplete.
+    fn parse_with_mode(
+        &self,
+        mail: &str,
+        config: &ParsingConfig,
+    ) -> Recoverable<(Emergency, ParseReport), ParseReport> {
+        let (ems, report) = match self.parse_with_report(mail) {
+            Recoverable::Ok(parsed) => parsed,
+            Recoverable::Recoverable(parsed) => parsed,
+            Recoverable::Unrecoverable(report) => return Recoverable::Unrecoverable(report),
+        };
+
+        return apply_mode(ems, report, config, |field| self.provides(field));
+    }
+}
+
+/// The `~~Key~~Value~~` export of ELS (see examples/emergency_simple.txt).
+pub struct ElsParser;
+
+impl EmergencyParser for ElsParser {
+    fn name(&self) -> &'static str {
+        return "ELS";
+    }
+
+    fn detect(&self, mail: &str) -> bool {
+        return mail
+            .lines()
+            .filter(|l| l.trim_start().starts_with("~~"))
+            .take(2)
+            .count()
+            == 2;
+    }
+
+    fn parse_with_report(&self, mail: &str) -> Recoverable<(Emergency, ParseReport), ParseReport> {
+        return Emergency::parse_with_report(mail);
+    }
+}
+
+/// Reports the missing mandatory fields and rejects incomplete alarms in strict mode.
+///
+/// # description
+/// only fields the format can transmit (`provides`) are reported as missing.
+fn apply_mode(
+    ems: Emergency,
+    mut report: ParseReport,
+    config: &ParsingConfig,
+    provides: impl Fn(EmergencyField) -> bool,
+) -> Recoverable<(Emergency, ParseReport), ParseReport> {
+    if config.mode == ParsingMode::Lenient {
+        return if report.issues.is_empty() {
+            Recoverable::Ok((ems, report))
+        } else {
+            Recoverable::Recoverable((ems, report))
+        };
+    }
+
+    for field in ems.verify_minimum_fields(&config.required_fields) {
+        if !provides(field) {
+            continue;
+        }
+        report.error(
+            0,
+            ParseIssueKind::MissingField { field },
+            format!("missing mandatory field {:?}", field),
+        );
+    }
+
+    return if report.issues.is_empty() {
+        Recoverable::Ok((ems, report))
+    } else if config.mode == ParsingMode::Strict && report.is_incomplete() {
+        Recoverable::Unrecoverable(report)
+    } else {
+        Recoverable::Recoverable((ems, report))
+    };
+}
+
+/// Checks an alarm, that was received already parsed (e.g. as json), like a parsed mail (see [EmergencyParser::parse_with_mode]).
+pub fn validate_emergency(
+    ems: Emergency,
+    config: &ParsingConfig,
+) -> Recoverable<(Emergency, ParseReport), ParseReport> {
+    return apply_mode(ems, ParseReport::default(), config, |_| true);
+}
+
+/// all known formats, in the order they are tried by the auto detection
+pub const PARSERS: &[&dyn EmergencyParser] = &[&ElsParser, &AlarmfaxParser];
+
+/// returns the parser of the first format matching the mail
+pub fn detect_parser(mail: &str) -> Option<&'static dyn EmergencyParser> {
+    return PARSERS.iter().copied().find(|p| p.detect(mail));
+}
+
+/// returns the parser for the configured format, None for auto detection
+pub fn configured_parser(format: MailFormat) -> Option<&'static dyn EmergencyParser> {
+    return match format {
+        MailFormat::Auto => None,
+        MailFormat::Els => Some(&ElsParser),
+        MailFormat::Alarmfax => Some(&AlarmfaxParser),
+    };
+}
+
+/// Parses a mail with the configured parser, detecting the format if none is configured.
+///
+/// # return value
+/// Recoverable::Unrecoverable, if the format couldn't be detected or in strict mode (see [EmergencyParser::parse_with_mode]).
+pub fn parse_mail(
+    mail: &str,
+    config: &ParsingConfig,
+) -> Recoverable<(Emergency, ParseReport), ParseReport> {
+    let parser = configured_parser(config.format).or_else(|| detect_parser(mail));
+    let Some(parser) = parser else {
+        let mut report = ParseReport::default();
+        report.error(
+            0,
+            ParseIssueKind::UnknownFormat,
+            "the mail doesn't match any known dispatch centre format".to_string(),
+        );
+        return Recoverable::Unrecoverable(report);
+    };
+    debug!("parsing mail as {}", parser.name());
+    return parser.parse_with_mode(mail, config);
+}
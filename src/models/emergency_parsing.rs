@@ -5,10 +5,16 @@ use std::{
 };
 
 use chrono::NaiveDateTime;
-use log::{debug, trace, warn};
+use log::{debug, trace};
 
 use crate::models::{
-    either::Either, radio_identifier::RadioIdentifier, unit_alarm_time::UnitAlarmTime,
+    alarm_time::resolve_unit_time,
+    coordinates::{Coordinates, Utm33Position, Wgs84Position},
+    either::Either,
+    parse_report::{ParseIssueKind, ParseReport},
+    radio_identifier::RadioIdentifier,
+    recoverable::Recoverable,
+    unit_alarm_time::UnitAlarmTime,
 };
 
 use super::emergency::Emergency;
@@ -45,9 +51,27 @@ fn read_value(chars: &mut Peekable<Chars>) -> String {
     return value;
 }
 
+/// reads all values of a property up to the end of the line, e.g. `a~~b~~` -> [a, b].
+/// The line break itself is not consumed.
+fn read_line_values(chars: &mut Peekable<Chars>) -> Vec<String> {
+    let mut line = String::new();
+    while let Some(next) = chars.peek() {
+        if next == &'\n' {
+            break;
+        }
+        line.push(*next);
+        let _ = chars.next();
+    }
+    let line = line.trim_end();
+    let line = line.strip_suffix("~~").unwrap_or(line);
+    return line.split("~~").map(|v| v.to_string()).collect();
+}
+
 fn skip_line(chars: &mut Peekable<Chars>, line_nr: &mut u64) -> () {
     while let Some(next) = chars.peek() {
         if next == &'\n' {
+            // consume the line break, so that it is not counted twice by skip_whitespace_count_lines
+            let _ = chars.next();
             *line_nr += 1;
             break;
         }
@@ -81,10 +105,10 @@ macro_rules! simple_property {
 }
 
 macro_rules! check_error_skip_line {
-    ($f: expr, $s: ident, $l: ident) => {
+    ($f: expr, $s: ident, $l: ident, $r: ident) => {
         match $f {
             Err(e) => {
-                warn!("{}", e);
+                $r.warning($l, ParseIssueKind::SkippedLine, e);
                 skip_line(&mut $s, &mut $l);
                 continue;
             }
@@ -93,14 +117,190 @@ macro_rules! check_error_skip_line {
     };
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone)]
 struct AlarmTableIndices {
     unit: usize,
     station: usize,
     alarm_time: usize,
+    departure_time: Option<usize>,
+    tableau_address: Option<usize>,
+    /// all columns not listed above (index, header)
+    other: Vec<(usize, String)>,
     header_count: usize,
 }
 
+/// collects all coordinate values of a mail, since they are spread over several lines
+/// and can only be cross-checked once all of them have been read.
+#[derive(Debug, Default)]
+struct CoordinateCandidates {
+    // NOTE: the ELS export transmits the latitude as X and the longitude as Y
+    wgs84_x: Option<f64>,
+    wgs84_y: Option<f64>,
+    wgs84: Vec<Wgs84Position>,
+    utm33: Vec<Utm33Position>,
+    /// the last line containing coordinates, used to report inconsistencies
+    last_line: u64,
+}
+
+impl CoordinateCandidates {
+    /// adds the values of the `Koord_EPSG_25833` line (easting, northing).
+    ///
+    /// Some exports fill this line with WGS84 degrees (longitude, latitude) instead of metres,
+    /// which is detected by the magnitude of the values.
+    fn add_epsg_25833(
+        &mut self,
+        first: &str,
+        second: &str,
+        line_nr: u64,
+        report: &mut ParseReport,
+    ) {
+        self.last_line = line_nr;
+        if first.trim().is_empty() && second.trim().is_empty() {
+            return; // not transmitted
+        }
+        let (Ok(first_value), Ok(second_value)) =
+            (first.trim().parse::<f64>(), second.trim().parse::<f64>())
+        else {
+            report.warning(
+                line_nr,
+                ParseIssueKind::ConversionFailed {
+                    field: "Koord_EPSG_25833".to_string(),
+                    value: format!("{}~~{}", first, second),
+                },
+                format!("failed to convert Koord_EPSG_25833 in line {}", line_nr),
+            );
+            return;
+        };
+        let (first, second) = (first_value, second_value);
+        if first == 0.0 || second == 0.0 {
+            return; // not transmitted
+        }
+
+        if first.abs() <= 180.0 && second.abs() <= 90.0 {
+            trace!("Koord_EPSG_25833 in line {} contains degrees", line_nr);
+            self.wgs84.push(Wgs84Position {
+                latitude: second,
+                longitude: first,
+            });
+        } else {
+            self.utm33.push(Utm33Position {
+                easting: first,
+                northing: second,
+            });
+        }
+    }
+
+    /// adds the values of the `Koord_EPSG_4326` line, e.g. `E1248630~~N5233820`.
+    ///
+    /// The values are prefixed by their direction and consist of two integer digits followed by the decimals.
+    fn add_epsg_4326(&mut self, first: &str, second: &str, line_nr: u64, report: &mut ParseReport) {
+        self.last_line = line_nr;
+        if first.trim().is_empty() && second.trim().is_empty() {
+            return; // not transmitted
+        }
+        let mut latitude = None;
+        let mut longitude = None;
+        for value in [first, second] {
+            let value = value.trim();
+            let (direction, digits) =
+                value.split_at(value.chars().next().map_or(0, char::len_utf8));
+            if digits.len() < 2 || !digits.chars().all(|c| c.is_ascii_digit()) {
+                report.warning(
+                    line_nr,
+                    ParseIssueKind::ConversionFailed {
+                        field: "Koord_EPSG_4326".to_string(),
+                        value: value.to_string(),
+                    },
+                    format!(
+                        "failed to convert Koord_EPSG_4326 value {} in line {}",
+                        value, line_nr
+                    ),
+                );
+                return;
+            }
+            let degrees = format!("{}.{}", &digits[..2], &digits[2..])
+                .parse::<f64>()
+                .ok();
+            match direction {
+                "N" => latitude = degrees,
+                "S" => latitude = degrees.map(|d| -d),
+                "E" => longitude = degrees,
+                "W" => longitude = degrees.map(|d| -d),
+                _ => {
+                    report.warning(
+                        line_nr,
+                        ParseIssueKind::ConversionFailed {
+                            field: "Koord_EPSG_4326".to_string(),
+                            value: value.to_string(),
+                        },
+                        format!(
+                            "unknown direction {} in Koord_EPSG_4326 in line {}",
+                            direction, line_nr
+                        ),
+                    );
+                    return;
+                }
+            }
+        }
+
+        match (latitude, longitude) {
+            (Some(latitude), Some(longitude)) if latitude != 0.0 && longitude != 0.0 => {
+                self.wgs84.push(Wgs84Position {
+                    latitude,
+                    longitude,
+                });
+            }
+            _ => {} // not transmitted (E00~~N00)
+        }
+    }
+
+    fn resolve(mut self, report: &mut ParseReport) -> Option<Coordinates> {
+        if let (Some(latitude), Some(longitude)) = (self.wgs84_x, self.wgs84_y) {
+            // WGS84_X/Y is the most precise source, so it is used as reference
+            self.wgs84.insert(
+                0,
+                Wgs84Position {
+                    latitude,
+                    longitude,
+                },
+            );
+        }
+
+        let coordinates = Coordinates::from_candidates(&self.wgs84, &self.utm33);
+        if coordinates.is_none() && !(self.wgs84.is_empty() && self.utm33.is_empty()) {
+            report.warning(
+                self.last_line,
+                ParseIssueKind::InconsistentValues {
+                    field: "Koordinaten".to_string(),
+                },
+                format!(
+                    "transmitted coordinates disagree, ignoring them: {:?}, {:?}",
+                    self.wgs84, self.utm33
+                ),
+            );
+        }
+        return coordinates;
+    }
+}
+
+fn parse_degrees(field: &str, value: &str, line_nr: u64, report: &mut ParseReport) -> Option<f64> {
+    if value.trim().is_empty() {
+        return None;
+    }
+    let Ok(degrees) = value.trim().parse::<f64>() else {
+        report.warning(
+            line_nr,
+            ParseIssueKind::ConversionFailed {
+                field: field.to_string(),
+                value: value.to_string(),
+            },
+            format!("failed to convert {} {} in line {}", field, value, line_nr),
+        );
+        return None;
+    };
+    return (degrees != 0.0).then_some(degrees);
+}
+
 //
 // ------------------ Parsing ------------------
 //
@@ -108,12 +308,30 @@ struct AlarmTableIndices {
 impl FromStr for Emergency {
     type Err = String;
 
+    /// parses the mail leniently, see [Emergency::parse_with_report] to get the skipped parts of the mail.
     fn from_str(s: &str) -> Result<Self, Self::Err> {
+        return Emergency::parse_with_report(s)
+            .to_lenient_result()
+            .map(|(ems, _report)| ems)
+            .map_err(|report| format!("couldn't parse emergency: {:?}", report));
+    }
+}
+
+impl Emergency {
+    /// Parses an alarm mail and reports every part of it, that couldn't be used.
+    ///
+    /// # return value
+    /// Recoverable::Ok, if the whole mail was understood.
+    /// Recoverable::Recoverable, if issues were found (see the report for details). The emergency contains
+    /// default values for all fields, that couldn't be parsed.
+    pub fn parse_with_report(s: &str) -> Recoverable<(Emergency, ParseReport), ParseReport> {
         let mut ems = Emergency::default();
+        let mut report = ParseReport::default();
         let mut line_nr = 1; // line numbers start at 1 (not 0!!!, lol)
 
         let mut in_stream = s.chars().peekable();
         let mut header_indicies: Option<AlarmTableIndices> = None;
+        let mut coordinates = CoordinateCandidates::default();
 
         while in_stream.peek().is_some() {
             skip_whitespace_count_lines(&mut in_stream, &mut line_nr);
@@ -123,7 +341,8 @@ impl FromStr for Emergency {
             check_error_skip_line!(
                 expect_literal(&mut in_stream, "~~", line_nr),
                 in_stream,
-                line_nr
+                line_nr,
+                report
             );
 
             let property = read_value(&mut in_stream);
@@ -131,7 +350,8 @@ impl FromStr for Emergency {
             check_error_skip_line!(
                 expect_literal(&mut in_stream, "~~", line_nr),
                 in_stream,
-                line_nr
+                line_nr,
+                report
             );
 
             match property.as_str() {
@@ -163,12 +383,20 @@ impl FromStr for Emergency {
                     ems.object_part = (!obj_part.is_empty()).then(|| obj_part);
                 }
                 "Objektnummer" => {
-                    if let Ok(number) = read_value(&mut in_stream).parse::<i64>() {
+                    let value = read_value(&mut in_stream);
+                    if let Ok(number) = value.parse::<i64>() {
                         if number != -1 {
                             ems.object_number = Some(number);
                         }
-                    } else {
-                        warn!("failed to convert Objektnummer to i64 in line {}", line_nr);
+                    } else if !value.is_empty() {
+                        report.warning(
+                            line_nr,
+                            ParseIssueKind::ConversionFailed {
+                                field: property.clone(),
+                                value,
+                            },
+                            format!("failed to convert Objektnummer to i64 in line {}", line_nr),
+                        );
                     }
                 }
                 "Einsatzart" => {
@@ -184,12 +412,20 @@ impl FromStr for Emergency {
                     // parse the number as u64, if it fails, set it to 0
                     // and warn (recovering is always better than crashing here, since we want to print a fax
                     // even if not everything could be parsed)
-                    if let Ok(number) = read_value(&mut in_stream).parse::<u64>() {
+                    let value = read_value(&mut in_stream);
+                    if let Ok(number) = value.parse::<u64>() {
                         ems.emergency_number = number;
                     } else {
-                        warn!(
-                            "failed to convert Einsatznummer integer in line {}",
-                            line_nr
+                        report.error(
+                            line_nr,
+                            ParseIssueKind::ConversionFailed {
+                                field: property.clone(),
+                                value,
+                            },
+                            format!(
+                                "failed to convert Einsatznummer integer in line {}",
+                                line_nr
+                            ),
                         );
                         ems.emergency_number = 0;
                     }
@@ -211,48 +447,73 @@ impl FromStr for Emergency {
 
                 "Status" => {
                     if header_indicies.is_some() {
-                        warn!("Found multiple alarm table headers in line {}!", line_nr);
+                        report.warning(
+                            line_nr,
+                            ParseIssueKind::MalformedTableRow,
+                            format!("Found multiple alarm table headers in line {}!", line_nr),
+                        );
                     }
 
-                    header_indicies = Some(parse_alarm_table_header(&mut in_stream, &mut line_nr));
+                    header_indicies = Some(parse_alarm_table_header(
+                        &mut in_stream,
+                        &mut line_nr,
+                        &mut report,
+                    ));
                     continue; // skip the line end ~~, since we already parsed it
                 }
                 "ALARM" => {
                     if header_indicies.is_none() {
-                        warn!(
-                            "Found alarm table entry before header in line {}! skipping!",
-                            line_nr
+                        report.warning(
+                            line_nr,
+                            ParseIssueKind::MalformedTableRow,
+                            format!(
+                                "Found alarm table entry before header in line {}! skipping!",
+                                line_nr
+                            ),
                         );
+                        skip_line(&mut in_stream, &mut line_nr);
                         continue;
-                    } else if let Some(headers) = header_indicies {
-                        parse_alarm_table_entry(&mut in_stream, headers, &mut ems, &mut line_nr);
+                    } else if let Some(headers) = &header_indicies {
+                        parse_alarm_table_entry(
+                            &mut in_stream,
+                            headers,
+                            &mut ems,
+                            &mut line_nr,
+                            &mut report,
+                        );
                         continue; // skip the line end ~~, since we already parsed it
                     }
                 }
 
                 "WGS84_X" => {
-                    let _ = read_value(&mut in_stream);
+                    coordinates.wgs84_x =
+                        parse_degrees(&property, &read_value(&mut in_stream), line_nr, &mut report);
                 }
                 "WGS84_Y" => {
-                    let _ = read_value(&mut in_stream);
+                    coordinates.wgs84_y =
+                        parse_degrees(&property, &read_value(&mut in_stream), line_nr, &mut report);
                 }
                 "Koord_EPSG_25833" => {
-                    let _ = read_value(&mut in_stream);
+                    let first = read_value(&mut in_stream);
                     check_error_skip_line!(
                         expect_literal(&mut in_stream, "~~", line_nr),
                         in_stream,
-                        line_nr
+                        line_nr,
+                        report
                     );
-                    let _ = read_value(&mut in_stream);
+                    let second = read_value(&mut in_stream);
+                    coordinates.add_epsg_25833(&first, &second, line_nr, &mut report);
                 }
                 "Koord_EPSG_4326" => {
-                    let _ = read_value(&mut in_stream);
+                    let first = read_value(&mut in_stream);
                     check_error_skip_line!(
                         expect_literal(&mut in_stream, "~~", line_nr),
                         in_stream,
-                        line_nr
+                        line_nr,
+                        report
                     );
-                    let _ = read_value(&mut in_stream);
+                    let second = read_value(&mut in_stream);
+                    coordinates.add_epsg_4326(&first, &second, line_nr, &mut report);
                 }
                 "Einsatzortzusatz" => {
                     let addition = read_value(&mut in_stream);
@@ -263,43 +524,104 @@ impl FromStr for Emergency {
                     if let Ok(time) = NaiveDateTime::parse_from_str(&time_str, "%d.%m.%y&%H:%M") {
                         ems.alarm_time = time;
                     } else {
-                        warn!(
-                            "Failed to parse alarm time {} in line {}!",
-                            time_str, line_nr
+                        report.warning(
+                            line_nr,
+                            ParseIssueKind::ConversionFailed {
+                                field: property.clone(),
+                                value: time_str.clone(),
+                            },
+                            format!(
+                                "Failed to parse alarm time {} in line {}!",
+                                time_str, line_nr
+                            ),
                         );
                     }
                 }
 
                 _ => {
-                    skip_line(&mut in_stream, &mut line_nr);
-                    // skip value. If this is not a simple key value pair, we might be screwed...
-                    // TODO: perhaps expect a new line also (since all but one property are followed by a new line)
-                    warn!(
-                        "Unknown property {} detected in line {}!",
-                        property, line_nr
+                    report.info(
+                        line_nr,
+                        ParseIssueKind::UnknownProperty {
+                            property: property.clone(),
+                        },
+                        format!(
+                            "Unknown property {} detected in line {}, keeping it as extra field",
+                            property, line_nr
+                        ),
                     );
+                    // the structure of unknown properties is not known, so the whole line is kept.
+                    // properties with multiple values are joined, e.g. ~~Key~~a~~b~~ -> "a, b"
+                    let value = read_line_values(&mut in_stream).join(", ");
+                    ems.extra_fields.insert(property, value);
+                    continue; // the line end was read as well
                 }
             }
 
             check_error_skip_line!(
                 expect_literal(&mut in_stream, "~~", line_nr),
                 in_stream,
-                line_nr
+                line_nr,
+                report
             ); // found at the end of each line
         }
 
-        return Ok(ems);
+        ems.coordinates = coordinates.resolve(&mut report);
+        resolve_unit_alarm_times(&mut ems, &mut report);
+
+        return if report.issues.is_empty() {
+            Recoverable::Ok((ems, report))
+        } else {
+            Recoverable::Recoverable((ems, report))
+        };
+    }
+}
+
+/// resolves the unit alarm times, once the emergency's alarm time is known (it is the last line of the mail)
+fn resolve_unit_alarm_times(ems: &mut Emergency, report: &mut ParseReport) {
+    let Some(alarm) = ems.alarm_time_local() else {
+        return;
+    };
+    for unit in ems.unit_alarm_times.iter_mut() {
+        unit.alarm_timestamp = resolve_unit_time(&unit.alarm_time, alarm);
+        unit.departure_timestamp = unit
+            .departure_time
+            .as_ref()
+            .and_then(|t| resolve_unit_time(t, alarm));
+        if let (None, Some(departure_time)) = (unit.departure_timestamp, &unit.departure_time) {
+            report.warning(
+                0,
+                ParseIssueKind::ConversionFailed {
+                    field: "Ausgerückt".to_string(),
+                    value: departure_time.clone(),
+                },
+                format!("failed to convert unit departure time {}", departure_time),
+            );
+        }
+        if unit.alarm_timestamp.is_none() && !unit.alarm_time.is_empty() {
+            report.warning(
+                0,
+                ParseIssueKind::ConversionFailed {
+                    field: "ALARM".to_string(),
+                    value: unit.alarm_time.clone(),
+                },
+                format!("failed to convert unit alarm time {}", unit.alarm_time),
+            );
+        }
     }
 }
 
 fn parse_alarm_table_header(
     in_stream: &mut Peekable<Chars<'_>>,
     line_nr: &mut u64,
+    report: &mut ParseReport,
 ) -> AlarmTableIndices {
     let mut indices = AlarmTableIndices {
         unit: 0,
         station: 0,
         alarm_time: 0,
+        departure_time: None,
+        tableau_address: None,
+        other: Vec::new(),
         header_count: 0,
     };
 
@@ -321,17 +643,26 @@ fn parse_alarm_table_header(
             "Wache" => indices.station = indices.header_count,
             "Alarm" => indices.alarm_time = indices.header_count,
             "Alarmiert" => indices.alarm_time = indices.header_count,
-            "Tableau-Adresse" => {}
-            "Ausgerückt" => {}
+            "Tableau-Adresse" => indices.tableau_address = Some(indices.header_count),
+            "Ausgerückt" => indices.departure_time = Some(indices.header_count),
             _ => {
-                trace!("Unused column {} in alarm table in line {}!", col, *line_nr);
+                trace!(
+                    "Unknown column {} in alarm table in line {}!",
+                    col,
+                    *line_nr
+                );
+                indices.other.push((indices.header_count, col));
             }
         }
         indices.header_count += 1;
 
         let end_section = expect_literal(in_stream, "~~", *line_nr);
         if let Err(e) = end_section {
-            warn!("missing '~~' to end alarm table header: {}", e);
+            report.warning(
+                *line_nr,
+                ParseIssueKind::MalformedTableRow,
+                format!("missing '~~' to end alarm table header: {}", e),
+            );
             continue;
         }
     }
@@ -347,9 +678,10 @@ fn vec_remove_replace(vec: &mut Vec<String>, index: usize) -> String {
 
 fn parse_alarm_table_entry(
     in_stream: &mut Peekable<Chars<'_>>,
-    headers: AlarmTableIndices,
+    headers: &AlarmTableIndices,
     ems: &mut Emergency,
     line_nr: &mut u64,
+    report: &mut ParseReport,
 ) {
     // in_stream is directly after the ALARM~~ part
     let mut entries: Vec<String> = Vec::with_capacity(headers.header_count);
@@ -367,7 +699,11 @@ fn parse_alarm_table_entry(
 
         let end_section = expect_literal(in_stream, "~~", *line_nr);
         if let Err(e) = end_section {
-            warn!("missing '~~' to end alarm table entry: {}", e);
+            report.warning(
+                *line_nr,
+                ParseIssueKind::MalformedTableRow,
+                format!("missing '~~' to end alarm table entry: {}", e),
+            );
             skip_line(in_stream, line_nr);
             return;
         }
@@ -375,10 +711,14 @@ fn parse_alarm_table_entry(
 
     // parse entry:
     if entries.len() <= max(headers.unit, max(headers.station, headers.alarm_time)) {
-        warn!(
-            "Insufficent amount of alarm table entry values  ({}) columns in line {}!",
-            entries.len(),
-            *line_nr
+        report.warning(
+            *line_nr,
+            ParseIssueKind::MalformedTableRow,
+            format!(
+                "Insufficent amount of alarm table entry values  ({}) columns in line {}!",
+                entries.len(),
+                *line_nr
+            ),
         );
         skip_line(in_stream, line_nr);
         return;
@@ -404,10 +744,30 @@ fn parse_alarm_table_entry(
         trace!("empty alarm table entry in line {}!", *line_nr);
         return;
     }
+    // optional columns, missing values (short rows) are treated as empty
+    let optional_entry = |index: usize| entries.get(index).filter(|e| !e.is_empty()).cloned();
+    let departure_time = headers.departure_time.and_then(optional_entry);
+    let tableau_address = headers.tableau_address.and_then(optional_entry);
+    let extra_columns = headers
+        .other
+        .iter()
+        .map(|(index, header)| {
+            (
+                header.clone(),
+                entries.get(*index).cloned().unwrap_or_default(),
+            )
+        })
+        .collect();
+
     let unit = UnitAlarmTime {
         unit_id: id,
         station: vec_remove_replace(&mut entries, headers.station),
         alarm_time: vec_remove_replace(&mut entries, headers.alarm_time),
+        alarm_timestamp: None, // resolved once the emergency's alarm time is known
+        departure_time,
+        departure_timestamp: None,
+        tableau_address,
+        extra_columns,
     };
     ems.unit_alarm_times.push(unit);
 }
@@ -415,15 +775,18 @@ fn parse_alarm_table_entry(
 fn parse_dispatched_units(in_stream: &mut Peekable<Chars<'_>>, ems: &mut Emergency) {
     let em_string = read_value(in_stream);
     em_string.split(", ").for_each(|em| {
-        let identifier = RadioIdentifier::from_str(em);
-        if let Ok(identifier) = identifier {
-            ems.dispatched_units.push(Either::Left(identifier));
-        } else {
-            debug!(
-                "Failed to parse RadioIdentifier {} using as bare Identifier!",
-                em
-            );
-            ems.dispatched_units.push(Either::Right(em.to_string()));
-        }
+        ems.dispatched_units.push(parse_unit_id(em));
     });
 }
+
+/// parses a unit as RadioIdentifier, falling back to the bare text for non standard units
+pub(super) fn parse_unit_id(em: &str) -> Either<RadioIdentifier, String> {
+    if let Ok(identifier) = RadioIdentifier::from_str(em) {
+        return Either::Left(identifier);
+    }
+    debug!(
+        "Failed to parse RadioIdentifier {} using as bare Identifier!",
+        em
+    );
+    return Either::Right(em.to_string());
+}
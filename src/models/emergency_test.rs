@@ -1,14 +1,31 @@
-use std::str::FromStr;
+use std::{env, fs, str::FromStr};
 
 use chrono::NaiveDate;
+use indexmap::IndexMap;
 
 use crate::{
-    config::logging,
-    models::{either::Either, emergency::Emergency, radio_identifier::RadioIdentifier},
+    config::{
+        config::{ParsingConfig, ParsingMode},
+        logging,
+    },
+    connection::message::mail_str_decode_unicode,
+    models::{
+        alarmfax_parsing::AlarmfaxParser,
+        either::Either,
+        emergency::Emergency,
+        emergency_field::EmergencyField,
+        emergency_parser::{detect_parser, parse_mail, ElsParser, EmergencyParser},
+        emergency_writing::{write_drill_mail, AlarmTableColumn, MailWriterOptions},
+        parse_report::{ParseIssueKind, Severity},
+        radio_identifier::RadioIdentifier,
+        recoverable::Recoverable,
+        unit_alarm_time::UnitAlarmTime,
+    },
 };
 
 #[cfg(test)]
 const TEST_MAIL_CONTENT: &str = include_str!("../../examples/emergency_simple.txt");
+const ALARMFAX_MAIL: &str = include_str!("../../examples/alarmfax_simple.txt");
 
 #[test]
 #[allow(deprecated)]
@@ -63,3 +80,581 @@ fn test_parse_emergency() {
         NaiveDate::from_ymd(2022, 9, 29).and_hms(8, 23, 0)
     );
 }
+
+#[test]
+fn test_parse_emergency_coordinates() {
+    let ems = Emergency::from_str(TEST_MAIL_CONTENT).unwrap();
+    let coordinates = ems.coordinates.expect("coordinates should agree");
+    assert_eq!(coordinates.wgs84.latitude, 52.33823333);
+    assert_eq!(coordinates.wgs84.longitude, 12.48626667);
+    assert!((coordinates.utm33.easting - 328_748.3).abs() < 1.0);
+    assert!((coordinates.utm33.northing - 5_801_633.3).abs() < 1.0);
+}
+
+#[test]
+fn test_parse_emergency_coordinates_utm_only() {
+    let ems = Emergency::from_str(
+        "~~Koord_EPSG_25833~~328748.3~~5801633.3~~\n~~Koord_EPSG_4326~~E00~~N00~~\n",
+    )
+    .unwrap();
+    let coordinates = ems
+        .coordinates
+        .expect("utm coordinates should be converted");
+    assert!((coordinates.wgs84.latitude - 52.33823333).abs() < 1e-6);
+    assert!((coordinates.wgs84.longitude - 12.48626667).abs() < 1e-6);
+}
+
+#[test]
+fn test_parse_emergency_coordinates_disagree() {
+    let ems = Emergency::from_str(
+        "~~WGS84_X~~52.33823333~~\n~~WGS84_Y~~12.48626667~~\n~~Koord_EPSG_4326~~E1322020~~N5240330~~\n",
+    )
+    .unwrap();
+    assert!(ems.coordinates.is_none());
+}
+
+#[test]
+fn test_parse_report_clean() {
+    let result = Emergency::parse_with_report(TEST_MAIL_CONTENT);
+    let Recoverable::Ok((_ems, report)) = result else {
+        panic!("expected a clean parse, got {:?}", result);
+    };
+    assert!(report.issues.is_empty());
+    assert!(!report.is_incomplete());
+}
+
+#[test]
+fn test_parse_report_issues() {
+    const MAIL: &str = "~~Ort~~Kleinmachnow~~\n~~Neu~~Wert~~\n~~Einsatznummer~~12a~~\n~~ALARM~~a~~b~~\nkaputt\n~~Status~~Wache~~Fahrzeug~~Alarm~~\n~~ALARM~~nur eine Spalte~~\n";
+    let result = Emergency::parse_with_report(MAIL);
+    let Recoverable::Recoverable((ems, report)) = result else {
+        panic!("expected a recoverable parse, got {:?}", result);
+    };
+    assert_eq!(ems.town, "Kleinmachnow");
+    assert_eq!(ems.emergency_number, 0);
+    assert_eq!(
+        ems.extra_fields.get("Neu").map(String::as_str),
+        Some("Wert")
+    );
+
+    let kinds: Vec<(u64, Severity, &ParseIssueKind)> = report
+        .issues
+        .iter()
+        .map(|i| (i.line, i.severity, &i.kind))
+        .collect();
+    assert_eq!(
+        kinds,
+        vec![
+            (
+                2,
+                Severity::Info,
+                &ParseIssueKind::UnknownProperty {
+                    property: "Neu".to_string()
+                }
+            ),
+            (
+                3,
+                Severity::Error,
+                &ParseIssueKind::ConversionFailed {
+                    field: "Einsatznummer".to_string(),
+                    value: "12a".to_string()
+                }
+            ),
+            (4, Severity::Warning, &ParseIssueKind::MalformedTableRow),
+            (5, Severity::Warning, &ParseIssueKind::SkippedLine),
+            (7, Severity::Warning, &ParseIssueKind::MalformedTableRow),
+        ]
+    );
+    assert!(report.is_incomplete());
+    assert_eq!(report.max_severity(), Some(Severity::Error));
+}
+
+#[test]
+fn test_parse_modes() {
+    const MAIL: &str = "~~Ort~~Kleinmachnow~~\n~~Alarmgrund~~B:Klein~~\n~~Einsatznummer~~1234~~\n";
+    let mut config = ParsingConfig {
+        mode: ParsingMode::Lenient,
+        required_fields: vec![EmergencyField::Town, EmergencyField::Street],
+        ..Default::default()
+    };
+
+    let result = ElsParser.parse_with_mode(MAIL, &config);
+    assert!(matches!(result, Recoverable::Ok(_)), "{:?}", result);
+
+    config.mode = ParsingMode::Validate;
+    let Recoverable::Recoverable((ems, report)) = ElsParser.parse_with_mode(MAIL, &config) else {
+        panic!("expected missing fields to be recoverable in validate mode");
+    };
+    assert_eq!(ems.town, "Kleinmachnow");
+    assert_eq!(report.missing_fields(), vec![EmergencyField::Street]);
+    assert!(!ems
+        .verify_minimum_fields(EmergencyField::DEFAULT_REQUIRED)
+        .is_empty());
+
+    config.mode = ParsingMode::Strict;
+    let Recoverable::Unrecoverable(report) = ElsParser.parse_with_mode(MAIL, &config) else {
+        panic!("expected missing fields to be rejected in strict mode");
+    };
+    assert_eq!(report.missing_fields(), vec![EmergencyField::Street]);
+
+    config.required_fields = vec![EmergencyField::Town, EmergencyField::Keyword];
+    let result = ElsParser.parse_with_mode(MAIL, &config);
+    assert!(matches!(result, Recoverable::Ok(_)), "{:?}", result);
+}
+
+#[test]
+fn test_verify_minimum_fields() {
+    let ems = Emergency::from_str(TEST_MAIL_CONTENT).unwrap();
+    assert_eq!(
+        ems.verify_minimum_fields(EmergencyField::DEFAULT_REQUIRED),
+        vec![]
+    );
+}
+
+#[cfg(test)]
+const TEST_MAIL_JSON: &str = include_str!("../../examples/emergency_simple.json");
+
+#[test]
+fn test_serialize_emergency_json_schema() {
+    let ems = Emergency::from_str(TEST_MAIL_CONTENT).unwrap();
+    let mut json: serde_json::Value = serde_json::to_value(&ems).unwrap();
+    let mut expected: serde_json::Value = serde_json::from_str(TEST_MAIL_JSON).unwrap();
+
+    // the projected coordinates are only compared approximately, as they depend on floating point math
+    let utm = json["coordinates"]["utm33"].take();
+    let expected_utm = expected["coordinates"]["utm33"].take();
+    for key in ["easting", "northing"] {
+        let diff = utm[key].as_f64().unwrap() - expected_utm[key].as_f64().unwrap();
+        assert!(diff.abs() < 0.1, "{} differs by {}", key, diff);
+    }
+    assert_eq!(json, expected);
+}
+
+#[test]
+fn test_deserialize_emergency_round_trip() {
+    let ems = Emergency::from_str(TEST_MAIL_CONTENT).unwrap();
+    let json = serde_json::to_string(&ems).unwrap();
+    let deserialized: Emergency = serde_json::from_str(&json).unwrap();
+    assert_eq!(deserialized, ems);
+    assert!(deserialized.dispatched_units[0].is_left());
+    assert_eq!(
+        deserialized.dispatched_units[1],
+        Either::Right("RLS BRB DGL 2".to_string())
+    );
+}
+
+#[test]
+fn test_deserialize_emergency_defaults() {
+    let ems: Emergency = serde_json::from_str(r#"{"town": "Kleinmachnow"}"#).unwrap();
+    assert_eq!(ems.town, "Kleinmachnow");
+    assert_eq!(ems.emergency_number, 0);
+    assert!(ems.unit_alarm_times.is_empty());
+}
+
+#[cfg(test)]
+const EXAMPLE_MAILS: [&str; 5] = [
+    include_str!("../../examples/emergency_simple.txt"),
+    include_str!("../../examples/emergency_bgebg.txt"),
+    include_str!("../../examples/emergency_obj.txt"),
+    include_str!("../../examples/emergency_r1n1f.txt"),
+    include_str!("../../examples/emergency_many_units.txt"),
+];
+
+#[test]
+fn test_write_parse_round_trip() {
+    for mail in EXAMPLE_MAILS {
+        let ems = Emergency::from_str(&mail_str_decode_unicode(mail)).unwrap();
+        let written = ems.to_mail_string(&MailWriterOptions::default());
+        let Recoverable::Ok((parsed, _)) = Emergency::parse_with_report(&written) else {
+            panic!("written mail could not be parsed cleanly:\n{}", written);
+        };
+        assert_eq!(parsed, ems);
+        // writing is stable, i.e. writing the parsed mail again yields the same text
+        assert_eq!(
+            parsed.to_mail_string(&MailWriterOptions::default()),
+            written
+        );
+    }
+}
+
+#[test]
+fn test_write_quoted_printable_round_trip() {
+    let options = MailWriterOptions {
+        quoted_printable: true,
+        ..Default::default()
+    };
+    for mail in EXAMPLE_MAILS {
+        let mut ems = Emergency::from_str(&mail_str_decode_unicode(mail)).unwrap();
+        ems.note = Some("Zufahrt über Tor 2=Hof".to_string());
+        let written = ems.to_mail_string(&options);
+        assert!(!written.contains(['ä', 'ö', 'ü', 'ß']), "{}", written);
+        assert!(written.contains("Tor 2=3DHof"), "{}", written);
+        assert!(written.lines().all(|l| l.len() <= 76), "{}", written);
+        let parsed = Emergency::from_str(&mail_str_decode_unicode(&written)).unwrap();
+        assert_eq!(parsed, ems);
+    }
+}
+
+/// a small deterministic random generator for the round-trip property tests
+struct TestRng(u64);
+
+impl TestRng {
+    /// a number in `0..n`
+    fn below(&mut self, n: usize) -> usize {
+        // xorshift64
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        return (self.0 % n as u64) as usize;
+    }
+
+    fn text(&mut self) -> String {
+        const CHARS: &[char] = &[
+            'a', 'k', 'z', 'B', 'Q', '0', '7', ' ', '-', '/', '.', ',', ':', '(', ')', '#', '&',
+            '=', 'ä', 'ö', 'ü', 'ß', 'Ä', 'Ö', 'Ü', '§',
+        ];
+        let length = 1 + self.below(40);
+        return (0..length)
+            .map(|_| CHARS[self.below(CHARS.len())])
+            .collect::<String>()
+            .trim()
+            .to_string();
+    }
+
+    fn optional(&mut self) -> Option<String> {
+        return if self.below(3) == 0 {
+            None
+        } else {
+            Some(self.text())
+        };
+    }
+
+    fn unit(&mut self) -> String {
+        return if self.below(4) == 0 {
+            format!("RLS BRB DGL {}", self.below(10))
+        } else {
+            format!(
+                "FL PM {:02}/{:02}-{:02}",
+                1 + self.below(20),
+                self.below(100),
+                1 + self.below(9)
+            )
+        };
+    }
+
+    fn emergency(&mut self) -> Emergency {
+        let alarm_time = NaiveDate::from_ymd_opt(
+            2000 + self.below(99) as i32,
+            1 + self.below(12) as u32,
+            1 + self.below(28) as u32,
+        )
+        .unwrap()
+        .and_hms_opt(self.below(24) as u32, self.below(60) as u32, 0)
+        .unwrap();
+        let unit_alarm_times = (0..self.below(6))
+            .map(|_| {
+                let time = format!("{:02}:{:02}", self.below(24), self.below(60));
+                UnitAlarmTime::from_values(self.unit(), self.text(), time)
+            })
+            .collect();
+        let extra_fields: IndexMap<String, String> = (0..self.below(3))
+            .map(|i| (format!("Zusatz{}", i), self.text()))
+            .collect();
+        return Emergency {
+            town: self.text(),
+            district: self.text(),
+            location: self.text(),
+            location_addition: self.optional(),
+            street: self.text(),
+            house_number: self.text(),
+            object: self.optional(),
+            fire_department_plan: self.optional(),
+            object_part: self.optional(),
+            object_number: (self.below(2) == 0).then(|| self.below(100_000) as i64),
+            emergency_type: self.text(),
+            keyword: self.text(),
+            code3: self.text(),
+            emergency_number: 1 + self.below(1_000_000_000) as u64,
+            note: self.optional(),
+            patient_name: self.optional(),
+            dispatched_units: (0..self.below(6))
+                .map(|_| match RadioIdentifier::from_str(&self.unit()) {
+                    Ok(id) => Either::Left(id),
+                    Err(_) => Either::Right(format!("RLS BRB DGL {}", self.below(10))),
+                })
+                .collect(),
+            unit_alarm_times,
+            alarm_time,
+            coordinates: None,
+            extra_fields,
+        };
+    }
+}
+
+#[test]
+fn test_parse_write_parse_property() {
+    let mut rng = TestRng(0x5eed_cafe_f00d_beef);
+    let qp = MailWriterOptions {
+        quoted_printable: true,
+        ..Default::default()
+    };
+    for _ in 0..500 {
+        let generated = rng
+            .emergency()
+            .to_mail_string(&MailWriterOptions::default());
+        let parsed = match Emergency::parse_with_report(&generated) {
+            Recoverable::Ok((parsed, _)) => parsed,
+            // the extra fields are reported as unknown properties
+            Recoverable::Recoverable((parsed, report))
+                if report.max_severity() == Some(Severity::Info) =>
+            {
+                parsed
+            }
+            Recoverable::Recoverable((_, report)) | Recoverable::Unrecoverable(report) => {
+                panic!("{:?} in generated mail:\n{}", report.issues, generated)
+            }
+        };
+
+        // the first parse normalizes the values (e.g. trailing non ascii characters of a station are cut),
+        // from then on parsing and writing are lossless
+        let written = parsed.to_mail_string(&MailWriterOptions::default());
+        let reparsed = Emergency::from_str(&written).unwrap();
+        assert_eq!(reparsed, parsed, "{}", written);
+        assert_eq!(
+            reparsed.to_mail_string(&MailWriterOptions::default()),
+            written
+        );
+
+        let encoded = parsed.to_mail_string(&qp);
+        let decoded = Emergency::from_str(&mail_str_decode_unicode(&encoded)).unwrap();
+        assert_eq!(decoded, parsed, "{}", encoded);
+    }
+}
+
+#[test]
+fn test_write_drill_mail() {
+    let dir = env::temp_dir().join("emergency_mail_test_drill");
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let mail_path = dir.join("uebung.txt");
+
+    write_drill_mail("examples/emergency_simple.json".as_ref(), &mail_path).unwrap();
+    let mail = fs::read_to_string(&mail_path).unwrap();
+    assert_eq!(
+        Emergency::from_str(&mail).unwrap(),
+        Emergency::from_str(TEST_MAIL_CONTENT).unwrap()
+    );
+    assert!(!dir.join("uebung.tmp").exists());
+
+    // columns unknown to the parser are written, too
+    let mut json: serde_json::Value = serde_json::from_str(TEST_MAIL_JSON).unwrap();
+    json["unit_alarm_times"][0]["extra_columns"] = serde_json::json!({ "Funkkanal": "K1" });
+    let json_path = dir.join("uebung.json");
+    fs::write(&json_path, json.to_string()).unwrap();
+    write_drill_mail(&json_path, &mail_path).unwrap();
+    let ems = Emergency::from_str(&fs::read_to_string(&mail_path).unwrap()).unwrap();
+    assert_eq!(ems.unit_alarm_times[0].extra_columns["Funkkanal"], "K1");
+
+    let err = write_drill_mail(dir.join("missing.json").as_ref(), &mail_path).unwrap_err();
+    assert!(err.contains("missing.json"), "{}", err);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_write_table_columns() {
+    let ems = Emergency::from_str(TEST_MAIL_CONTENT).unwrap();
+    let options = MailWriterOptions {
+        table_columns: vec![
+            AlarmTableColumn::Unit,
+            AlarmTableColumn::AlarmTime,
+            AlarmTableColumn::Station,
+        ],
+        ..Default::default()
+    };
+    let written = ems.to_mail_string(&options);
+    assert!(written.contains("~~Status~~Fahrzeug~~Alarmiert~~Wache~~\r\n"));
+    assert!(written.contains("~~ALARM~~FL BRB 01/16-21~~08:21~~BRB FW Brandenburg 1~~\r\n"));
+    // the tableau address isn't written with these columns
+    let mut expected = Emergency::from_str(TEST_MAIL_CONTENT).unwrap();
+    for unit in &mut expected.unit_alarm_times {
+        unit.tableau_address = None;
+    }
+    assert_eq!(Emergency::from_str(&written).unwrap(), expected);
+
+    let encoded = ems.to_mail_string(&MailWriterOptions {
+        quoted_printable: true,
+        ..options
+    });
+    assert!(encoded.contains("~~Ortsteil~~G=C3=B6ttin/BRB~~\r\n"));
+}
+
+#[test]
+fn test_unit_alarm_timestamps() {
+    let ems = Emergency::from_str(include_str!("../../examples/emergency_bgebg.txt")).unwrap();
+    let alarm = ems.alarm_time_local().unwrap().fixed_offset();
+    assert_eq!(alarm.to_rfc3339(), "2023-08-06T00:00:00+02:00");
+
+    let minutes: Vec<i64> = ems
+        .unit_alarm_times
+        .iter()
+        .map(|u| u.minutes_since_alarm(alarm).unwrap())
+        .collect();
+    assert_eq!(
+        minutes,
+        vec![1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 12, 11, 11, 13, 14, 15]
+    );
+
+    let sorted: Vec<&str> = ems
+        .unit_alarm_times_by_time()
+        .iter()
+        .map(|u| u.alarm_time.as_str())
+        .collect();
+    assert_eq!(sorted[9..13], ["00:10", "00:11", "00:11", "00:12"]);
+}
+
+#[test]
+fn test_alarm_table_departure_and_extra_columns() {
+    const MAIL: &str = "~~Alarmzeit~~29.09.22&08:23~~\n\
+        ~~Status~~Tableau-Adresse~~Wache~~Fahrzeug~~Alarmiert~~Ausgerückt~~Status~~\n\
+        ~~ALARM~~unbekannt#~~BRB FW Brandenburg 1~~FL BRB 01/16-21~~08:21~~08:25~~3~~\n\
+        ~~ALARM~~~~BRB FW Brandenburg 1~~RLS BRB DGL 2~~08:22~~~~~~\n";
+    let ems = Emergency::from_str(MAIL).unwrap();
+    assert_eq!(ems.unit_alarm_times.len(), 2);
+
+    let first = &ems.unit_alarm_times[0];
+    assert_eq!(first.tableau_address.as_deref(), Some("unbekannt#"));
+    assert_eq!(first.departure_time.as_deref(), Some("08:25"));
+    assert_eq!(
+        first.departure_timestamp.unwrap().to_rfc3339(),
+        "2022-09-29T08:25:00+02:00"
+    );
+    assert_eq!(
+        first.extra_columns.get("Status").map(String::as_str),
+        Some("3")
+    );
+
+    let second = &ems.unit_alarm_times[1];
+    assert_eq!(second.tableau_address, None);
+    assert_eq!(second.departure_time, None);
+    assert_eq!(second.departure_timestamp, None);
+
+    let options = MailWriterOptions {
+        table_columns: vec![
+            AlarmTableColumn::TableauAddress,
+            AlarmTableColumn::Station,
+            AlarmTableColumn::Unit,
+            AlarmTableColumn::AlarmTime,
+            AlarmTableColumn::Departed,
+            AlarmTableColumn::Other("Status".to_string()),
+        ],
+        ..Default::default()
+    };
+    let written = ems.to_mail_string(&options);
+    assert!(written.contains("~~08:21~~08:25~~3~~\r\n"), "{}", written);
+    let parsed = Emergency::from_str(&written).unwrap();
+    assert_eq!(parsed.unit_alarm_times, ems.unit_alarm_times);
+}
+
+#[test]
+fn test_extra_fields() {
+    const MAIL: &str = "~~Ort~~Kleinmachnow~~\r\n~~Meldender~~Müller~~\r\n~~Rueckruf~~0331~~123~~\r\n~~Leer~~~~\r\n~~Einsatznummer~~322088295~~\r\n";
+    let ems = Emergency::from_str(MAIL).unwrap();
+    assert_eq!(ems.town, "Kleinmachnow");
+    assert_eq!(ems.emergency_number, 322088295);
+    let fields: Vec<(&str, &str)> = ems
+        .extra_fields
+        .iter()
+        .map(|(k, v)| (k.as_str(), v.as_str()))
+        .collect();
+    assert_eq!(
+        fields,
+        vec![
+            ("Meldender", "Müller"),
+            ("Rueckruf", "0331, 123"),
+            ("Leer", "")
+        ]
+    );
+
+    let written = ems.to_mail_string(&MailWriterOptions::default());
+    assert!(written.contains("~~Meldender~~Müller~~\r\n"));
+    let parsed = Emergency::from_str(&written).unwrap();
+    assert_eq!(parsed.extra_fields, ems.extra_fields);
+}
+
+#[test]
+fn test_detect_format() {
+    for mail in EXAMPLE_MAILS {
+        let parser = detect_parser(&mail_str_decode_unicode(mail)).unwrap();
+        assert_eq!(parser.name(), "ELS");
+    }
+    let parser = detect_parser(ALARMFAX_MAIL).unwrap();
+    assert_eq!(parser.name(), "Alarmfax");
+    assert!(detect_parser("Hallo,\nbitte Rückruf: 0331 123\n").is_none());
+
+    let Recoverable::Unrecoverable(report) = parse_mail("Hallo", &ParsingConfig::default()) else {
+        panic!("expected an unknown format");
+    };
+    assert_eq!(report.issues[0].kind, ParseIssueKind::UnknownFormat);
+}
+
+#[test]
+fn test_parse_alarmfax() {
+    let config = ParsingConfig {
+        mode: ParsingMode::Validate,
+        ..Default::default()
+    };
+    let Recoverable::Recoverable((ems, report)) = parse_mail(ALARMFAX_MAIL, &config) else {
+        panic!("expected a recoverable parse");
+    };
+    let els = Emergency::from_str(TEST_MAIL_CONTENT).unwrap();
+
+    assert_eq!(ems.emergency_number, els.emergency_number);
+    assert_eq!(ems.alarm_time, els.alarm_time);
+    assert_eq!(ems.keyword, els.keyword);
+    assert_eq!(ems.emergency_type, els.emergency_type);
+    assert_eq!(ems.code3, els.code3);
+    assert_eq!(ems.town, els.town);
+    assert_eq!(ems.district, els.district);
+    assert_eq!(ems.location, els.location);
+    assert_eq!(ems.street, els.street);
+    assert_eq!(ems.house_number, els.house_number);
+    assert_eq!(ems.object, None);
+    assert_eq!(ems.dispatched_units, els.dispatched_units);
+    assert_eq!(
+        ems.coordinates.unwrap().wgs84,
+        els.coordinates.unwrap().wgs84
+    );
+    assert_eq!(
+        ems.note.as_deref(),
+        Some("TESTETESTTESTE\nZufahrt über den Feldweg")
+    );
+    assert_eq!(
+        ems.extra_fields.get("Meldender").map(String::as_str),
+        Some("Müller")
+    );
+    assert!(ems.unit_alarm_times.is_empty());
+
+    // the unknown label is the only issue, the missing alarm table is not reported
+    assert_eq!(report.issues.len(), 1);
+    assert_eq!(
+        report.issues[0].kind,
+        ParseIssueKind::UnknownProperty {
+            property: "Meldender".to_string()
+        }
+    );
+    assert_eq!(report.issues[0].line, 15);
+}
+
+#[test]
+fn test_parse_alarmfax_address_labels() {
+    const MAIL: &str = "Stichwort: B:Klein\nEinsatzort: Hauptstr. 5a, Kleinmachnow\nStraße: Zehlendorfer Damm\nEinsatznummer: 12a\n";
+    let Recoverable::Recoverable((ems, report)) = AlarmfaxParser.parse_with_report(MAIL) else {
+        panic!("expected a recoverable parse");
+    };
+    // explicit labels take precedence over the Einsatzort line
+    assert_eq!(ems.street, "Zehlendorfer Damm");
+    assert_eq!(ems.house_number, "5a");
+    assert_eq!(ems.town, "Kleinmachnow");
+    assert_eq!(ems.district, "");
+    assert_eq!(report.max_severity(), Some(Severity::Error));
+}
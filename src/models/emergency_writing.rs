@@ -0,0 +1,273 @@
+use std::{fmt::Write, fs, path::Path};
+
+use log::warn;
+
+use super::{either::Either, emergency::Emergency, unit_alarm_time::UnitAlarmTime};
+
+/// maximum line length of quoted-printable encoded text (RFC 2045), excluding the soft line break
+const QP_MAX_LINE_LENGTH: usize = 75;
+const LINE_END: &str = "\r\n";
+
+/// The columns of the `~~Status~~` alarm table, named by their header in the mail.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AlarmTableColumn {
+    TableauAddress,
+    Station,
+    Unit,
+    AlarmTime,
+    Departed,
+    /// a column unknown to the parser, written from [UnitAlarmTime::extra_columns]
+    Other(String),
+}
+
+impl AlarmTableColumn {
+    pub fn header(&self) -> &str {
+        return match self {
+            AlarmTableColumn::TableauAddress => "Tableau-Adresse",
+            AlarmTableColumn::Station => "Wache",
+            AlarmTableColumn::Unit => "Fahrzeug",
+            AlarmTableColumn::AlarmTime => "Alarmiert",
+            AlarmTableColumn::Departed => "Ausgerückt",
+            AlarmTableColumn::Other(header) => header,
+        };
+    }
+
+    fn value(&self, unit: &UnitAlarmTime) -> String {
+        return match self {
+            AlarmTableColumn::TableauAddress => unit.tableau_address.clone().unwrap_or_default(),
+            AlarmTableColumn::Station => unit.station.clone(),
+            AlarmTableColumn::Unit => match &unit.unit_id {
+                Either::Left(id) => id.to_string(),
+                Either::Right(id) => id.clone(),
+            },
+            AlarmTableColumn::AlarmTime => unit.alarm_time.clone(),
+            AlarmTableColumn::Departed => unit.departure_time.clone().unwrap_or_default(),
+            AlarmTableColumn::Other(header) => {
+                unit.extra_columns.get(header).cloned().unwrap_or_default()
+            }
+        };
+    }
+}
+
+/// Options for [Emergency::to_mail_string].
+#[derive(Debug, Clone)]
+pub struct MailWriterOptions {
+    /// the columns (and their order) of the alarm table
+    pub table_columns: Vec<AlarmTableColumn>,
+    /// encode non ascii characters as quoted-printable, as done by the dispatch centre's mail server
+    pub quoted_printable: bool,
+}
+
+impl Default for MailWriterOptions {
+    /// the layout of the ELS export (see examples/emergency_simple.txt)
+    fn default() -> Self {
+        return MailWriterOptions {
+            table_columns: vec![
+                AlarmTableColumn::TableauAddress,
+                AlarmTableColumn::Station,
+                AlarmTableColumn::Unit,
+                AlarmTableColumn::AlarmTime,
+                AlarmTableColumn::Departed,
+            ],
+            quoted_printable: false,
+        };
+    }
+}
+
+/// escapes a value, so that it can't end the `~~Key~~Value~~` section early.
+fn escape_value(value: &str) -> String {
+    if value.contains('~') {
+        warn!("replacing '~' in value {}, as it is not allowed", value);
+        return value.replace('~', "-");
+    }
+    return value.to_string();
+}
+
+fn write_property(out: &mut String, key: &str, values: &[&str]) {
+    out.push_str("~~");
+    out.push_str(key);
+    for value in values {
+        out.push_str("~~");
+        out.push_str(&escape_value(value));
+    }
+    out.push_str("~~");
+    out.push_str(LINE_END);
+}
+
+/// formats decimal degrees as in the `Koord_EPSG_4326` line, e.g. 12.48630 -> E1248630
+fn format_epsg_4326(degrees: f64, positive: char, negative: char) -> String {
+    let direction = if degrees < 0.0 { negative } else { positive };
+    return format!("{}{:07.0}", direction, degrees.abs() * 100_000.0);
+}
+
+/// Encodes a text as quoted-printable, in the subset understood by [mail_str_decode_unicode].
+///
+/// # description
+/// Two byte UTF-8 characters (e.g. umlauts) are escaped as `=XX=XX` and `=` as `=3D`, all other characters are kept.
+/// Lines longer than 76 characters are wrapped with soft line breaks (`=\r\n`), escape sequences are never split.
+///
+/// [mail_str_decode_unicode]: crate::connection::message::mail_str_decode_unicode
+pub fn mail_str_encode_unicode(str: &str) -> String {
+    let mut encoded = String::with_capacity(str.len() * 2);
+    let mut line_length = 0;
+
+    for c in str.chars() {
+        if c == '\n' {
+            encoded.push(c);
+            line_length = 0;
+            continue;
+        }
+
+        let mut sequence = String::with_capacity(6);
+        if c == '=' {
+            sequence.push_str("=3D");
+        } else if c.len_utf8() == 2 {
+            let mut buffer = [0; 2];
+            for byte in c.encode_utf8(&mut buffer).bytes() {
+                let _ = write!(sequence, "={:02X}", byte);
+            }
+        } else {
+            sequence.push(c);
+        }
+
+        let length = sequence.chars().count();
+        if line_length + length > QP_MAX_LINE_LENGTH && c != '\r' {
+            encoded.push('=');
+            encoded.push_str(LINE_END);
+            line_length = 0;
+        }
+        encoded.push_str(&sequence);
+        line_length += length;
+    }
+    return encoded;
+}
+
+impl Emergency {
+    /// Renders the emergency in the `~~Key~~Value~~` format of the dispatch centre.
+    ///
+    /// This is the inverse of [Emergency::from_str], i.e. parsing the result yields the same emergency
+    /// (except for projected coordinates, which are transmitted with limited precision).
+    pub fn to_mail_string(&self, options: &MailWriterOptions) -> String {
+        let mut out = String::new();
+        let optional = |value: &Option<String>| value.clone().unwrap_or_default();
+
+        write_property(&mut out, "Ort", &[&self.town]);
+        write_property(&mut out, "Ortsteil", &[&self.district]);
+        write_property(&mut out, "Ortslage", &[&self.location]);
+        write_property(&mut out, "Strasse", &[&self.street]);
+        write_property(&mut out, "Hausnummer", &[&self.house_number]);
+        write_property(&mut out, "Objekt", &[&optional(&self.object)]);
+        write_property(&mut out, "FWPlan", &[&optional(&self.fire_department_plan)]);
+        write_property(&mut out, "Objektteil", &[&optional(&self.object_part)]);
+        write_property(
+            &mut out,
+            "Objektnummer",
+            &[&self.object_number.unwrap_or(-1).to_string()],
+        );
+        write_property(&mut out, "Einsatzart", &[&self.emergency_type]);
+        write_property(&mut out, "Alarmgrund", &[&self.keyword]);
+        write_property(&mut out, "Sondersignal", &[&self.code3]);
+        write_property(
+            &mut out,
+            "Einsatznummer",
+            &[&self.emergency_number.to_string()],
+        );
+        write_property(&mut out, "Besonderheiten", &[&optional(&self.note)]);
+        let patient = self.patient_name.clone().unwrap_or(",".to_string());
+        write_property(&mut out, "Name", &[&patient]);
+
+        let units = self
+            .dispatched_units
+            .iter()
+            .map(|u| match u {
+                Either::Left(id) => id.to_string(),
+                Either::Right(id) => id.clone(),
+            })
+            .collect::<Vec<String>>()
+            .join(", ");
+        write_property(&mut out, "EMListe", &[&units]);
+
+        let headers: Vec<&str> = options.table_columns.iter().map(|c| c.header()).collect();
+        write_property(&mut out, "Status", &headers);
+        for unit in &self.unit_alarm_times {
+            let values: Vec<String> = options
+                .table_columns
+                .iter()
+                .map(|c| c.value(unit))
+                .collect();
+            let values: Vec<&str> = values.iter().map(String::as_str).collect();
+            write_property(&mut out, "ALARM", &values);
+        }
+
+        if let Some(coordinates) = &self.coordinates {
+            let wgs84 = coordinates.wgs84;
+            let utm33 = coordinates.utm33;
+            write_property(&mut out, "WGS84_X", &[&wgs84.latitude.to_string()]);
+            write_property(&mut out, "WGS84_Y", &[&wgs84.longitude.to_string()]);
+            write_property(
+                &mut out,
+                "Koord_EPSG_25833",
+                &[
+                    &format!("{:.2}", utm33.easting),
+                    &format!("{:.2}", utm33.northing),
+                ],
+            );
+            write_property(
+                &mut out,
+                "Koord_EPSG_4326",
+                &[
+                    &format_epsg_4326(wgs84.longitude, 'E', 'W'),
+                    &format_epsg_4326(wgs84.latitude, 'N', 'S'),
+                ],
+            );
+        }
+
+        write_property(
+            &mut out,
+            "Einsatzortzusatz",
+            &[&optional(&self.location_addition)],
+        );
+        for (key, value) in &self.extra_fields {
+            write_property(&mut out, key, &[value]);
+        }
+        write_property(
+            &mut out,
+            "Alarmzeit",
+            &[&self.alarm_time.format("%d.%m.%y&%H:%M").to_string()],
+        );
+
+        if options.quoted_printable {
+            return mail_str_encode_unicode(&out);
+        }
+        return out;
+    }
+}
+
+/// Writes an emergency given as json (see doc/emergency_json.md) as ELS alarm mail, e.g. to drop a drill alarm
+/// into the spool directory.
+///
+/// # description
+/// the mail is written to a temporary file first and renamed, so a watched spool never reads it half written.
+pub fn write_drill_mail(json_path: &Path, mail_path: &Path) -> Result<(), String> {
+    let json = fs::read_to_string(json_path)
+        .map_err(|e| format!("couldn't read {:?}: {}", json_path, e))?;
+    let ems: Emergency = serde_json::from_str(&json)
+        .map_err(|e| format!("invalid emergency json in {:?}: {}", json_path, e))?;
+    // columns unknown to the parser are kept, behind the columns of the ELS export
+    let mut options = MailWriterOptions::default();
+    for unit in &ems.unit_alarm_times {
+        for header in unit.extra_columns.keys() {
+            let column = AlarmTableColumn::Other(header.clone());
+            if !options.table_columns.contains(&column) {
+                options.table_columns.push(column);
+            }
+        }
+    }
+    let mail = ems.to_mail_string(&options);
+
+    let tmp = mail_path.with_extension("tmp");
+    fs::write(&tmp, mail).map_err(|e| format!("couldn't write {:?}: {}", tmp, e))?;
+    fs::rename(&tmp, mail_path)
+        .map_err(|e| format!("couldn't move {:?} to {:?}: {}", tmp, mail_path, e))?;
+    return Ok(());
+}
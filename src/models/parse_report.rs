@@ -0,0 +1,124 @@
+use std::fmt::Display;
+
+use serde::{Deserialize, Serialize};
+
+use super::emergency_field::EmergencyField;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// the mail contained something unexpected, but no information relevant to the printout was lost
+    Info,
+    /// information was lost, the printout might be incomplete
+    Warning,
+    /// a mandatory field could not be read
+    Error,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ParseIssueKind {
+    /// the rest of the line was skipped, because it didn't match the `~~Key~~Value~~` format
+    SkippedLine,
+    UnknownProperty {
+        property: String,
+    },
+    MalformedTableRow,
+    ConversionFailed {
+        field: String,
+        value: String,
+    },
+    /// several fields describe the same information, but contradict each other
+    InconsistentValues {
+        field: String,
+    },
+    /// a configured mandatory field is missing, only reported when validating (line 0)
+    MissingField {
+        field: EmergencyField,
+    },
+    /// the mail doesn't match any known dispatch centre format (line 0)
+    UnknownFormat,
+}
+
+/// A single problem found while parsing an alarm mail.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ParseIssue {
+    pub line: u64,
+    pub severity: Severity,
+    #[serde(flatten)]
+    pub kind: ParseIssueKind,
+    pub message: String,
+}
+
+/// Collects all problems found while parsing an alarm mail.
+///
+/// The report is serializable, so it can be handed to other tools (e.g. to notify an admin).
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ParseReport {
+    pub issues: Vec<ParseIssue>,
+}
+
+impl ParseReport {
+    pub fn push(&mut self, line: u64, severity: Severity, kind: ParseIssueKind, message: String) {
+        self.issues.push(ParseIssue {
+            line,
+            severity,
+            kind,
+            message,
+        });
+    }
+
+    pub fn info(&mut self, line: u64, kind: ParseIssueKind, message: String) {
+        self.push(line, Severity::Info, kind, message);
+    }
+
+    pub fn warning(&mut self, line: u64, kind: ParseIssueKind, message: String) {
+        self.push(line, Severity::Warning, kind, message);
+    }
+
+    pub fn error(&mut self, line: u64, kind: ParseIssueKind, message: String) {
+        self.push(line, Severity::Error, kind, message);
+    }
+
+    /// the highest severity of all issues, None if no issues were found
+    pub fn max_severity(&self) -> Option<Severity> {
+        return self.issues.iter().map(|i| i.severity).max();
+    }
+
+    /// whether information was lost while parsing (i.e. the printout should carry a warning)
+    pub fn is_incomplete(&self) -> bool {
+        return self
+            .max_severity()
+            .is_some_and(|severity| severity >= Severity::Warning);
+    }
+
+    /// the mandatory fields, that were reported as missing
+    pub fn missing_fields(&self) -> Vec<EmergencyField> {
+        return self
+            .issues
+            .iter()
+            .filter_map(|i| match i.kind {
+                ParseIssueKind::MissingField { field } => Some(field),
+                _ => None,
+            })
+            .collect();
+    }
+
+    pub fn count(&self, min_severity: Severity) -> usize {
+        return self
+            .issues
+            .iter()
+            .filter(|i| i.severity >= min_severity)
+            .count();
+    }
+}
+
+impl Display for ParseIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:?} in line {}: {}",
+            self.severity, self.line, self.message
+        )
+    }
+}
@@ -1,3 +1,4 @@
+#[derive(Debug)]
 pub enum Recoverable<T, E> {
     Ok(T),
     Unrecoverable(E),
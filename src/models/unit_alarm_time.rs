@@ -1,12 +1,30 @@
 use std::str::FromStr;
 
+use chrono::{DateTime, FixedOffset};
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+
 use super::{either::Either, radio_identifier::RadioIdentifier};
 
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
 pub struct UnitAlarmTime {
     pub unit_id: Either<RadioIdentifier, String>,
     pub station: String,
+    /// the alarm time as transmitted (e.g. `08:21`)
     pub alarm_time: String,
+    /// the alarm time resolved on the date of the emergency, None if it couldn't be parsed
+    #[serde(default)]
+    pub alarm_timestamp: Option<DateTime<FixedOffset>>,
+    /// the departure time (`Ausgerückt`) as transmitted, only filled in update mails
+    #[serde(default)]
+    pub departure_time: Option<String>,
+    #[serde(default)]
+    pub departure_timestamp: Option<DateTime<FixedOffset>>,
+    #[serde(default)]
+    pub tableau_address: Option<String>,
+    /// all columns of the alarm table, that are not known to the parser (header -> value), in the order of the mail
+    #[serde(default)]
+    pub extra_columns: IndexMap<String, String>,
 }
 
 impl UnitAlarmTime {
@@ -18,12 +36,30 @@ impl UnitAlarmTime {
                 unit_id: radio_id.to_left(),
                 station,
                 alarm_time,
+                alarm_timestamp: None,
+                departure_time: None,
+                departure_timestamp: None,
+                tableau_address: None,
+                extra_columns: IndexMap::new(),
             },
             Err(_) => UnitAlarmTime {
                 unit_id: Either::Right(unit_id),
                 station,
                 alarm_time,
+                alarm_timestamp: None,
+                departure_time: None,
+                departure_timestamp: None,
+                tableau_address: None,
+                extra_columns: IndexMap::new(),
             },
         };
     }
+
+    /// minutes between the alarm of the emergency and the alarm of this unit
+    /// (negative, if the unit was alarmed before the emergency's alarm time).
+    pub fn minutes_since_alarm(&self, emergency_alarm: DateTime<FixedOffset>) -> Option<i64> {
+        return self
+            .alarm_timestamp
+            .map(|t| (t - emergency_alarm).num_minutes());
+    }
 }
@@ -122,6 +122,18 @@ pub trait PageBuilder {
     fn add_horizontal_divider(&mut self, y: f32);
 
     fn add_img(&mut self, content: &[u8], x: f32, y: f32, width: f32, height: f32);
+
+    /// Adds an image attached to the alarm mail (png, jpeg or bmp), scaled to fit into the given box
+    /// while keeping its aspect ratio. (x, y) is the upper left corner.
+    fn add_attachment_img(
+        &mut self,
+        content: &[u8],
+        mime_type: &str,
+        x: f32,
+        y: f32,
+        max_width: f32,
+        max_height: f32,
+    ) -> Result<(), DocumentBuildingError>;
 }
 
 pub trait Printable {
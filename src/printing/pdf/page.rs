@@ -1,13 +1,14 @@
 use std::{cell::RefCell, io::Cursor, rc::Weak};
 
 use printpdf::{
-    image_crate::codecs, Color, Image, ImageTransform, IndirectFontRef, Line, Mm,
-    PdfDocumentReference, PdfLayer, PdfLayerIndex, PdfLayerReference, PdfPageIndex, Rgb,
+    image_crate::{codecs, ImageError},
+    Color, Image, ImageTransform, IndirectFontRef, Line, Mm, PdfDocumentReference, PdfLayer,
+    PdfLayerIndex, PdfLayerReference, PdfPageIndex, Rgb,
 };
 
 use crate::{
     font_size, line_thickness, points_to_mm,
-    printing::document::{DrawingAttributes, PageBuilder, Point},
+    printing::document::{DocumentBuildingError, DrawingAttributes, PageBuilder, Point},
     text_line_height,
 };
 
@@ -22,6 +23,8 @@ pub struct PDFPage {
 
 pub const MARGIN_HORIZONTAL: f32 = 15.0;
 pub const MARGIN_VERTICAL: f32 = 20.0;
+const ATTACHMENT_IMAGE_DPI: f32 = 300.0;
+const MM_PER_INCH: f32 = 25.4;
 /// the height of one line in pts
 /// use the [point_to_mm!()] macro to convert to mm
 
@@ -222,4 +225,51 @@ impl PageBuilder for PDFPage {
             },
         );
     }
+
+    fn add_attachment_img(
+        &mut self,
+        content: &[u8],
+        mime_type: &str,
+        x: f32,
+        y: f32,
+        max_width: f32,
+        max_height: f32,
+    ) -> Result<(), DocumentBuildingError> {
+        let nested = |e: ImageError| DocumentBuildingError::NestedError(Box::new(e));
+        let cursor = Cursor::new(content);
+        let image = match mime_type {
+            "image/png" => Image::try_from(codecs::png::PngDecoder::new(cursor).map_err(nested)?),
+            "image/jpeg" | "image/jpg" => {
+                Image::try_from(codecs::jpeg::JpegDecoder::new(cursor).map_err(nested)?)
+            }
+            "image/bmp" => Image::try_from(codecs::bmp::BmpDecoder::new(cursor).map_err(nested)?),
+            _ => {
+                return Err(DocumentBuildingError::Error(format!(
+                    "unsupported image type {}",
+                    mime_type
+                )))
+            }
+        }
+        .map_err(nested)?;
+
+        // natural size of the image in mm at the given resolution
+        let width = image.image.width.0 as f32 / ATTACHMENT_IMAGE_DPI * MM_PER_INCH;
+        let height = image.image.height.0 as f32 / ATTACHMENT_IMAGE_DPI * MM_PER_INCH;
+        let scale = (max_width / width).min(max_height / height);
+
+        let layer = self.get_current_layer();
+        image.add_to_layer(
+            layer,
+            ImageTransform {
+                translate_x: Some(Mm(x)),
+                // pdf coordinates start at the bottom of the page, at the lower left corner of the image
+                translate_y: Some(Mm(self.dimensions.1 - y - height * scale)),
+                scale_x: Some(scale),
+                scale_y: Some(scale),
+                dpi: Some(ATTACHMENT_IMAGE_DPI),
+                ..Default::default()
+            },
+        );
+        return Ok(());
+    }
 }
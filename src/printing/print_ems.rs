@@ -1,4 +1,4 @@
-use chrono::Local;
+use chrono::{DateTime, FixedOffset, Local};
 use std::path::PathBuf;
 use std::{
     cmp::{max, min},
@@ -8,11 +8,20 @@ use std::{
     rc::Rc,
 };
 
-use log::{debug, error, info, trace};
+use log::{debug, error, info, trace, warn};
+use printpdf::image_crate::{self, ImageFormat};
 
 use crate::{
-    config::Config,
-    models::{either::Either, emergency::Emergency},
+    config::{config::AttachmentPrintMode, Config},
+    models::{
+        attachment::Attachment,
+        either::Either,
+        emergency::Emergency,
+        emergency_diff::{unit_name, EmergencyDiff},
+        parse_report::{ParseReport, Severity},
+        radio_identifier::RadioIdentifier,
+        unit_alarm_time::UnitAlarmTime,
+    },
     points_to_mm,
     printing::{
         document::{DocumentBuildingError, Printable},
@@ -54,6 +63,8 @@ impl DrawingAttributes {
 }
 
 const LABEL_OFFSET: f32 = 18.0;
+const HEADER_TOP: f32 = 25.0;
+const HEADER_BOTTOM: f32 = 40.0;
 const SECTION_OFFSET: f32 = 15.0;
 const CHAR_WIDTH_40: f32 = 2.5;
 const DEFAULT_FONT_SIZE: f32 = 12.0;
@@ -63,12 +74,76 @@ struct AlarmTableOffsets {
     // radioId is always = LABEL_OFFSET
     station: f32,
     time: f32,
+    minutes: f32,
 }
 
-pub fn print_emergency(ems: Emergency, config: &Config) {
+pub fn print_emergency(
+    ems: Emergency,
+    report: &ParseReport,
+    attachments: &[Attachment],
+    config: &Config,
+) {
+    let attachments: Vec<&Attachment> = attachments
+        .iter()
+        .filter(|a| {
+            let allowed = config
+                .printing
+                .attachments
+                .allows(&a.mime_type, a.data.len());
+            if !allowed {
+                info!(
+                    "not printing attachment {:?} ({}, {} bytes)",
+                    a.filename,
+                    a.mime_type,
+                    a.data.len()
+                );
+            }
+            allowed
+        })
+        .collect();
+    // images can be appended to the emergency document, pdf attachments are always printed separately
+    let (appended, separate): (Vec<&Attachment>, Vec<&Attachment>) =
+        match config.printing.attachments.mode {
+            AttachmentPrintMode::Append => attachments.into_iter().partition(|a| a.is_image()),
+            _ => (Vec::new(), attachments),
+        };
+
     let mut doc = PDFDocument::new();
-    create_emergency_doc(&ems, &mut doc, config);
+    create_emergency_doc(&ems, report, &mut doc, config);
+    add_attachment_pages(&appended, &mut doc);
+
+    let Some(ems_dir) = output_path(&ems.keyword, config) else {
+        return;
+    };
+    info!("saving to: {:?}", &ems_dir);
+    save_pdf(doc, &ems_dir);
+
+    let copies = count_copies(&ems, config);
+    let printer = PDFFilePrinter::new(ems_dir.as_path());
+    printer.print(copies, config);
 
+    print_separate_attachments(&separate, &ems_dir, copies, config);
+}
+
+/// Prints the changes of an emergency sent again by the dispatch centre (Nachalarmierung),
+/// instead of printing the whole emergency again.
+pub fn print_update(ems: &Emergency, diff: &EmergencyDiff, config: &Config) {
+    let mut doc = PDFDocument::new();
+    create_update_doc(ems, diff, &mut doc, config);
+
+    let Some(path) = output_path(&format!("{}_Nachalarmierung", ems.keyword), config) else {
+        return;
+    };
+    info!("saving update to: {:?}", &path);
+    save_pdf(doc, &path);
+
+    let copies = count_copies(ems, config);
+    let printer = PDFFilePrinter::new(path.as_path());
+    printer.print(copies, config);
+}
+
+/// the path of the pdf to print, None if the directory couldn't be created
+fn output_path(keyword: &str, config: &Config) -> Option<PathBuf> {
     let mut ems_dir: PathBuf = if config.pdf_save_path.is_some() {
         Path::new(config.pdf_save_path.as_ref().unwrap().as_str()).to_path_buf()
     } else {
@@ -80,7 +155,7 @@ pub fn print_emergency(ems: Emergency, config: &Config) {
     let res = fs::create_dir_all(&ems_dir);
     if let Err(e) = res {
         error!("couldn't create temp dir: {}", e);
-        return;
+        return None;
     }
 
     ems_dir.push(format!(
@@ -88,40 +163,124 @@ pub fn print_emergency(ems: Emergency, config: &Config) {
         Local::now().format("%Y-%m-%d_%H-%M-%S"),
         // using - since windows does not allow : in file names
         // using current time since alarm time could be duplicated when multiple mails are send (e.g. resend)
-        ems.keyword.replace(':', "-"), // due to windows, see above.
+        keyword.replace(':', "-"), // due to windows, see above.
     ));
     if cfg!(debug_assertions) || (config.printing.disabled() && config.pdf_save_path.is_none()) {
         ems_dir = Path::new("test.pdf").to_path_buf();
     }
+    return Some(ems_dir);
+}
 
-    info!("saving to: {:?}", &ems_dir);
+fn save_pdf(doc: PDFDocument, path: &Path) {
     let docref = doc.document;
     let docref = Rc::try_unwrap(docref)
         .map_err(|_| DocumentBuildingError::Error("couldn't unwrap document".to_string()))
         .unwrap()
         .into_inner();
-    let file = fs::File::create(&ems_dir).unwrap();
+    let file = fs::File::create(path).unwrap();
     let mut writer = BufWriter::new(file);
     docref.save(&mut writer).unwrap();
-
-    let printer = PDFFilePrinter::new(ems_dir.as_path());
-    printer.print(count_copies(&ems, config), config);
 }
 
-pub(super) fn count_units_from_configured_amt(ems: &Emergency, config: &Config) -> usize {
-    let mut count = 0;
-    for unit in &ems.dispatched_units {
-        let Either::Left(unit) = unit else {
+/// adds one page per image attachment, returns the number of added pages
+pub(super) fn add_attachment_pages(
+    attachments: &[&Attachment],
+    doc: &mut dyn DocumentBuilder,
+) -> usize {
+    let mut added = 0;
+    for attachment in attachments {
+        // a page is only added for images, that can be read
+        if let Err(e) = decode_attachment_img(attachment) {
+            error!("couldn't read attachment {:?}: {}", attachment.filename, e);
             continue;
-        };
-        // skipped all units, that do not have a standard radio id (Funkkenner)
+        }
+        let page_id = doc.new_page().unwrap();
+        let page = doc.page_at(page_id).unwrap();
+        let (width, height) = page.get_dimnensions();
+        let res = page.add_attachment_img(
+            &attachment.data,
+            &attachment.mime_type,
+            SECTION_OFFSET,
+            SECTION_OFFSET,
+            width - 2.0 * SECTION_OFFSET,
+            height - 2.0 * SECTION_OFFSET,
+        );
+        match res {
+            Ok(()) => added += 1,
+            Err(e) => error!(
+                "couldn't add attachment {:?} to the document: {}",
+                attachment.filename, e
+            ),
+        }
+    }
+    return added;
+}
+
+/// decodes the image once to check it, before a page is added for it (only png, jpeg and bmp are supported)
+fn decode_attachment_img(attachment: &Attachment) -> Result<(), String> {
+    let format = match attachment.extension() {
+        ext @ ("png" | "jpg" | "bmp") => ImageFormat::from_extension(ext).unwrap(),
+        _ => return Err(format!("unsupported image type {}", attachment.mime_type)),
+    };
+    image_crate::load_from_memory_with_format(&attachment.data, format)
+        .map_err(|e| e.to_string())?;
+    return Ok(());
+}
 
-        if unit.agency == config.printing.amt && unit.county == "PM" && unit.org == "FL" {
-            // NOTE: county and org are hardcoded for now!
-            count += 1;
+/// saves each attachment next to the emergency document and prints it as its own job
+fn print_separate_attachments(
+    attachments: &[&Attachment],
+    ems_path: &Path,
+    copies: usize,
+    config: &Config,
+) {
+    let stem = ems_path
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+    for (i, attachment) in attachments.iter().enumerate() {
+        let path = ems_path.with_file_name(format!("{}_anhang_{}.pdf", stem, i + 1));
+        info!(
+            "saving attachment {:?} to: {:?}",
+            attachment.filename, &path
+        );
+        if attachment.is_image() {
+            // images are printed from a document of their own
+            let mut doc = PDFDocument::new();
+            if add_attachment_pages(&[attachment], &mut doc) == 0 {
+                continue;
+            }
+            save_pdf(doc, &path);
+        } else if !attachment.is_pdf() {
+            warn!(
+                "printing {} attachments is not supported, skipping {:?}",
+                attachment.mime_type, attachment.filename
+            );
+            continue;
+        } else if let Err(e) = fs::write(&path, &attachment.data) {
+            error!("couldn't save attachment: {}", e);
+            continue;
         }
+        let printer = PDFFilePrinter::new(path.as_path());
+        printer.print(copies, config);
     }
-    return count;
+}
+
+/// whether the unit belongs to the configured amt, units without a standard radio id (Funkkenner) never do
+fn is_home_unit(unit: &Either<RadioIdentifier, String>, config: &Config) -> bool {
+    let Either::Left(unit) = unit else {
+        return false;
+    };
+    // NOTE: county and org are hardcoded for now!
+    return unit.agency == config.printing.amt && unit.county == "PM" && unit.org == "FL";
+}
+
+pub(super) fn count_units_from_configured_amt(ems: &Emergency, config: &Config) -> usize {
+    return ems
+        .dispatched_units
+        .iter()
+        .filter(|unit| is_home_unit(unit, config))
+        .count();
 }
 
 pub(super) fn count_copies(ems: &Emergency, config: &Config) -> usize {
@@ -138,7 +297,8 @@ pub(super) fn count_copies(ems: &Emergency, config: &Config) -> usize {
     return count;
 }
 
-fn add_emergency_header_section(ems: &Emergency, page: &mut dyn PageBuilder) {
+/// adds the header boxes, returns the y coordinate where the content below starts
+fn add_emergency_header_section(ems: &Emergency, page: &mut dyn PageBuilder) -> f32 {
     // create header blocks
 
     page.add_img(LOGO, 142.0, 41.5, 200.0, 200.0);
@@ -147,56 +307,104 @@ fn add_emergency_header_section(ems: &Emergency, page: &mut dyn PageBuilder) {
         &[
             Point {
                 x: SECTION_OFFSET,
-                y: 25.0,
+                y: HEADER_TOP,
             },
             Point {
                 x: SECTION_OFFSET,
-                y: 40.0,
+                y: HEADER_BOTTOM,
+            },
+            Point {
+                x: 50.0,
+                y: HEADER_BOTTOM,
+            },
+            Point {
+                x: 50.0,
+                y: HEADER_TOP,
             },
-            Point { x: 50.0, y: 40.0 },
-            Point { x: 50.0, y: 25.0 },
         ],
         DrawingAttributes::OUTLINE_POLY,
     );
     page.add_outline_polygon(
         &[
-            Point { x: 50.0, y: 25.0 },
-            Point { x: 50.0, y: 40.0 },
-            Point { x: 78.0, y: 40.0 },
-            Point { x: 78.0, y: 25.0 },
+            Point {
+                x: 50.0,
+                y: HEADER_TOP,
+            },
+            Point {
+                x: 50.0,
+                y: HEADER_BOTTOM,
+            },
+            Point {
+                x: 78.0,
+                y: HEADER_BOTTOM,
+            },
+            Point {
+                x: 78.0,
+                y: HEADER_TOP,
+            },
         ],
         DrawingAttributes::OUTLINE_POLY,
     );
     page.add_outline_polygon(
         &[
-            Point { x: 78.0, y: 25.0 },
-            Point { x: 78.0, y: 40.0 },
-            Point { x: 103.0, y: 40.0 },
-            Point { x: 103.0, y: 25.0 },
+            Point {
+                x: 78.0,
+                y: HEADER_TOP,
+            },
+            Point {
+                x: 78.0,
+                y: HEADER_BOTTOM,
+            },
+            Point {
+                x: 103.0,
+                y: HEADER_BOTTOM,
+            },
+            Point {
+                x: 103.0,
+                y: HEADER_TOP,
+            },
         ],
         DrawingAttributes::OUTLINE_POLY,
     );
     page.add_outline_polygon(
         &[
-            Point { x: 103.0, y: 25.0 },
-            Point { x: 103.0, y: 40.0 },
-            Point { x: 142.0, y: 40.0 },
-            Point { x: 142.0, y: 25.0 },
+            Point {
+                x: 103.0,
+                y: HEADER_TOP,
+            },
+            Point {
+                x: 103.0,
+                y: HEADER_BOTTOM,
+            },
+            Point {
+                x: 142.0,
+                y: HEADER_BOTTOM,
+            },
+            Point {
+                x: 142.0,
+                y: HEADER_TOP,
+            },
         ],
         DrawingAttributes::OUTLINE_POLY,
     );
 
     page.add_outline_polygon(
         &[
-            Point { x: 142.0, y: 25.0 },
-            Point { x: 142.0, y: 40.0 },
+            Point {
+                x: 142.0,
+                y: HEADER_TOP,
+            },
+            Point {
+                x: 142.0,
+                y: HEADER_BOTTOM,
+            },
             Point {
                 x: page.get_dimnensions().0 - SECTION_OFFSET,
-                y: 40.0,
+                y: HEADER_BOTTOM,
             },
             Point {
                 x: page.get_dimnensions().0 - SECTION_OFFSET,
-                y: 25.0,
+                y: HEADER_TOP,
             },
         ],
         DrawingAttributes::OUTLINE_POLY,
@@ -225,15 +433,21 @@ fn add_emergency_header_section(ems: &Emergency, page: &mut dyn PageBuilder) {
         32.0,
         DrawingAttributes::LABEL,
     );
+
+    return HEADER_BOTTOM + 12.0;
 }
 
-fn create_emergency_doc(ems: &Emergency, doc: &mut dyn DocumentBuilder, config: &Config) {
+fn create_emergency_doc(
+    ems: &Emergency,
+    report: &ParseReport,
+    doc: &mut dyn DocumentBuilder,
+    config: &Config,
+) {
     let page_id = doc.new_page().unwrap();
     let page = doc.page_at(page_id).unwrap();
 
-    add_emergency_header_section(ems, page);
-
-    let mut curr_y = 52.0;
+    let curr_y = add_emergency_header_section(ems, page);
+    let mut curr_y = add_incomplete_banner(report, page, curr_y);
 
     let text = format!("{}\n{}", ems.keyword, ems.code3);
     curr_y = add_optional_property(page, "Stichwort:", Some(text), curr_y);
@@ -258,6 +472,15 @@ fn create_emergency_doc(ems: &Emergency, doc: &mut dyn DocumentBuilder, config:
 
     curr_y = add_optional_property(page, "Patient:", ems.get_patient_name(), curr_y);
 
+    for extra in &config.printing.extra_fields {
+        curr_y = add_optional_ml_property(
+            page,
+            extra.label.clone(),
+            ems.extra_fields.get(&extra.key).cloned(),
+            curr_y,
+        );
+    }
+
     page.add_horizontal_divider(curr_y);
 
     curr_y += points_to_mm!(text_line_height!(DrawingAttributes::FIELD_VALUE)) * 1.2;
@@ -276,31 +499,30 @@ fn create_emergency_doc(ems: &Emergency, doc: &mut dyn DocumentBuilder, config:
     let mut offsets = AlarmTableOffsets {
         station: 0.0,
         time: 0.0,
+        minutes: 0.0,
     };
-    let home_count = count_units_from_configured_amt(ems, config);
-    let remaining = create_unit_table(ems, page, curr_y, &mut offsets, home_count);
-    let printed = ems.unit_alarm_times.len() - remaining;
+    let units = sorted_unit_alarm_times(ems, config);
+    let home_count = units
+        .iter()
+        .filter(|u| is_home_unit(&u.unit_id, config))
+        .count();
+    let alarm = ems.alarm_time_local().map(|t| t.fixed_offset());
+    let remaining = create_unit_table(&units, alarm, page, curr_y, &mut offsets, home_count);
+    let printed = units.len() - remaining;
 
     if remaining > 0 {
         trace!("creating second page");
         let page_id = doc.new_page().unwrap();
         let page = doc.page_at(page_id).unwrap();
-        let curr_y = 25.0;
+        let curr_y = HEADER_TOP;
         // assumes that all remaining units fit on one page :):
-        let page_units = &ems.unit_alarm_times[ems.unit_alarm_times.len() - remaining..];
-        let remaining_home_count = if home_count > printed {
-            home_count - printed
-        } else {
-            0
-        };
+        let page_units = &units[printed..];
+        let remaining_home_count = home_count.saturating_sub(printed);
 
         // create column 1 (radio id):
         add_column(
             0,
-            page_units.iter().map(|u| match &u.unit_id {
-                Either::Left(id) => id.to_string(),
-                Either::Right(id) => id.clone(),
-            }),
+            page_units.iter().map(|u| unit_name(&u.unit_id)),
             page,
             LABEL_OFFSET,
             curr_y,
@@ -326,9 +548,226 @@ fn create_emergency_doc(ems: &Emergency, doc: &mut dyn DocumentBuilder, config:
             curr_y,
             remaining_home_count,
         );
+
+        // create column 4 (minutes since the alarm):
+        add_column(
+            0,
+            page_units.iter().map(|u| minutes_text(u, alarm)),
+            page,
+            offsets.minutes,
+            curr_y,
+            remaining_home_count,
+        );
     }
 }
 
+/// the unit alarm times in the order of the printed table
+///
+/// # description
+/// units of the configured amt are listed first (they are highlighted), both groups are sorted by their alarm time.
+pub(super) fn sorted_unit_alarm_times<'a>(
+    ems: &'a Emergency,
+    config: &Config,
+) -> Vec<&'a UnitAlarmTime> {
+    let mut units = ems.unit_alarm_times_by_time();
+    // stable sort, keeps the order by time within both groups
+    units.sort_by_key(|u| !is_home_unit(&u.unit_id, config));
+    return units;
+}
+
+/// the minutes between the alarm of the emergency and the unit (e.g. `+3`), empty if either time is unknown
+pub(super) fn minutes_text(unit: &UnitAlarmTime, alarm: Option<DateTime<FixedOffset>>) -> String {
+    return match alarm.and_then(|alarm| unit.minutes_since_alarm(alarm)) {
+        Some(minutes) => format!("{:+}", minutes),
+        None => String::new(),
+    };
+}
+
+/// Creates the compact update printout: changed fields and newly alarmed units.
+///
+/// # description
+/// the keyword and address are repeated for orientation. Units of the configured amt are listed
+/// first and highlighted.
+pub(super) fn create_update_doc(
+    ems: &Emergency,
+    diff: &EmergencyDiff,
+    doc: &mut dyn DocumentBuilder,
+    config: &Config,
+) {
+    let page_id = doc.new_page().unwrap();
+    let mut page = doc.page_at(page_id).unwrap();
+
+    let mut curr_y = add_emergency_header_section(ems, page);
+    let text = format!(
+        "Nachalarmierung vom {}",
+        Local::now().format("%d.%m.%Y %H:%M")
+    );
+    page.add_text(
+        &text,
+        SECTION_OFFSET,
+        curr_y,
+        DrawingAttributes::HIGHLIGHTED_ENTRY,
+    );
+    curr_y += points_to_mm!(text_line_height!(DrawingAttributes::HIGHLIGHTED_ENTRY)) * 2.0;
+
+    let text = format!("{}\n{}", ems.keyword, ems.code3);
+    curr_y = add_optional_property(page, "Stichwort:", Some(text), curr_y);
+    curr_y = add_optional_property(page, "Einsatzort:", Some(ems.address_text()), curr_y);
+    page.add_horizontal_divider(curr_y);
+    curr_y += points_to_mm!(text_line_height!(DrawingAttributes::FIELD_VALUE)) * 1.2;
+
+    if !diff.changed_fields.is_empty() {
+        page.add_text("Geänderte Angaben", 15.0, curr_y, DrawingAttributes::LABEL);
+        curr_y += points_to_mm!(text_line_height!(DrawingAttributes::LABEL)) * 2.0;
+        for change in &diff.changed_fields {
+            let text = format!(
+                "{}\n(bisher: {})",
+                change.new.as_deref().unwrap_or("entfernt"),
+                change.old.as_deref().unwrap_or("-")
+            );
+            curr_y =
+                add_optional_ml_property(page, format!("{}:", change.label), Some(text), curr_y);
+        }
+        page.add_horizontal_divider(curr_y);
+        curr_y += points_to_mm!(text_line_height!(DrawingAttributes::FIELD_VALUE)) * 1.2;
+    }
+
+    // (radio id, station, alarm time, home unit)
+    let mut units: Vec<(String, String, String, bool)> = diff
+        .new_unit_alarm_times
+        .iter()
+        .map(|u| {
+            let home = is_home_unit(&u.unit_id, config);
+            (
+                unit_name(&u.unit_id),
+                u.station.clone(),
+                u.alarm_time.clone(),
+                home,
+            )
+        })
+        .chain(diff.new_dispatched_units.iter().map(|u| {
+            let home = is_home_unit(u, config);
+            (unit_name(u), String::new(), String::new(), home)
+        }))
+        .collect();
+    if units.is_empty() {
+        return;
+    }
+    // stable sort, the own units are highlighted by printing them first
+    units.sort_by_key(|(_, _, _, home)| !home);
+    let home_count = units.iter().filter(|(_, _, _, home)| *home).count();
+
+    let label_height = points_to_mm!(text_line_height!(DrawingAttributes::LABEL)) * 2.0;
+    // the table needs its header and at least one unit, otherwise it continues on a new page
+    if page.max_lines_before_overflow(curr_y + label_height, DrawingAttributes::FIELD_VALUE) < 2 {
+        trace!("creating second page for the new units");
+        let page_id = doc.new_page().unwrap();
+        page = doc.page_at(page_id).unwrap();
+        curr_y = HEADER_TOP;
+    }
+    page.add_text(
+        "Nachalarmierte Einheiten",
+        15.0,
+        curr_y,
+        DrawingAttributes::LABEL,
+    );
+    curr_y += label_height;
+    let max_items = page
+        .max_lines_before_overflow(curr_y, DrawingAttributes::FIELD_VALUE)
+        .saturating_sub(1);
+    if units.len() > max_items {
+        warn!(
+            "only {} of {} new units fit on the update page",
+            max_items,
+            units.len()
+        );
+        units.truncate(max_items);
+    }
+
+    let max_len = add_start_column(
+        page,
+        "Funkrufname",
+        LABEL_OFFSET,
+        curr_y,
+        units.iter().map(|u| u.0.clone()),
+        home_count,
+    );
+    let station_offset = CHAR_WIDTH_40 * max_len as f32 + LABEL_OFFSET + 8.0;
+    let max_len = add_start_column(
+        page,
+        "Wache",
+        station_offset,
+        curr_y,
+        units.iter().map(|u| u.1.clone()),
+        home_count,
+    );
+    let time_offset = station_offset + CHAR_WIDTH_40 * max_len as f32 + 8.0;
+    add_start_column(
+        page,
+        "Alarmzeit",
+        time_offset,
+        curr_y,
+        units.iter().map(|u| u.2.clone()),
+        home_count,
+    );
+}
+
+/// warns the crew, that the alarm mail could not be read completely (below the header section)
+///
+/// returns the y coordinate below the banner, `y` if the mail is complete
+fn add_incomplete_banner(report: &ParseReport, page: &mut dyn PageBuilder, y: f32) -> f32 {
+    if !report.is_incomplete() {
+        return y;
+    }
+    let text = format!(
+        "Alarmfax unvollständig ({} Fehler) - Angaben bei der Leitstelle prüfen!",
+        report.count(Severity::Warning)
+    );
+    let line_height = points_to_mm!(text_line_height!(DrawingAttributes::HIGHLIGHTED_ENTRY));
+    page.add_text(
+        &text,
+        SECTION_OFFSET,
+        y,
+        DrawingAttributes::HIGHLIGHTED_ENTRY,
+    );
+    let mut y = y + line_height * 1.5;
+
+    let missing = report.missing_fields();
+    if !missing.is_empty() {
+        let labels = missing.iter().map(|f| f.label()).collect::<Vec<_>>();
+        let max_chars =
+            ((page.get_dimnensions().0 - 2.0 * SECTION_OFFSET) / CHAR_WIDTH_40) as usize;
+        y = page.add_multiline_text(
+            wrap_list("Fehlende Pflichtangaben:", &labels, max_chars),
+            SECTION_OFFSET,
+            y,
+            DrawingAttributes::HIGHLIGHTED_ENTRY,
+        );
+    }
+
+    return y + line_height * 0.5;
+}
+
+/// appends the comma separated items to the label, breaking the line before it gets longer than `max_chars`
+pub(super) fn wrap_list(label: &str, items: &[&str], max_chars: usize) -> String {
+    let mut lines = vec![label.to_string()];
+    for (i, item) in items.iter().enumerate() {
+        let item = if i + 1 < items.len() {
+            format!("{},", item)
+        } else {
+            item.to_string()
+        };
+        let line = lines.last_mut().unwrap();
+        if line.chars().count() + 1 + item.chars().count() > max_chars {
+            lines.push(item);
+        } else {
+            line.push(' ');
+            line.push_str(&item);
+        }
+    }
+    return lines.join("\n");
+}
+
 fn add_optional_property(
     page: &mut dyn PageBuilder,
     label: &str,
@@ -369,7 +808,8 @@ fn add_optional_ml_property(
 }
 
 fn create_unit_table(
-    ems: &Emergency,
+    units: &[&UnitAlarmTime],
+    alarm: Option<DateTime<FixedOffset>>,
     page: &mut dyn PageBuilder,
     start_y: f32,
     offsets: &mut AlarmTableOffsets,
@@ -381,10 +821,12 @@ fn create_unit_table(
     start_y += points_to_mm!(text_line_height!(DrawingAttributes::LABEL)) * 2.0;
 
     // calculate the number of items that fit on the page (excluding the header: -1):
-    let max_items = page.max_lines_before_overflow(start_y, DrawingAttributes::FIELD_VALUE) - 1;
-    let max_items = min(ems.unit_alarm_times.len(), max_items);
+    let max_items = page
+        .max_lines_before_overflow(start_y, DrawingAttributes::FIELD_VALUE)
+        .saturating_sub(1);
+    let max_items = min(units.len(), max_items);
 
-    let page_units = &ems.unit_alarm_times[0..max_items];
+    let page_units = &units[0..max_items];
 
     // create column 1 (radio id):
     let max_len = add_start_column(
@@ -392,10 +834,7 @@ fn create_unit_table(
         "Funkrufname",
         LABEL_OFFSET,
         start_y,
-        page_units.iter().map(|u| match &u.unit_id {
-            Either::Left(id) => id.to_string(),
-            Either::Right(id) => id.clone(),
-        }),
+        page_units.iter().map(|u| unit_name(&u.unit_id)),
         home_count,
     );
     offsets.station = CHAR_WIDTH_40 * max_len as f32 + LABEL_OFFSET + 8.0;
@@ -412,7 +851,7 @@ fn create_unit_table(
     offsets.time = offsets.station + CHAR_WIDTH_40 * max_len as f32 + 8.0;
 
     // create column 3 (alarm time):
-    add_start_column(
+    let max_len = add_start_column(
         page,
         "Alarmzeit",
         offsets.time,
@@ -420,8 +859,19 @@ fn create_unit_table(
         page_units.iter().map(|u| u.alarm_time.clone()),
         home_count,
     );
+    offsets.minutes = offsets.time + CHAR_WIDTH_40 * max_len as f32 + 8.0;
+
+    // create column 4 (minutes since the alarm):
+    add_start_column(
+        page,
+        "Minuten",
+        offsets.minutes,
+        start_y,
+        page_units.iter().map(|u| minutes_text(u, alarm)),
+        home_count,
+    );
 
-    return max(0, ems.unit_alarm_times.len() - max_items);
+    return units.len() - max_items;
 }
 
 fn add_start_column<I>(
@@ -1,6 +1,24 @@
 use std::{env, str::FromStr};
 
-use crate::{config::Config, models::emergency::Emergency, printing::print_ems::count_copies};
+use printpdf::image_crate::{codecs::png::PngEncoder, ColorType, ImageEncoder};
+
+use crate::{
+    config::Config,
+    models::{
+        attachment::Attachment,
+        emergency::Emergency,
+        emergency_diff::{unit_name, EmergencyDiff},
+        unit_alarm_time::UnitAlarmTime,
+    },
+    printing::{
+        document::DocumentBuilder,
+        pdf::document::PDFDocument,
+        print_ems::{
+            add_attachment_pages, count_copies, create_update_doc, minutes_text,
+            sorted_unit_alarm_times, wrap_list,
+        },
+    },
+};
 
 const EMS_FULL: &str = include_str!("../../examples/emergency_bgebg.txt");
 const EMS_NONE: &str = include_str!("../../examples/emergency_obj.txt");
@@ -49,3 +67,126 @@ fn test_count_copies_one() {
     assert_eq!(count_copies(&ems, &config_full), 2); // min_copies = 2
     assert_eq!(count_copies(&ems, &config_min), 1);
 }
+
+#[test]
+fn test_add_attachment_pages() {
+    let mut png = Vec::new();
+    PngEncoder::new(&mut png)
+        .write_image(&[0u8; 4 * 2 * 3], 4, 2, ColorType::Rgb8)
+        .unwrap();
+    let image = Attachment {
+        filename: Some("karte.png".to_string()),
+        mime_type: "image/png".to_string(),
+        data: png,
+    };
+    let broken = Attachment {
+        filename: None,
+        mime_type: "image/jpeg".to_string(),
+        data: b"no jpeg".to_vec(),
+    };
+
+    let truncated = Attachment {
+        filename: Some("abgeschnitten.png".to_string()),
+        mime_type: "image/png".to_string(),
+        data: image.data[..image.data.len() / 2].to_vec(),
+    };
+    let gif = Attachment {
+        filename: Some("karte.gif".to_string()),
+        mime_type: "image/gif".to_string(),
+        data: b"GIF89a".to_vec(),
+    };
+
+    let mut doc = PDFDocument::new();
+    assert_eq!(
+        add_attachment_pages(&[&broken, &image, &truncated, &gif], &mut doc),
+        1
+    );
+    // no empty pages for the attachments, that couldn't be read
+    assert!(doc.page_at(0).is_some());
+    assert!(doc.page_at(1).is_none());
+}
+
+#[test]
+fn test_create_update_doc() {
+    // required for config parsing
+    env::set_var("EM_IMAP_HOST", "host");
+    env::set_var("EM_IMAP_USERNAME", "user");
+    env::set_var("EM_IMAP_PASSWORD", "pass");
+
+    let config = Config::parse("examples/config_full.yaml").unwrap(); // amt = 1
+    let previous = Emergency::from_str(EMS_FULL).unwrap();
+    let mut ems = Emergency::from_str(EMS_FULL).unwrap();
+    ems.note = Some("Nachforderung Drehleiter".to_string());
+    for unit in ["FL BRB 01/33-01", "FL PM 01/33-01"] {
+        ems.unit_alarm_times.push(UnitAlarmTime::from_values(
+            unit.to_string(),
+            "FW".to_string(),
+            "12:45".to_string(),
+        ));
+    }
+    let diff = EmergencyDiff::between(&previous, &ems);
+    assert_eq!(diff.new_unit_alarm_times.len(), 2);
+
+    let mut doc = PDFDocument::new();
+    create_update_doc(&ems, &diff, &mut doc, &config);
+    assert!(doc.page_at(0).is_some());
+    assert!(doc.page_at(1).is_none()); // the update fits on one page
+
+    // the changed note fills the first page, the units are listed on the next one
+    ems.note = Some("Nachforderung\n".repeat(33));
+    let diff = EmergencyDiff::between(&previous, &ems);
+    let mut doc = PDFDocument::new();
+    create_update_doc(&ems, &diff, &mut doc, &config);
+    assert!(doc.page_at(1).is_some());
+    assert!(doc.page_at(2).is_none());
+}
+
+#[test]
+fn test_wrap_list() {
+    assert_eq!(wrap_list("Fehlend:", &[], 20), "Fehlend:");
+    assert_eq!(
+        wrap_list("Fehlend:", &["Stichwort", "Einsatzort"], 40),
+        "Fehlend: Stichwort, Einsatzort"
+    );
+    assert_eq!(
+        wrap_list("Fehlend:", &["Stichwort", "Einsatzort", "Alarmzeit"], 20),
+        "Fehlend: Stichwort,\nEinsatzort,\nAlarmzeit"
+    );
+}
+
+#[test]
+fn test_sorted_unit_alarm_times() {
+    // required for config parsing
+    env::set_var("EM_IMAP_HOST", "host");
+    env::set_var("EM_IMAP_USERNAME", "user");
+    env::set_var("EM_IMAP_PASSWORD", "pass");
+
+    let config = Config::parse("examples/config_full.yaml").unwrap(); // amt = 1
+                                                                      // the first unit of the amt is alarmed last
+    let mail = EMS_FULL.replace("FL PM 01/01-01~~00:01", "FL PM 01/01-01~~00:16");
+    let ems = Emergency::from_str(&mail).unwrap();
+    let alarm = ems.alarm_time_local().map(|t| t.fixed_offset());
+
+    let units = sorted_unit_alarm_times(&ems, &config);
+    let rows: Vec<(String, String)> = units
+        .iter()
+        .map(|u| (unit_name(&u.unit_id), minutes_text(u, alarm)))
+        .collect();
+    let row = |name: &str, minutes: &str| (name.to_string(), minutes.to_string());
+    assert_eq!(rows.len(), 16);
+    // the units of the amt are printed (and highlighted) first, both groups by time
+    assert_eq!(rows[0], row("FL PM 01/10-01", "+2"));
+    assert_eq!(
+        rows[9..16],
+        [
+            row("FL PM 01/84-01", "+11"),
+            row("FL PM 01/85-01", "+11"),
+            row("FL PM 01/79-01", "+12"),
+            row("FL PM 01/01-01", "+16"),
+            row("FL PM 03/33-01", "+13"),
+            row("FL PM 03/44-01", "+14"),
+            row("RT PM 03/83-01", "+15"),
+        ]
+    );
+    assert_eq!(minutes_text(units[0], None), "");
+}
@@ -14,7 +14,7 @@ use windows::{
 
 use crate::{
     font_size,
-    printing::document::{DrawingAttributes, PageBuilder, Point},
+    printing::document::{DocumentBuildingError, DrawingAttributes, PageBuilder, Point},
 };
 
 use super::helper::XPSHelper;
@@ -335,4 +335,18 @@ impl PageBuilder for XPSPage {
         // TODO: implement
         unimplemented!("adding images is not yet supported for xps printing");
     }
+
+    fn add_attachment_img(
+        &mut self,
+        _content: &[u8],
+        _mime_type: &str,
+        _x: f32,
+        _y: f32,
+        _max_width: f32,
+        _max_height: f32,
+    ) -> Result<(), DocumentBuildingError> {
+        return Err(DocumentBuildingError::Error(
+            "adding images is not yet supported for xps printing".to_string(),
+        ));
+    }
 }